@@ -0,0 +1,88 @@
+/****
+ * Interpreter speed benchmark: instructions per second with and without the decode cache, and of the basic
+ * block engine
+ *
+ * Each workload runs at an instruction rate far above real time, so frames are bound by the interpreter. The
+ * roms are demos which never stop or wait for keys, and display wait is off, so every budgeted instruction runs.
+ *
+ *   cargo bench --bench interpreter
+ */
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use chip8_rs::{Machine, Engine, NullLogger};
+use chip8_rs::assembler::assemble;
+
+const IPS : u32 = 6_000_000;        // 100000 instructions per frame
+const FRAMES : usize = 60;
+
+const ROMS : [&str; 3] = [
+    "Trip8 Demo (2008) [Revival Studios].ch8",
+    "Zero Demo [zeroZshadow, 2007].ch8",
+    "Particle Demo [zeroZshadow, 2008].ch8",
+];
+
+// arithmetic, skips & memory access in a loop, without drawing
+const ALU_LOOP : &str = "
+    loop:
+        LD I, data
+        ADD V0, 3
+        LD V1, V0
+        XOR V1, V2
+        SHR V1
+        ADD V2, V1
+        SNE V2, 0x40
+        LD V3, 1
+        LD [I], V2
+        LD V2, [I]
+        JP loop
+    data:
+";
+
+fn main() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut workloads = vec![("alu loop".to_string(), assemble(ALU_LOOP).unwrap().rom)];
+
+    for file in ROMS.iter() {
+        let name = Path::new(file).file_stem().unwrap().to_string_lossy().into_owned();
+        workloads.push((name, fs::read(root.join("roms").join(file)).unwrap()));
+    }
+
+    println!("{:<44} {:>14} {:>14} {:>8} {:>14} {:>8}", "workload", "uncached ips", "cached ips", "speedup",
+        "blocks ips", "speedup");
+
+    for (name, rom) in workloads.iter() {
+        let uncached = instructions_per_second(rom, Engine::Interpreter, false);
+        let cached = instructions_per_second(rom, Engine::Interpreter, true);
+        let blocks = instructions_per_second(rom, Engine::BasicBlock, true);
+
+        println!("{:<44} {:>14.0} {:>14.0} {:>7.2}x {:>14.0} {:>7.2}x", name, uncached, cached, cached / uncached,
+            blocks, blocks / uncached);
+    }
+}
+
+// instructions_per_second returns the best rate of a few runs
+fn instructions_per_second(rom: &[u8], engine: Engine, decode_cache: bool) -> f64 {
+    let mut best = Duration::MAX;
+
+    for _ in 0..5 {
+        let mut machine = Machine::new();
+        machine.set_logger(Box::new(NullLogger));
+        machine.set_seed(0);
+        machine.set_instructions_per_second(IPS);
+        machine.set_engine(engine);
+        machine.set_decode_cache(decode_cache);
+        machine.load_rom(rom);
+
+        let start = Instant::now();
+
+        for _ in 0..FRAMES {
+            machine.update();
+        }
+
+        best = best.min(start.elapsed());
+    }
+
+    (IPS as usize * FRAMES / 60) as f64 / best.as_secs_f64()
+}
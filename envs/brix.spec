@@ -0,0 +1,11 @@
+# Brix (1990) by Andreas Gustafsson
+#
+# Breakout clone: one point per brick, 5 lives. The game idles in a jump to itself at 0x2DE after the last
+# life is lost or all 96 bricks are broken.
+
+name      brix
+rom       Brix [Andreas Gustafsson, 1990].ch8
+actions   - 4 6               # none, left, right
+score     bcd 0x314 3         # bricks broken, V5 as drawn
+lives     raw VE
+game_over 0x2DE
@@ -0,0 +1,13 @@
+# Pong (1990) by Paul Vervalin
+#
+# The agent plays the left paddle, the right one stays still. Scores are kept in VE as 10 * left + right, so
+# from the 10th point of the right player on its points carry into the left digit: points are counted from
+# the growth of VE instead, 10 for the left player and 1 for the right one. Pong never ends, episodes are cut
+# after 5 minutes.
+
+name      pong
+rom       Pong [Paul Vervalin, 1990].ch8
+actions   - 1 4               # none, up, down
+score     steps VE 10         # left player points
+score     steps VE 1 -1       # right player points
+frames    18000
@@ -0,0 +1,10 @@
+# Tetris (1991) by Fran Dachille
+#
+# One point per completed line. The game has no game over screen: a new piece colliding at its spawn row
+# (0x232, reached with V1 at 3) is the end, the game would keep spawning pieces over the stack.
+
+name      tetris
+rom       Tetris [Fran Dachille, 1991].ch8
+actions   - 4 5 6 7           # none, rotate, left, right, drop
+score     bcd 0x804 3         # lines, VA as drawn
+game_over 0x232 V1 == 3
@@ -25,6 +25,30 @@ turnoff.onclick = () => {
   game.halt();
 }
 
+/// quick save: F6 saves, F7 loads. hold shift to use the second slot. hold backspace to rewind
+window.addEventListener("keydown", (e) => {
+  if (e.key === "Backspace") {
+    e.preventDefault();
+    game.setRewinding(true);
+    return;
+  }
+
+  if (e.key !== "F6" && e.key !== "F7") return;
+
+  e.preventDefault();
+  const slot = e.shiftKey ? 1 : 0;
+
+  if (e.key === "F6") {
+    game.saveState(slot);
+  } else {
+    game.loadState(slot);
+  }
+});
+
+window.addEventListener("keyup", (e) => {
+  if (e.key === "Backspace") game.setRewinding(false);
+});
+
 game.addListener((state) => {
   switch(state) {
     case GameState.PLAYING: {
@@ -0,0 +1,767 @@
+/****
+ * Ahead-of-time recompiler: turns a rom into a Rust module running the game on the Display, Keypad & Audio traits
+ *
+ * Control flow is recovered from PROGRAM_START by following jumps, calls & returns and both ways of skips.
+ * Indirect jumps (bnnn) are flagged, their targets are only known at run time. Recovered code is split into
+ * basic blocks like the basic block engine's, each emitted as a function of straight-line Rust: register, timer,
+ * stack & memory instructions are written out as their effect on Core, with quirks resolved at recompile time.
+ * Only display, keypad & audio instructions call into the CPU, through Core::device.
+ *
+ * The emitted module runs on Core, which holds the machine state. A block runs when its code is unchanged and the
+ * frame can afford all of it, costs being summed when it is left. Any pc without a recovered block, e.g. a bnnn
+ * target, and any block whose code was rewritten at run time, runs on the interpreter until execution reaches
+ * a recovered block again. So does an instruction which would fault, e.g. out of bounds, so the interpreter
+ * applies the error policy.
+ */
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use crate::cpu::{CPU, ExecutionStatus, TimingMode, ends_block, vip_cost, SKIP_CYCLES, BCD_DIGIT_CYCLES};
+use crate::cpu::instruction::{Instruction, decode_at};
+use crate::clock::{Clock, FRAME_TIME};
+use crate::memory::{PROGRAM_START, BIG_DIGIT_SPRITE_START, allocate_memory};
+use crate::disasm::{Syntax, format_instruction};
+use crate::iodevice::{Display, Keypad, Audio};
+use crate::backend::{Logger, XorShiftRng};
+use crate::error::{Chip8Error, ErrorPolicy};
+use crate::quirks::{Platform, Quirks, LoadStore};
+
+// Recovery is the code found in a rom
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recovery {
+    pub blocks: Vec<CodeBlock>,         // by start address
+    pub indirect_jumps: Vec<usize>,     // bnnn addresses
+    pub external: Vec<usize>,           // targets outside of the rom, run by the interpreter
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeBlock {
+    pub start: usize,
+    pub instructions: Vec<(usize, Instruction)>,
+}
+
+impl CodeBlock {
+    // end returns the address after the last instruction
+    pub fn end(&self) -> usize {
+        self.instructions.last().map_or(self.start, |&(addr, instruction)| addr + instruction.size())
+    }
+}
+
+// recover finds the code reachable from PROGRAM_START, as loaded at PROGRAM_START
+pub fn recover(rom: &[u8]) -> Recovery {
+    use Instruction::*;
+
+    let mut memory = allocate_memory(PROGRAM_START + rom.len());
+    memory[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+
+    let rom_end = PROGRAM_START + rom.len();
+    let in_rom = |addr: usize, size: usize| addr >= PROGRAM_START && addr + size <= rom_end;
+
+    let mut reachable = BTreeMap::new();
+    let mut leaders = BTreeSet::new();
+    let mut indirect_jumps = Vec::new();
+    let mut external = BTreeSet::new();
+    let mut pending = vec![PROGRAM_START];
+
+    leaders.insert(PROGRAM_START);
+
+    while let Some(addr) = pending.pop() {
+        if reachable.contains_key(&addr) {
+            continue;
+        }
+
+        let instruction = if in_rom(addr, 2) { decode_at(&memory, addr) } else { Unknown(0) };
+
+        if !in_rom(addr, instruction.size()) {
+            external.insert(addr);
+            continue;
+        }
+
+        reachable.insert(addr, instruction);
+        let next = addr + instruction.size();
+
+        let successors = match instruction {
+            Jump(nnn) => vec![nnn as usize],
+            Call(nnn) => vec![nnn as usize, next],
+            Ret | Exit | Unknown(_) => vec![],
+            JumpV(_) => {
+                indirect_jumps.push(addr);
+                vec![]
+            },
+            SkipEq(_, _) | SkipNeq(_, _) | SkipEqV(_, _) | SkipNeqV(_, _) | SkipKey(_) | SkipNotKey(_) => {
+                let skipped = if in_rom(next, 2) { decode_at(&memory, next).size() } else { 2 };
+                vec![next, next + skipped]
+            },
+            _ => vec![next],
+        };
+
+        // instructions after the end of a block start a new one
+        if ends_block(instruction) {
+            leaders.extend(successors.iter().copied());
+        }
+
+        pending.extend(successors);
+    }
+
+    let blocks = leaders.iter()
+        .filter(|addr| reachable.contains_key(addr))
+        .map(|&start| {
+            let mut instructions = Vec::new();
+            let mut addr = start;
+
+            while let Some(&instruction) = reachable.get(&addr) {
+                instructions.push((addr, instruction));
+                addr += instruction.size();
+
+                if ends_block(instruction) || leaders.contains(&addr) {
+                    break;
+                }
+            }
+
+            CodeBlock { start, instructions }
+        })
+        .collect();
+
+    indirect_jumps.sort_unstable();
+
+    Recovery { blocks, indirect_jumps, external: external.into_iter().collect() }
+}
+
+// emit returns a Rust module running the recovered code of rom. `source` names the rom in the module header
+pub fn emit(rom: &[u8], recovery: &Recovery, platform: Option<Platform>, source: &str) -> String {
+    let mut out = String::new();
+    let hex_list = |addrs: &[usize]| addrs.iter().map(|addr| format!("0x{:03X}", addr)).collect::<Vec<_>>().join(", ");
+
+    writeln!(out, "// Generated from {} by the chip8-rs recompiler, do not edit.", source).unwrap();
+    writeln!(out, "//").unwrap();
+    writeln!(out, "// {} blocks recovered. Run by the interpreter:", recovery.blocks.len()).unwrap();
+    writeln!(out, "//   indirect jumps: {}", or_none(hex_list(&recovery.indirect_jumps))).unwrap();
+    writeln!(out, "//   targets outside of the rom: {}", or_none(hex_list(&recovery.external))).unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "use chip8_rs::{{Display, Keypad, Audio, ExecutionStatus, Quirks{}}};",
+        if platform.is_some() { ", Platform" } else { "" }).unwrap();
+
+    // instructions are only named by device calls
+    if recovery.blocks.iter().flat_map(|block| block.instructions.iter()).any(|&(_, instruction)| is_device(instruction)) {
+        writeln!(out, "use chip8_rs::Instruction::*;").unwrap();
+    }
+
+    writeln!(out, "use chip8_rs::aot::Core;").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "pub const ROM : [u8; {}] = [", rom.len()).unwrap();
+    for chunk in rom.chunks(16) {
+        let bytes: Vec<String> = chunk.iter().map(|byte| format!("0x{:02X},", byte)).collect();
+        writeln!(out, "    {}", bytes.join(" ")).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "pub fn quirks() -> Quirks {{").unwrap();
+    match platform {
+        Some(platform) => writeln!(out, "    Quirks::for_platform(Platform::{:?})", platform).unwrap(),
+        None => writeln!(out, "    Quirks::new()").unwrap(),
+    }
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "// new_core returns a core with the rom loaded").unwrap();
+    writeln!(out, "pub fn new_core() -> Core {{").unwrap();
+    writeln!(out, "    Core::new(&ROM, quirks())").unwrap();
+    writeln!(out, "}}").unwrap();
+    writeln!(out).unwrap();
+
+    writeln!(out, "// frame runs one 60 Hz frame: the instructions due, then a timer tick").unwrap();
+    writeln!(out, "pub fn frame(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, \
+        audio: &mut impl Audio) -> ExecutionStatus {{").unwrap();
+    writeln!(out, "    core.begin_frame();").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    while core.running() {{").unwrap();
+    writeln!(out, "        match core.pc() {{").unwrap();
+    for block in recovery.blocks.iter() {
+        writeln!(out, "            0x{:03X} => {}(core, display, keypad, audio),", block.start, block_name(block)).unwrap();
+    }
+    writeln!(out, "            _ => core.interpret(display, keypad, audio),").unwrap();
+    writeln!(out, "        }}").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    core.end_frame(audio)").unwrap();
+    writeln!(out, "}}").unwrap();
+
+    let quirks = platform.map_or(Quirks::new(), Quirks::for_platform);
+
+    for block in recovery.blocks.iter() {
+        writeln!(out).unwrap();
+        emit_block(&mut out, block, quirks);
+    }
+
+    out
+}
+
+// emit_block writes the function of block: a check that it can run to its end, then the effect of each instruction
+// on core. instructions using the display, keypad or audio run through Core::device
+fn emit_block(out: &mut String, block: &CodeBlock, quirks: Quirks) {
+    use Instruction::*;
+
+    let (start, end) = (block.start - PROGRAM_START, block.end() - PROGRAM_START);
+    let count = block.instructions.len();
+
+    // cost of the instructions before the last one, with the slowest draws
+    let before_last = &block.instructions[..count - 1];
+    let vip_bound: u32 = before_last.iter().map(|&(_, instruction)| vip_cost(instruction, 7)).sum();
+
+    writeln!(out, "fn {}(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, \
+        audio: &mut impl Audio) {{", block_name(block)).unwrap();
+    writeln!(out, "    if !core.enter(0x{:03X}, &ROM[0x{:03X}..0x{:03X}], {}, {}) {{",
+        block.start, start, end, count - 1, vip_bound).unwrap();
+    writeln!(out, "        return core.interpret(display, keypad, audio);").unwrap();
+    writeln!(out, "    }}").unwrap();
+    writeln!(out).unwrap();
+
+    // units & vip cycles of the inline instructions run so far, spent when leaving the block
+    let (mut units, mut cycles) = (0u32, 0u32);
+    let mut next = block.start;
+
+    for &(addr, instruction) in block.instructions.iter() {
+        let mut lines: Vec<String> = Vec::new();
+        let last = addr + instruction.size() == block.end();
+        next = addr + instruction.size();
+
+        let cost = vip_cost(instruction, 0);
+        let (done, done_cycles) = (units + 1, cycles + cost);
+
+        let reg = |x: u8| format!("core.register(0x{:X})", x);
+        let set = |x: u8, value: String| format!("core.set_register(0x{:X}, {});", x, value);
+        let fault = format!("core.interpret_at(0x{:03X}, {}, {}, display, keypad, audio)", addr, units, cycles);
+        let leave = |pc: String| format!("core.leave({}, {}, {});", pc, done, done_cycles);
+        let skip = |condition: String| vec![
+            format!("if {} {{", condition),
+            format!("    core.skip(0x{:03X}, {}, {});", next, done, done_cycles + SKIP_CYCLES),
+            "} else {".to_string(),
+            format!("    core.leave(0x{:03X}, {}, {});", next, done, done_cycles),
+            "}".to_string(),
+        ];
+
+        let mut ends = ends_block(instruction);
+
+        match instruction {
+            Sys(_) => lines.push(leave(format!("0x{:03X}", next))),
+            Exit => {
+                lines.push(format!("core.halt(0x{:03X});", next));
+                lines.push(format!("core.spend({}, {});", units, cycles));
+            },
+            Jump(nnn) => {
+                // a jump to itself halts
+                if nnn as usize == addr {
+                    lines.push(format!("core.halt(0x{:03X});", next));
+                    lines.push(format!("core.spend({}, {});", units, cycles));
+                } else {
+                    lines.push(leave(format!("0x{:03X}", nnn)));
+                }
+            },
+            Call(nnn) => {
+                lines.push(format!("if core.push(0x{:03X}) {{", next));
+                lines.push(format!("    {}", leave(format!("0x{:03X}", nnn))));
+                lines.push("} else {".to_string());
+                lines.push(format!("    {};", fault));
+                lines.push("}".to_string());
+            },
+            Ret => {
+                lines.push("match core.pop() {".to_string());
+                lines.push(format!("    Some(addr) => core.leave(addr, {}, {}),", done, done_cycles));
+                lines.push(format!("    None => {},", fault));
+                lines.push("}".to_string());
+            },
+            SkipEq(x, kk) => lines.extend(skip(format!("{} == 0x{:02X}", reg(x), kk))),
+            SkipNeq(x, kk) => lines.extend(skip(format!("{} != 0x{:02X}", reg(x), kk))),
+            SkipEqV(x, y) => lines.extend(skip(format!("{} == {}", reg(x), reg(y)))),
+            SkipNeqV(x, y) => lines.extend(skip(format!("{} != {}", reg(x), reg(y)))),
+            SaveRange(x, y) | LoadRange(x, y) => {
+                let len = x.abs_diff(y) + 1;
+
+                lines.push("let addr = core.index();".to_string());
+                lines.push(format!("if !core.fits(addr, {}) {{ return {}; }}", len, fault));
+
+                for i in 0..len {
+                    let r = if x <= y { x + i } else { x - i };
+
+                    lines.push(match instruction {
+                        SaveRange(_, _) => format!("core.write({}, {});", offset("addr", i as usize), reg(r)),
+                        _ => set(r, format!("core.memory()[{}]", offset("addr", i as usize))),
+                    });
+                }
+
+                if let SaveRange(_, _) = instruction {
+                    lines.push(leave(format!("0x{:03X}", next)));
+                }
+            },
+            Load(x, kk) => lines.push(set(x, format!("0x{:02X}", kk))),
+            Incr(x, kk) => lines.push(set(x, format!("{}.wrapping_add(0x{:02X})", reg(x), kk))),
+            Set(x, y) => lines.push(set(x, reg(y))),
+            Or(x, y) | And(x, y) | Xor(x, y) => {
+                let op = match instruction { Or(_, _) => "|", And(_, _) => "&", _ => "^" };
+                lines.push(set(x, format!("{} {} {}", reg(x), op, reg(y))));
+
+                if quirks.vf_reset {
+                    lines.push(set(0xF, "0".to_string()));
+                }
+            },
+            Add(x, y) => {
+                lines.push(format!("let (value, carry) = {}.overflowing_add({});", reg(x), reg(y)));
+                lines.push(set(x, "value".to_string()));
+                lines.push(set(0xF, "carry as u8".to_string()));
+            },
+            Sub(x, y) | SubN(x, y) => {
+                let (a, b) = if let Sub(_, _) = instruction { (x, y) } else { (y, x) };
+                lines.push(format!("let (value, borrow) = {}.overflowing_sub({});", reg(a), reg(b)));
+                lines.push(set(x, "value".to_string()));
+                lines.push(set(0xF, "!borrow as u8".to_string()));
+            },
+            Shr(x, y) | Shl(x, y) => {
+                let source = if quirks.shift_vx { x } else { y };
+                let (flag, shift) = if let Shr(_, _) = instruction { ("value & 0x01", ">>") } else { ("value >> 7", "<<") };
+
+                lines.push(format!("let value = {};", reg(source)));
+                lines.push(set(0xF, flag.to_string()));
+                lines.push(set(x, format!("value {} 1", shift)));
+            },
+            LoadI(nnn) => lines.push(format!("core.set_index(0x{:03X});", nnn)),
+            LoadLong(nnnn) => lines.push(format!("core.set_index(0x{:04X});", nnnn)),
+            JumpV(nnn) => {
+                let (x, base) = if quirks.jump_vx { ((nnn >> 8) as u8, nnn) } else { (0, nnn) };
+
+                lines.push(format!("let target = {} as usize + 0x{:03X};", reg(x), base));
+                lines.push("if core.fits(target, 2) {".to_string());
+                lines.push(format!("    {}", leave("target".to_string())));
+                lines.push("} else {".to_string());
+                lines.push(format!("    {};", fault));
+                lines.push("}".to_string());
+            },
+            Rand(x, kk) => {
+                lines.push(format!("let value = core.rand() & 0x{:02X};", kk));
+                lines.push(set(x, "value".to_string()));
+            },
+            ReadDt(x) => lines.push(set(x, "core.delay_timer()".to_string())),
+            LoadDt(x) => lines.push(format!("core.set_delay_timer({});", reg(x))),
+            AddI(x) => {
+                lines.push(format!("let addr = core.index() + {} as usize;", reg(x)));
+                lines.push(format!("if !core.fits(addr, 1) {{ return {}; }}", fault));
+                lines.push("core.set_index(addr);".to_string());
+            },
+            Digit(x) => lines.push(format!("core.set_index(5 * {} as usize);", reg(x))),
+            BigDigit(x) => {
+                lines.push(format!("let digit = {} as usize;", reg(x)));
+                lines.push(format!("if digit > 9 {{ return {}; }}", fault));
+                lines.push(format!("core.set_index(0x{:03X} + 10 * digit);", BIG_DIGIT_SPRITE_START));
+            },
+            Bcd(x) => {
+                lines.push(format!("let (addr, value) = (core.index(), {});", reg(x)));
+                lines.push(format!("if !core.fits(addr, 3) {{ return {}; }}", fault));
+                lines.push("core.write(addr, value / 100);".to_string());
+                lines.push("core.write(addr + 1, value / 10 % 10);".to_string());
+                lines.push("core.write(addr + 2, value % 10);".to_string());
+                lines.push(format!("core.leave(0x{:03X}, {}, {} + {} * (value / 100 + value / 10 % 10 + value % 10) as u32);",
+                    next, done, done_cycles, BCD_DIGIT_CYCLES));
+            },
+            Store(x) | LoadReg(x) => {
+                lines.push("let addr = core.index();".to_string());
+                lines.push(format!("if !core.fits(addr, {}) {{ return {}; }}", x + 1, fault));
+
+                for i in 0..=x {
+                    lines.push(match instruction {
+                        Store(_) => format!("core.write({}, {});", offset("addr", i as usize), reg(i)),
+                        _ => set(i, format!("core.memory()[{}]", offset("addr", i as usize))),
+                    });
+                }
+
+                match quirks.loadstore {
+                    LoadStore::IncrementXPlusOne => lines.push(format!("core.set_index({});", offset("addr", x as usize + 1))),
+                    LoadStore::IncrementX => lines.push(format!("core.set_index({});", offset("addr", x as usize))),
+                    LoadStore::Unchanged => (),
+                }
+
+                if let Store(_) = instruction {
+                    lines.push(leave(format!("0x{:03X}", next)));
+                }
+            },
+            SaveRpl(x) | LoadRpl(x) if (x as usize) < quirks.rpl_flags() => {
+                for i in 0..=x {
+                    lines.push(match instruction {
+                        SaveRpl(_) => format!("core.set_rpl(0x{:X}, {});", i, reg(i)),
+                        _ => set(i, format!("core.rpl(0x{:X})", i)),
+                    });
+                }
+            },
+
+            // always faults: the interpreter applies the error policy and runs the rest
+            SaveRpl(_) | LoadRpl(_) | Unknown(_) => {
+                lines.push(format!("{};", fault));
+                ends = true;
+            },
+
+            // the device instruction counts itself & sets pc, the block is left if it stopped
+            _ => {
+                let call = format!("core.device(0x{:03X}, {:?}, display, keypad, audio)", addr, instruction);
+                let spend = format!("core.spend({}, {})", units, cycles);
+
+                if ends || last {
+                    lines.push(format!("{};", call));
+                    ends = true;
+
+                    if units > 0 {
+                        lines.push(format!("{};", spend));
+                    }
+                } else if units > 0 {
+                    lines.push(format!("if !{} {{ return {}; }}", call, spend));
+                } else {
+                    lines.push(format!("if !{} {{ return; }}", call));
+                }
+            },
+        }
+
+        writeln!(out, "    // 0x{:03X}: {}", addr, format_instruction(instruction, Syntax::Classic)).unwrap();
+
+        for line in lines.iter() {
+            writeln!(out, "    {}", line).unwrap();
+        }
+
+        if ends {
+            writeln!(out, "}}").unwrap();
+            return;
+        }
+
+        if !is_device(instruction) {
+            units = done;
+            cycles = done_cycles;
+        }
+    }
+
+    writeln!(out, "    core.leave(0x{:03X}, {}, {});", next, units, cycles).unwrap();
+    writeln!(out, "}}").unwrap();
+}
+
+fn is_device(instruction: Instruction) -> bool {
+    use Instruction::*;
+
+    matches!(instruction,
+        ScrollDown(_) | ScrollUp(_) | Cls | ScrollRight | ScrollLeft | LowRes | HighRes | Draw(_, _, _) |
+        SkipKey(_) | SkipNotKey(_) | Plane(_) | Audio | WaitKey(_) | LoadSt(_) | Pitch(_))
+}
+
+// offset returns `base + n`, or base for 0
+fn offset(base: &str, n: usize) -> String {
+    if n == 0 { base.to_string() } else { format!("{} + {}", base, n) }
+}
+
+fn block_name(block: &CodeBlock) -> String {
+    format!("block_{:03x}", block.start)
+}
+
+fn or_none(text: String) -> String {
+    if text.is_empty() { "none".to_string() } else { text }
+}
+
+// Core is the machine state of a recompiled program, stepped by its generated code.
+// a frame runs as begin_frame, then blocks or interpret while running, then end_frame. a block runs if enter
+// accepts it, changes the state through the accessors below, and ends by leave, skip or halt
+pub struct Core {
+    cpu: CPU,
+    clock: Clock,
+    budget: u32,                // units due in this frame
+    spent: u32,
+    status: ExecutionStatus,    // of the last instruction
+}
+
+impl Core {
+    pub fn new(rom: &[u8], quirks: Quirks) -> Core {
+        let mut cpu = CPU::new();
+        cpu.set_memory_size(PROGRAM_START + rom.len());
+        cpu.set_quirks(quirks);
+        cpu.load_rom(rom);
+
+        Core { cpu, clock: Clock::new(), budget: 0, spent: 0, status: ExecutionStatus::OK }
+    }
+
+    pub fn set_instructions_per_second(&mut self, ips: u32) {
+        self.clock.set_ips(ips);
+    }
+
+    pub fn set_timing_mode(&mut self, timing: TimingMode) {
+        self.cpu.set_timing(timing);
+        self.clock.set_timing(timing);
+    }
+
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.cpu.set_error_policy(policy);
+    }
+
+    // set_seed makes cxkk random numbers reproducible, like Machine::set_seed
+    pub fn set_seed(&mut self, seed: u32) {
+        self.cpu.set_rng(Box::new(XorShiftRng::new(seed)));
+    }
+
+    pub fn set_logger(&mut self, logger: Box<dyn Logger>) {
+        self.cpu.set_logger(logger);
+    }
+
+    pub fn pc(&self) -> usize {
+        self.cpu.pc()
+    }
+
+    #[inline]
+    pub fn register(&self, x: usize) -> u8 {
+        self.cpu.register(x)
+    }
+
+    #[inline]
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.cpu.set_register(x, value);
+    }
+
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.cpu.ir()
+    }
+
+    #[inline]
+    pub fn set_index(&mut self, addr: usize) {
+        self.cpu.set_ir(addr);
+    }
+
+    #[inline]
+    pub fn delay_timer(&self) -> u8 {
+        self.cpu.dt()
+    }
+
+    #[inline]
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.cpu.set_dt(value);
+    }
+
+    #[inline]
+    pub fn rpl(&self, x: usize) -> u8 {
+        self.cpu.rpl(x)
+    }
+
+    #[inline]
+    pub fn set_rpl(&mut self, x: usize, value: u8) {
+        self.cpu.set_rpl(x, value);
+    }
+
+    // push pushes a return address, false if the stack is full
+    #[inline]
+    pub fn push(&mut self, addr: usize) -> bool {
+        self.cpu.push(addr)
+    }
+
+    // pop pops a return address, None if the stack is empty
+    #[inline]
+    pub fn pop(&mut self) -> Option<usize> {
+        self.cpu.pop()
+    }
+
+    #[inline]
+    pub fn rand(&mut self) -> u8 {
+        self.cpu.next_random()
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        self.cpu.memory()
+    }
+
+    // fits returns true if M[addr..addr+len] is inside memory
+    #[inline]
+    pub fn fits(&self, addr: usize, len: usize) -> bool {
+        addr + len <= self.cpu.memory().len()
+    }
+
+    // write writes a byte inside memory
+    #[inline]
+    pub fn write(&mut self, addr: usize, value: u8) {
+        self.cpu.write_memory(addr, value);
+    }
+
+    pub fn error(&self) -> Option<&Chip8Error> {
+        self.cpu.error()
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.budget = self.clock.pending(FRAME_TIME);
+        self.spent = 0;
+        self.status = ExecutionStatus::OK;
+    }
+
+    // running returns true while the frame has budget left and no instruction stopped it
+    pub fn running(&self) -> bool {
+        self.status == ExecutionStatus::OK && self.spent < self.budget
+    }
+
+    // enter returns true if the block at addr can run to its end: memory still holds its code, and the budget left
+    // covers the instructions before its last one, costing at most `units`, or `vip_cycles` with VIP timing
+    #[inline]
+    pub fn enter(&self, addr: usize, code: &[u8], units: u32, vip_cycles: u32) -> bool {
+        self.running() && self.spent + self.cost(units, vip_cycles) < self.budget &&
+            self.cpu.memory()[addr..].starts_with(code)
+    }
+
+    // spend counts instructions run by a block: `units` instructions, or `vip_cycles` with VIP timing
+    #[inline]
+    pub fn spend(&mut self, units: u32, vip_cycles: u32) {
+        self.spent += self.cost(units, vip_cycles);
+    }
+
+    // leave ends a block going to pc
+    #[inline]
+    pub fn leave(&mut self, pc: usize, units: u32, vip_cycles: u32) {
+        self.cpu.set_pc(pc);
+        self.spend(units, vip_cycles);
+    }
+
+    // skip ends a block skipping the instruction at next, which may be 4 bytes long
+    #[inline]
+    pub fn skip(&mut self, next: usize, units: u32, vip_cycles: u32) {
+        self.cpu.set_pc(next);
+        self.cpu.skip_next();
+        self.spend(units, vip_cycles);
+    }
+
+    // halt stops the frame, pc left at the next instruction like the interpreter
+    #[inline]
+    pub fn halt(&mut self, pc: usize) {
+        self.cpu.set_pc(pc);
+        self.status = ExecutionStatus::Halt;
+    }
+
+    // device executes a display, keypad or audio instruction, recovered at pc, and counts it. returns false if it
+    // stopped the frame, e.g. waiting for a key
+    #[inline]
+    pub fn device(&mut self, pc: usize, instruction: Instruction, display: &mut impl Display, keypad: &mut impl Keypad,
+        audio: &mut impl Audio) -> bool
+    {
+        self.status = self.cpu.exec_at(pc, instruction, display, keypad, audio);
+
+        if self.status == ExecutionStatus::OK {
+            self.spent += self.cpu.cycles();
+        }
+
+        self.status == ExecutionStatus::OK
+    }
+
+    // interpret runs the instruction at pc by the interpreter
+    pub fn interpret(&mut self, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+        if !self.running() {
+            return;
+        }
+
+        let (status, spent) = self.cpu.step(display, keypad, audio);
+        self.status = status;
+        self.spent += spent;
+    }
+
+    // interpret_at ends a block before the instruction at pc, which faults, and runs it by the interpreter
+    pub fn interpret_at(&mut self, pc: usize, units: u32, vip_cycles: u32, display: &mut impl Display,
+        keypad: &mut impl Keypad, audio: &mut impl Audio)
+    {
+        self.leave(pc, units, vip_cycles);
+        self.interpret(display, keypad, audio);
+    }
+
+    // end_frame ticks timers, returns the status of the frame like Machine::update
+    pub fn end_frame(&mut self, audio: &mut impl Audio) -> ExecutionStatus {
+        self.clock.spend(self.spent);
+        self.cpu.update_timer(audio);
+        self.clock.end_frame();
+
+        match self.status {
+            ExecutionStatus::WaitForKey | ExecutionStatus::WaitForVBlank => ExecutionStatus::OK,
+            status => status,
+        }
+    }
+
+    fn cost(&self, units: u32, vip_cycles: u32) -> u32 {
+        match self.cpu.timing() {
+            TimingMode::Fixed => units,
+            TimingMode::CosmacVip => vip_cycles,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+    use crate::assembler::assemble;
+
+    const PROGRAM : &str = "
+            CALL draw
+            LD V1, 0x02
+        loop:
+            SE V0, 0x05
+            ADD V0, 1
+            LD B, V0
+            JP V0, table
+        table:
+            JP loop
+        draw:
+            DRW V0, V1, 5
+            RET
+    ";
+
+    #[wasm_bindgen_test]
+    fn test_recover() {
+        let rom = assemble(PROGRAM).unwrap().rom;
+        let recovery = recover(&rom);
+
+        let starts: Vec<usize> = recovery.blocks.iter().map(|block| block.start).collect();
+
+        // start, return of call, both skip targets, after bcd write, call target
+        assert_eq!(starts, vec![0x200, 0x202, 0x206, 0x208, 0x20A, 0x20E]);
+        assert_eq!(recovery.blocks[1].instructions, vec![(0x202, Instruction::Load(1, 2)), (0x204, Instruction::SkipEq(0, 5))]);
+        assert_eq!(recovery.blocks[3].end(), 0x20A);
+
+        // bnnn target is not followed: the jump table is never reached
+        assert_eq!(recovery.indirect_jumps, vec![0x20A]);
+        assert!(recovery.blocks.iter().all(|block| block.start != 0x20C));
+        assert!(recovery.external.is_empty());
+
+        // falling off the rom end
+        let recovery = recover(&assemble("LD V0, 1").unwrap().rom);
+        assert_eq!(recovery.external, vec![0x202]);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_emit() {
+        let rom = assemble(PROGRAM).unwrap().rom;
+        let module = emit(&rom, &recover(&rom), Some(Platform::CosmacVip), "test.ch8");
+
+        assert!(module.contains("Quirks::for_platform(Platform::CosmacVip)"));
+        assert!(module.contains("            0x20E => block_20e(core, display, keypad, audio),"));
+        assert!(module.contains("    if !core.enter(0x20E, &ROM[0x00E..0x012], 1, 596) {"));
+        assert!(module.contains("//   indirect jumps: 0x20A"));
+
+        // registers & memory are changed inline, devices through the cpu
+        assert!(module.contains("    core.set_register(0x0, core.register(0x0).wrapping_add(0x01));\n"));
+        assert!(module.contains("    core.write(addr + 2, value % 10);\n"));
+        assert!(module.contains("    if !core.device(0x20E, Draw(0, 1, 5), display, keypad, audio) { return; }\n"));
+        assert!(!module.contains("core.exec("));
+
+        // skips, returns & indirect jumps end blocks
+        assert!(module.contains("    if core.register(0x0) == 0x05 {\n        core.skip(0x206, 2, 100);\n"));
+        assert!(module.contains("        Some(addr) => core.leave(addr, 1, 50),\n"));
+        assert!(module.contains("    let target = core.register(0x0) as usize + 0x20C;\n"));
+
+        // quirks are resolved when emitting: the VIP increments I after fx55 & fx65, and resets VF
+        let rom = assemble("LD [I], V2\nOR V1, V2\nSHR V3, V4\nEXIT").unwrap().rom;
+        let vip = emit(&rom, &recover(&rom), Some(Platform::CosmacVip), "test.ch8");
+        let schip = emit(&rom, &recover(&rom), Some(Platform::SChip11), "test.ch8");
+
+        assert!(vip.contains("    core.set_index(addr + 3);\n"));
+        assert!(!schip.contains("core.set_index(addr"));
+        assert!(schip.contains("    core.write(addr + 2, core.register(0x2));\n    core.leave(0x202, 1, 96);\n"));
+        assert!(vip.contains("    core.set_register(0x1, core.register(0x1) | core.register(0x2));\n    core.set_register(0xF, 0);\n"));
+        assert!(vip.contains("    let value = core.register(0x4);\n"));
+        assert!(schip.contains("    let value = core.register(0x3);\n"));
+        assert!(!vip.contains("use chip8_rs::Instruction::*;"));
+    }
+}
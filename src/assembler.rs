@@ -0,0 +1,627 @@
+/****
+ * CHIP-8 assembler, producing loadable rom images
+ *
+ * One statement per line. Mnemonics, registers and directives are case insensitive, symbols are not.
+ *
+ *   ; comment               comments run to the end of line
+ *   name:                   label at current address, may precede a statement on the same line
+ *   :const NAME value       constant, value must be known at definition
+ *   :org addr               move current address forward, filling the gap with zeros
+ *   :byte v1 v2 ...         raw bytes
+ *   :sprite ..XX..XX ...    sprite rows of 8 or 16 pixels (`X`, `#` or `1` is on, `.` or `0` is off)
+ *   :include "name"         bytes registered with `Assembler::include`, e.g. sprite data
+ *   MNEMONIC operands       instruction in classic syntax, as printed by the disassembler
+ *
+ * Instructions, with x & y registers (V0..VF), kk byte, n nibble and nnn address:
+ *
+ *   CLS, RET, SCR, SCL, EXIT, LOW, HIGH, AUDIO, SCD n, SCU n, SYS nnn, JP nnn, JP V0, nnn, CALL nnn,
+ *   SE Vx, kk, SE Vx, Vy, SNE Vx, kk, SNE Vx, Vy, SAVE Vx - Vy, LOAD Vx - Vy,
+ *   LD Vx, kk, LD Vx, Vy, LD I, nnn, LD I, LONG nnnn, LD Vx, DT, LD Vx, K, LD DT, Vx, LD ST, Vx,
+ *   LD F, Vx, LD HF, Vx, LD B, Vx, LD [I], Vx, LD Vx, [I], LD R, Vx, LD Vx, R,
+ *   ADD Vx, kk, ADD Vx, Vy, ADD I, Vx, OR, AND, XOR, SUB, SUBN Vx, Vy, SHR Vx[, Vy], SHL Vx[, Vy],
+ *   RND Vx, kk, DRW Vx, Vy, n, SKP Vx, SKNP Vx, PLANE n, PITCH Vx, DW nnnn (raw 2 bytes word)
+ *
+ * Values are decimal (12), hex (0x1F), binary (0b0101), label or constant names.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use crate::cpu::instruction::Instruction;
+use crate::memory::{PROGRAM_START, MAX_MEM_SIZE};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,        // 1-based
+    pub column: usize,      // 1-based
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    pub rom: Vec<u8>,                       // rom image, first byte is located at origin
+    pub symbols: BTreeMap<String, u16>,     // labels & constants
+}
+
+pub struct Assembler {
+    origin: usize,
+    includes: HashMap<String, Vec<u8>>,
+}
+
+// assemble assembles `source` located at PROGRAM_START
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    Assembler::new().assemble(source)
+}
+
+impl Assembler {
+    pub fn new() -> Assembler {
+        Assembler {
+            origin: PROGRAM_START,
+            includes: HashMap::new(),
+        }
+    }
+
+    // origin sets address of the first rom byte
+    pub fn origin(&mut self, origin: usize) -> &mut Assembler {
+        self.origin = origin;
+        self
+    }
+
+    // include registers bytes that can be included by `:include "name"`
+    pub fn include(&mut self, name: &str, bytes: &[u8]) -> &mut Assembler {
+        self.includes.insert(name.to_string(), bytes.to_vec());
+        self
+    }
+
+    pub fn assemble(&self, source: &str) -> Result<Program, AsmError> {
+        let mut symbols = BTreeMap::new();
+        let mut items = Vec::new();
+        let mut addr = self.origin;
+
+        // first pass: parse statements, assign addresses to labels & evaluate constants
+        for (i, text) in source.lines().enumerate() {
+            let line = Line { number: i + 1, text: strip_comment(text) };
+            let mut rest = line.token_at(0);
+
+            if let Some((label, statement)) = rest.split_label() {
+                define(&mut symbols, &label, addr)?;
+                rest = statement;
+            }
+
+            let (head, operands) = rest.split_first();
+
+            if head.text.is_empty() {
+                continue;
+            }
+
+            let item = match head.text.to_ascii_lowercase().as_str() {
+                ":const" => {
+                    let (name, value) = operands.split_first();
+                    let value = value.trimmed();
+
+                    if !is_symbol(name.text) {
+                        return Err(name.error("invalid constant name"));
+                    }
+
+                    let value = eval(&symbols, &value, 0xFFFF)?;
+                    define(&mut symbols, &name, value as usize)?;
+                    continue;
+                },
+
+                ":org" => {
+                    let target = eval(&symbols, &operands.trimmed(), 0xFFFF)? as usize;
+
+                    if target < addr {
+                        return Err(operands.error("org address is before current address"));
+                    }
+
+                    Item::Bytes(vec![0; target - addr])
+                },
+
+                ":byte" => Item::Values(operands.words()),
+
+                ":sprite" => {
+                    let mut bytes = Vec::new();
+
+                    for row in operands.words() {
+                        bytes.extend(parse_sprite_row(&row)?);
+                    }
+
+                    Item::Bytes(bytes)
+                },
+
+                ":include" => {
+                    let name = operands.trimmed();
+                    let key = name.text.trim_matches('"');
+
+                    match self.includes.get(key) {
+                        Some(bytes) => Item::Bytes(bytes.clone()),
+                        None => return Err(name.error(&format!("unknown include \"{}\"", key))),
+                    }
+                },
+
+                directive if directive.starts_with(':') => {
+                    return Err(head.error(&format!("unknown directive {}", head.text)));
+                },
+
+                _ => Item::Instruction(head, operands.operands()),
+            };
+
+            addr += item.size();
+
+            if addr > MAX_MEM_SIZE {
+                return Err(head.error("program does not fit in memory"));
+            }
+
+            items.push(item);
+        }
+
+        // second pass: encode
+        let mut rom = Vec::with_capacity(addr - self.origin);
+
+        for item in items {
+            match item {
+                Item::Bytes(bytes) => rom.extend(bytes),
+
+                Item::Values(values) => {
+                    for value in values {
+                        rom.push(eval(&symbols, &value, 0xFF)? as u8);
+                    }
+                },
+
+                Item::Instruction(mnemonic, operands) => {
+                    let instruction = encode(&symbols, &mnemonic, &operands)?;
+                    let opcode = instruction.encode();
+
+                    for i in (0..instruction.size()).rev() {
+                        rom.push((opcode >> (8 * i)) as u8);
+                    }
+                },
+            }
+        }
+
+        Ok(Program { rom, symbols })
+    }
+}
+
+impl Default for Assembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum Item<'a> {
+    Bytes(Vec<u8>),
+    Values(Vec<Token<'a>>),
+    Instruction(Token<'a>, Vec<Token<'a>>),
+}
+
+impl<'a> Item<'a> {
+    fn size(&self) -> usize {
+        match self {
+            Item::Bytes(bytes) => bytes.len(),
+            Item::Values(values) => values.len(),
+
+            // LD I, LONG nnnn is the only 4 bytes instruction
+            Item::Instruction(_, operands) => {
+                let long = operands.get(1)
+                    .map(|op| op.text.to_ascii_lowercase().starts_with("long "))
+                    .unwrap_or(false);
+
+                if long { 4 } else { 2 }
+            },
+        }
+    }
+}
+
+struct Line<'a> {
+    number: usize,
+    text: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn token_at(&self, start: usize) -> Token<'a> {
+        Token { text: &self.text[start..], line: self.number, column: self.text[..start].chars().count() + 1 }
+    }
+}
+
+// Token is a part of line, with its position for error reporting
+#[derive(Clone, Copy, Debug)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Token<'a> {
+    fn error(&self, message: &str) -> AsmError {
+        AsmError { line: self.line, column: self.column, message: message.to_string() }
+    }
+
+    fn after(&self, offset: usize) -> Token<'a> {
+        Token {
+            text: &self.text[offset..],
+            line: self.line,
+            column: self.column + self.text[..offset].chars().count(),
+        }
+    }
+
+    fn trimmed(&self) -> Token<'a> {
+        let start = self.text.len() - self.text.trim_start().len();
+        let token = self.after(start);
+
+        Token { text: token.text.trim_end(), ..token }
+    }
+
+    // split_first splits token into the first word and the rest
+    fn split_first(&self) -> (Token<'a>, Token<'a>) {
+        let token = self.trimmed();
+
+        match token.text.find(char::is_whitespace) {
+            Some(i) => (Token { text: &token.text[..i], ..token }, token.after(i).trimmed()),
+            None => (token, token.after(token.text.len())),
+        }
+    }
+
+    // split_label splits `name: statement` into label name and the statement
+    fn split_label(&self) -> Option<(Token<'a>, Token<'a>)> {
+        let token = self.trimmed();
+        let end = token.text.find(':')?;
+        let label = Token { text: &token.text[..end], ..token };
+
+        if is_symbol(label.text) {
+            Some((label, token.after(end + 1)))
+        } else {
+            None
+        }
+    }
+
+    // words splits token by whitespace & commas
+    fn words(&self) -> Vec<Token<'a>> {
+        self.split(|c| c.is_whitespace() || c == ',')
+    }
+
+    // operands splits token by commas
+    fn operands(&self) -> Vec<Token<'a>> {
+        if self.text.trim().is_empty() {
+            return Vec::new();
+        }
+
+        self.split(|c| c == ',')
+    }
+
+    fn split(&self, separator: impl Fn(char) -> bool) -> Vec<Token<'a>> {
+        let mut tokens = Vec::new();
+        let mut start = 0;
+        let mut push = |start: usize, end: usize| {
+            let token = Token { text: &self.text[start..end], ..self.after(start) }.trimmed();
+
+            if !token.text.is_empty() {
+                tokens.push(token);
+            }
+        };
+
+        for (i, c) in self.text.char_indices() {
+            if separator(c) {
+                push(start, i);
+                start = i + c.len_utf8();
+            }
+        }
+
+        push(start, self.text.len());
+        tokens
+    }
+}
+
+fn strip_comment(text: &str) -> &str {
+    match text.find(';') {
+        Some(i) => &text[..i],
+        None => text,
+    }
+}
+
+fn is_symbol(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+        _ => return false,
+    }
+
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') && parse_register(name).is_none()
+}
+
+fn define(symbols: &mut BTreeMap<String, u16>, name: &Token, value: usize) -> Result<(), AsmError> {
+    if symbols.contains_key(name.text) {
+        return Err(name.error(&format!("duplicate symbol {}", name.text)));
+    }
+
+    symbols.insert(name.text.to_string(), value as u16);
+    Ok(())
+}
+
+// eval evaluates number or symbol, which must not exceed `max`
+fn eval(symbols: &BTreeMap<String, u16>, token: &Token, max: u32) -> Result<u16, AsmError> {
+    let text = token.text;
+    let lower = text.to_ascii_lowercase();
+
+    let value = if text.is_empty() {
+        return Err(token.error("missing value"));
+    } else if let Some(hex) = lower.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        u32::from_str_radix(bin, 2).ok()
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        text.parse::<u32>().ok()
+    } else {
+        match symbols.get(text) {
+            Some(&value) => Some(value as u32),
+            None => return Err(token.error(&format!("undefined symbol {}", text))),
+        }
+    };
+
+    match value {
+        Some(value) if value <= max => Ok(value as u16),
+        Some(_) => Err(token.error(&format!("value {} is out of range (max 0x{:X})", text, max))),
+        None => Err(token.error(&format!("invalid number {}", text))),
+    }
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let mut chars = text.chars();
+
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(c), None) | (Some('V'), Some(c), None) => c.to_digit(16).map(|x| x as u8),
+        _ => None,
+    }
+}
+
+fn parse_sprite_row(row: &Token) -> Result<Vec<u8>, AsmError> {
+    let width = row.text.chars().count();
+
+    if width != 8 && width != 16 {
+        return Err(row.error("sprite row must be 8 or 16 pixels wide"));
+    }
+
+    let mut bits = 0u16;
+
+    for c in row.text.chars() {
+        let bit = match c {
+            'X' | 'x' | '#' | '1' => 1,
+            '.' | '0' => 0,
+            _ => return Err(row.error(&format!("invalid sprite pixel '{}'", c))),
+        };
+
+        bits = bits << 1 | bit;
+    }
+
+    if width == 8 {
+        Ok(vec![bits as u8])
+    } else {
+        Ok(vec![(bits >> 8) as u8, bits as u8])
+    }
+}
+
+// Operand is a classified instruction operand
+enum Operand<'a> {
+    Reg(u8),
+    Range(u8, u8),  // Vx - Vy
+    Keyword(String),// I, DT, ST, K, F, HF, B, R, [I]
+    Long(Token<'a>),
+    Value(Token<'a>),
+}
+
+fn classify<'a>(token: &Token<'a>) -> Operand<'a> {
+    let lower = token.text.to_ascii_lowercase();
+
+    if let Some(x) = parse_register(token.text) {
+        return Operand::Reg(x);
+    }
+
+    if let Some(i) = token.text.find('-') {
+        let (x, y) = (token.text[..i].trim(), token.text[i + 1..].trim());
+
+        if let (Some(x), Some(y)) = (parse_register(x), parse_register(y)) {
+            return Operand::Range(x, y);
+        }
+    }
+
+    if lower.starts_with("long ") {
+        return Operand::Long(token.after(4).trimmed());
+    }
+
+    match lower.as_str() {
+        "i" | "dt" | "st" | "k" | "f" | "hf" | "b" | "r" | "[i]" => Operand::Keyword(lower),
+        _ => Operand::Value(*token),
+    }
+}
+
+const MNEMONICS: [&str; 33] = [
+    "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SCD", "SCU", "SYS", "JP", "CALL", "SE", "SNE",
+    "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND", "DRW", "SKP", "SKNP",
+    "PLANE", "PITCH", "DW",
+];
+
+fn encode(symbols: &BTreeMap<String, u16>, mnemonic: &Token, operands: &[Token]) -> Result<Instruction, AsmError> {
+    use Instruction::*;
+    use Operand::*;
+
+    let ops: Vec<Operand> = operands.iter().map(classify).collect();
+    let name = mnemonic.text.to_ascii_uppercase();
+
+    let byte = |token: &Token| eval(symbols, token, 0xFF).map(|v| v as u8);
+    let nibble = |token: &Token| eval(symbols, token, 0xF).map(|v| v as u8);
+    let addr = |token: &Token| eval(symbols, token, 0xFFF);
+
+    let instruction = match (name.as_str(), ops.as_slice()) {
+        ("CLS", []) => Cls,
+        ("RET", []) => Ret,
+        ("SCR", []) => ScrollRight,
+        ("SCL", []) => ScrollLeft,
+        ("EXIT", []) => Exit,
+        ("LOW", []) => LowRes,
+        ("HIGH", []) => HighRes,
+        ("AUDIO", []) => Audio,
+        ("SCD", [Value(n)]) => ScrollDown(nibble(n)?),
+        ("SCU", [Value(n)]) => ScrollUp(nibble(n)?),
+        ("SYS", [Value(a)]) => Sys(addr(a)?),
+        ("JP", [Value(a)]) => Jump(addr(a)?),
+        ("JP", [Reg(0), Value(a)]) => JumpV(addr(a)?),
+        ("CALL", [Value(a)]) => Call(addr(a)?),
+        ("SE", [Reg(x), Value(kk)]) => SkipEq(*x, byte(kk)?),
+        ("SE", [Reg(x), Reg(y)]) => SkipEqV(*x, *y),
+        ("SNE", [Reg(x), Value(kk)]) => SkipNeq(*x, byte(kk)?),
+        ("SNE", [Reg(x), Reg(y)]) => SkipNeqV(*x, *y),
+        ("SAVE", [Range(x, y)]) => SaveRange(*x, *y),
+        ("LOAD", [Range(x, y)]) => LoadRange(*x, *y),
+        ("LD", [Reg(x), Value(kk)]) => Load(*x, byte(kk)?),
+        ("LD", [Reg(x), Reg(y)]) => Set(*x, *y),
+        ("LD", [Reg(x), Keyword(k)]) => match k.as_str() {
+            "dt" => ReadDt(*x),
+            "k" => WaitKey(*x),
+            "[i]" => LoadReg(*x),
+            "r" => LoadRpl(*x),
+            _ => return Err(operands[1].error("invalid source operand")),
+        },
+        ("LD", [Keyword(k), Value(a)]) if k == "i" => LoadI(addr(a)?),
+        ("LD", [Keyword(k), Long(a)]) if k == "i" => LoadLong(eval(symbols, a, 0xFFFF)?),
+        ("LD", [Keyword(k), Reg(x)]) => match k.as_str() {
+            "dt" => LoadDt(*x),
+            "st" => LoadSt(*x),
+            "f" => Digit(*x),
+            "hf" => BigDigit(*x),
+            "b" => Bcd(*x),
+            "[i]" => Store(*x),
+            "r" => SaveRpl(*x),
+            _ => return Err(operands[0].error("invalid destination operand")),
+        },
+        ("ADD", [Reg(x), Value(kk)]) => Incr(*x, byte(kk)?),
+        ("ADD", [Reg(x), Reg(y)]) => Add(*x, *y),
+        ("ADD", [Keyword(k), Reg(x)]) if k == "i" => AddI(*x),
+        ("OR", [Reg(x), Reg(y)]) => Or(*x, *y),
+        ("AND", [Reg(x), Reg(y)]) => And(*x, *y),
+        ("XOR", [Reg(x), Reg(y)]) => Xor(*x, *y),
+        ("SUB", [Reg(x), Reg(y)]) => Sub(*x, *y),
+        ("SUBN", [Reg(x), Reg(y)]) => SubN(*x, *y),
+        ("SHR", [Reg(x)]) => Shr(*x, *x),
+        ("SHR", [Reg(x), Reg(y)]) => Shr(*x, *y),
+        ("SHL", [Reg(x)]) => Shl(*x, *x),
+        ("SHL", [Reg(x), Reg(y)]) => Shl(*x, *y),
+        ("RND", [Reg(x), Value(kk)]) => Rand(*x, byte(kk)?),
+        ("DRW", [Reg(x), Reg(y), Value(n)]) => Draw(*x, *y, nibble(n)?),
+        ("SKP", [Reg(x)]) => SkipKey(*x),
+        ("SKNP", [Reg(x)]) => SkipNotKey(*x),
+        ("PLANE", [Value(n)]) => Plane(eval(symbols, n, 3)? as u8),
+        ("PITCH", [Reg(x)]) => Pitch(*x),
+        ("DW", [Value(word)]) => Unknown(eval(symbols, word, 0xFFFF)?),
+
+        (name, _) if MNEMONICS.contains(&name) => {
+            return Err(mnemonic.error(&format!("invalid operands for {}", name)));
+        },
+
+        _ => return Err(mnemonic.error(&format!("unknown mnemonic {}", mnemonic.text))),
+    };
+
+    Ok(instruction)
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+    use crate::disasm::{disassemble, Syntax};
+
+    #[wasm_bindgen_test]
+    fn test_assemble() {
+        let source = "
+            :const SPEED 3      ; constant
+            start:
+                LD V0, SPEED
+                ld v1, 0x1F
+            loop: ADD V0, V1
+                SE V0, 0b0101
+                JP loop
+                LD I, sprite
+                DRW V0, V1, 2
+                LD I, LONG 0x1234
+                SAVE V1 - V3
+                JP start
+            sprite:
+                :sprite X......X ..XXXX..
+                :byte 1 0x02
+        ";
+
+        let program = assemble(source).unwrap();
+
+        assert_eq!(program.rom, vec![
+            0x60, 0x03, 0x61, 0x1F, 0x80, 0x14, 0x30, 0x05, 0x12, 0x04, 0xA2, 0x16, 0xD0, 0x12,
+            0xF0, 0x00, 0x12, 0x34, 0x51, 0x32, 0x12, 0x00, 0x81, 0x3C, 0x01, 0x02,
+        ]);
+
+        assert_eq!(program.symbols["SPEED"], 3);
+        assert_eq!(program.symbols["start"], 0x200);
+        assert_eq!(program.symbols["loop"], 0x204);
+        assert_eq!(program.symbols["sprite"], 0x216);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_assemble_directives() {
+        let mut assembler = Assembler::new();
+        assembler.origin(0x300).include("ship", &[0x18, 0x3C]);
+
+        let program = assembler.assemble("
+            JP data
+            :org 0x306
+            data: :include \"ship\"
+        ").unwrap();
+
+        assert_eq!(program.rom, vec![0x13, 0x06, 0, 0, 0, 0, 0x18, 0x3C]);
+        assert_eq!(program.symbols["data"], 0x306);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_disassembly_round_trip() {
+        let rom = [
+            0x00, 0xE0, 0x00, 0xC3, 0x23, 0x45, 0x4A, 0x12, 0x5A, 0xB3, 0x8A, 0xB6, 0xB2, 0x22, 0xE3, 0xA1,
+            0xF3, 0x0A, 0xF3, 0x30, 0xF3, 0x85, 0xF2, 0x01, 0xF0, 0x00, 0xAB, 0xCD, 0x81, 0x29, 0x00, 0xFF,
+        ];
+
+        let source: Vec<String> = disassemble(&rom, 0x200, Syntax::Classic)
+            .iter()
+            .map(|line| format!("{} {}", line.mnemonic, line.operands))
+            .collect();
+
+        let program = assemble(&source.join("\n")).unwrap();
+        assert_eq!(program.rom, rom.to_vec());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_assemble_errors() {
+        let error = assemble("CLS\n  LD V0, unknown").unwrap_err();
+        assert_eq!(error, AsmError { line: 2, column: 10, message: "undefined symbol unknown".to_string() });
+
+        let error = assemble("  FOO V0").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 3));
+        assert_eq!(error.message, "unknown mnemonic FOO");
+
+        let error = assemble("DRW V0, V1, 16").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 13));
+
+        let error = assemble("LD DT, 5").unwrap_err();
+        assert_eq!(error.message, "invalid operands for LD");
+
+        let error = assemble("a:\na: CLS").unwrap_err();
+        assert_eq!((error.line, error.message.as_str()), (2, "duplicate symbol a"));
+
+        let error = assemble(":sprite XX..").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 9));
+    }
+}
@@ -0,0 +1,141 @@
+/****
+ * Host backends: random number source & logging, so the core runs the same in browser, native & tests.
+ * JS-backed implementations are only available with the `wasm` feature on wasm32 target
+ */
+
+// Rng is the random source of cxkk
+pub trait Rng {
+    fn next_u8(&mut self) -> u8;
+
+    // state returns the generator state kept in save states, None if it can't be restored (e.g. browser random)
+    fn state(&self) -> Option<u32> {
+        None
+    }
+
+    fn set_state(&mut self, _state: u32) {}
+}
+
+// Logger receives runtime messages, e.g. runtime errors
+pub trait Logger {
+    fn log(&mut self, message: &str);
+}
+
+// XorShiftRng is a small seedable PRNG (xorshift32), same seed always gives the same sequence
+pub struct XorShiftRng {
+    state: u32,
+}
+
+impl XorShiftRng {
+    pub fn new(seed: u32) -> XorShiftRng {
+        // xorshift state must not be zero
+        let state = if seed == 0 { 0x9E37_79B9 } else { seed };
+        XorShiftRng { state }
+    }
+}
+
+impl Rng for XorShiftRng {
+    fn next_u8(&mut self) -> u8 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+
+        (x >> 24) as u8
+    }
+
+    fn state(&self) -> Option<u32> {
+        Some(self.state)
+    }
+
+    fn set_state(&mut self, state: u32) {
+        *self = XorShiftRng::new(state);
+    }
+}
+
+// StderrLogger prints to standard error
+pub struct StderrLogger;
+
+impl Logger for StderrLogger {
+    fn log(&mut self, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+// NullLogger discards every message
+pub struct NullLogger;
+
+impl Logger for NullLogger {
+    fn log(&mut self, _message: &str) {}
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub struct JsRng;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+impl Rng for JsRng {
+    fn next_u8(&mut self) -> u8 {
+        (js_sys::Math::random() * 255.0).floor() as u8
+    }
+}
+
+// ConsoleLogger prints to browser console
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub struct ConsoleLogger;
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+impl Logger for ConsoleLogger {
+    fn log(&mut self, message: &str) {
+        web_sys::console::log_1(&message.into());
+    }
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub fn default_rng() -> Box<dyn Rng> {
+    Box::new(JsRng)
+}
+
+// default_rng is seeded from clock on native targets
+#[cfg(not(target_arch = "wasm32"))]
+pub fn default_rng() -> Box<dyn Rng> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    Box::new(XorShiftRng::new(seed))
+}
+
+// wasm32 without browser has no clock, SystemTime::now panics there: fixed seed
+#[cfg(all(not(feature = "wasm"), target_arch = "wasm32"))]
+pub fn default_rng() -> Box<dyn Rng> {
+    Box::new(XorShiftRng::new(0))
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub fn default_logger() -> Box<dyn Logger> {
+    Box::new(ConsoleLogger)
+}
+
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+pub fn default_logger() -> Box<dyn Logger> {
+    Box::new(StderrLogger)
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_seeded_rng() {
+        let sequence = |seed| {
+            let mut rng = XorShiftRng::new(seed);
+            (0..32).map(|_| rng.next_u8()).collect::<Vec<u8>>()
+        };
+
+        assert_eq!(sequence(1234), sequence(1234));
+        assert_ne!(sequence(1234), sequence(1235));
+
+        // zero seed still produces numbers
+        assert!(sequence(0).iter().any(|&x| x != 0));
+    }
+}
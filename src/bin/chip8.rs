@@ -0,0 +1,341 @@
+/****
+ * Headless command line runner
+ *
+ *   chip8 [options] <rom.ch8>
+ *
+ * Runs rom for a number of frames or until it halts, then prints the display, registers and exit status.
+ * Exit code is 0 when the run ends normally, 1 on runtime error or replay mismatch and 2 on invalid
+ * arguments, rom or movie.
+ */
+
+use std::env;
+use std::fs;
+use std::process;
+use chip8_rs::{Machine, Platform, ExecutionStatus, ErrorPolicy, TimingMode, PROGRAM_START};
+use common::{KeyPress, KeyScript, parse_number, parse_platform, parse_keys};
+
+mod common;
+
+const USAGE : &str = "usage: chip8 [options] <rom.ch8>
+
+options:
+  --frames N          run N frames (default 600), stopping early on halt or error
+  --ips N             instructions per second (default 540), timers always run at 60 Hz
+  --timing MODE       fixed (default, --ips instructions per second) or vip (COSMAC VIP cycle costs)
+  --platform NAME     quirk profile: vip, chip48, schip10, schip11, schip-modern, xochip
+  --memory SIZE       memory size in bytes, up to 65536 (XO-CHIP)
+  --errors POLICY     unknown opcode & bad memory access handling: strict (default), lenient, wrap
+  --seed N            random seed, runs with the same seed and keys are reproducible
+  --keys SCRIPT       scripted key input, comma separated FRAME:KEY[:DURATION] presses, e.g. 10:5,40:a:3
+                      key is a hex digit, held for DURATION frames (default 1) then released
+  --record FILE       save the run as a movie: seed, settings & keys of each frame
+  --replay FILE       replay a movie recorded with this rom instead of --frames, --seed & --keys,
+                      and check that it ends in the recorded state
+  --quiet             only print exit status
+  --help              show this message";
+
+const DEFAULT_FRAMES : usize = 600;
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    rom: String,
+    frames: usize,
+    ips: Option<u32>,
+    timing: Option<TimingMode>,
+    platform: Option<Platform>,
+    memory: Option<usize>,
+    errors: Option<ErrorPolicy>,
+    seed: Option<u32>,
+    keys: Vec<KeyPress>,
+    record: Option<String>,
+    replay: Option<String>,
+    quiet: bool,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let options = match parse_args(&args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        },
+        Err(err) => {
+            eprintln!("chip8: {}\n\n{}", err, USAGE);
+            process::exit(2);
+        },
+    };
+
+    let rom = read_file(&options.rom);
+
+    let mut machine = Machine::new();
+
+    if let Some(platform) = options.platform {
+        machine.set_platform(platform);
+    }
+
+    if let Some(size) = options.memory {
+        machine.set_memory_size(size);
+    }
+
+    if let Some(policy) = options.errors {
+        machine.set_error_policy(policy);
+    }
+
+    if let Some(seed) = options.seed {
+        machine.set_seed(seed);
+    }
+
+    if let Some(ips) = options.ips {
+        machine.set_instructions_per_second(ips);
+    }
+
+    if let Some(timing) = options.timing {
+        machine.set_timing_mode(timing);
+    }
+
+    if !machine.load_rom(&rom) {
+        eprintln!("chip8: rom is too big ({} bytes, max {})", rom.len(), machine.memory().len() - PROGRAM_START);
+        process::exit(2);
+    }
+
+    let (status, frames) = match &options.replay {
+        Some(path) => {
+            let movie = read_file(path);
+
+            if let Err(err) = machine.start_replay(&movie) {
+                eprintln!("chip8: cannot replay {}: {}", path, err);
+                process::exit(2);
+            }
+
+            replay(&mut machine)
+        },
+        None => {
+            // recording starts from power on with the seed, a random one if not given
+            if options.record.is_some() {
+                machine.start_recording(options.seed.unwrap_or_else(random_seed));
+            }
+
+            run(&mut machine, &options)
+        },
+    };
+
+    if !options.quiet {
+        print_display(&machine);
+        print_registers(&machine);
+    }
+
+    println!("status: {:?} after {} frames", status, frames);
+
+    if let Some(path) = &options.record {
+        let movie = machine.stop_recording().unwrap_or_default();
+
+        if let Err(err) = fs::write(path, movie) {
+            eprintln!("chip8: cannot write {}: {}", path, err);
+            process::exit(2);
+        }
+    }
+
+    if options.replay.is_some() {
+        let matches = machine.replay_matches();
+        println!("replay: {}", if matches { "ok" } else { "state mismatch" });
+
+        if !matches {
+            process::exit(1);
+        }
+    }
+
+    if let Some(error) = machine.error() {
+        println!("error: {}", error);
+    }
+
+    if status == ExecutionStatus::RuntimeError {
+        process::exit(1);
+    }
+}
+
+fn read_file(path: &str) -> Vec<u8> {
+    match fs::read(path) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("chip8: cannot read {}: {}", path, err);
+            process::exit(2);
+        },
+    }
+}
+
+fn random_seed() -> u32 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0)
+}
+
+// replay updates machine until every movie frame is played, returns the last status and frames run
+fn replay(machine: &mut Machine) -> (ExecutionStatus, usize) {
+    let mut status = ExecutionStatus::OK;
+    let mut frames = 0;
+
+    while machine.is_replaying() {
+        status = machine.update();
+        frames += 1;
+    }
+
+    (status, frames)
+}
+
+// run updates machine frame by frame, returns the last status and frames run
+fn run(machine: &mut Machine, options: &Options) -> (ExecutionStatus, usize) {
+    let mut keys = KeyScript::new(options.keys.clone());
+
+    for frame in 0..options.frames {
+        keys.apply(machine, frame);
+
+        let status = machine.update();
+
+        if status != ExecutionStatus::OK {
+            return (status, frame + 1);
+        }
+    }
+
+    (ExecutionStatus::OK, options.frames)
+}
+
+fn print_display(machine: &Machine) {
+    let width = machine.get_display_update().width;
+
+    // one char per pixel: plane bits 0..3
+    for row in machine.display().chunks(width) {
+        let line: String = row.iter().map(|&p| ['.', '#', '+', '@'][p as usize & 3]).collect();
+        println!("{}", line);
+    }
+}
+
+fn print_registers(machine: &Machine) {
+    let registers: Vec<String> = (0..16)
+        .map(|x| format!("V{:X}={:02X}", x, machine.get_register(x)))
+        .collect();
+
+    println!("{}", registers.join(" "));
+    println!("I={:04X} PC={:04X} SP={}", machine.get_index(), machine.get_pc(), machine.get_stack_depth());
+}
+
+// parse_args returns None when help is requested
+fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut rom = None;
+    let mut options = Options {
+        rom: String::new(),
+        frames: DEFAULT_FRAMES,
+        ips: None,
+        timing: None,
+        platform: None,
+        memory: None,
+        errors: None,
+        seed: None,
+        keys: Vec::new(),
+        record: None,
+        replay: None,
+        quiet: false,
+    };
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("missing value for {}", name));
+
+        match arg.as_str() {
+            "--help" | "-h" => return Ok(None),
+            "--quiet" | "-q" => options.quiet = true,
+            "--frames" => options.frames = parse_number(value(arg)?)?,
+            "--ips" => options.ips = Some(parse_number(value(arg)?)? as u32),
+            "--timing" => options.timing = Some(parse_timing(value(arg)?)?),
+            "--memory" => options.memory = Some(parse_number(value(arg)?)?),
+            "--seed" => options.seed = Some(parse_number(value(arg)?)? as u32),
+            "--platform" => options.platform = Some(parse_platform(value(arg)?)?),
+            "--errors" => options.errors = Some(parse_error_policy(value(arg)?)?),
+            "--keys" => options.keys = parse_keys(value(arg)?)?,
+            "--record" => options.record = Some(value(arg)?.to_string()),
+            "--replay" => options.replay = Some(value(arg)?.to_string()),
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+            path if rom.is_none() => rom = Some(path.to_string()),
+            extra => return Err(format!("unexpected argument {}", extra)),
+        }
+    }
+
+    options.rom = rom.ok_or("missing rom file")?;
+    Ok(Some(options))
+}
+
+fn parse_error_policy(name: &str) -> Result<ErrorPolicy, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "strict" => Ok(ErrorPolicy::Strict),
+        "lenient" => Ok(ErrorPolicy::Lenient),
+        "wrap" => Ok(ErrorPolicy::Wrap),
+        _ => Err(format!("unknown error policy {}", name)),
+    }
+}
+
+fn parse_timing(name: &str) -> Result<TimingMode, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "fixed" => Ok(TimingMode::Fixed),
+        "vip" | "cosmac-vip" => Ok(TimingMode::CosmacVip),
+        _ => Err(format!("unknown timing mode {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(&args("--frames 0x10 --ips 1000 --timing vip --seed 7 --platform schip11 --errors wrap --keys 3:a,5:1:2 --record pong.c8mv -q pong.ch8"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(options.rom, "pong.ch8");
+        assert_eq!(options.frames, 16);
+        assert_eq!(options.ips, Some(1000));
+        assert_eq!(options.timing, Some(TimingMode::CosmacVip));
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.platform, Some(Platform::SChip11));
+        assert_eq!(options.errors, Some(ErrorPolicy::Wrap));
+        assert_eq!(options.keys, vec![
+            KeyPress { frame: 3, key: 0xA, duration: 1 },
+            KeyPress { frame: 5, key: 1, duration: 2 },
+        ]);
+        assert_eq!(options.record, Some("pong.c8mv".to_string()));
+        assert!(options.quiet);
+
+        assert_eq!(parse_args(&args("--help")), Ok(None));
+        assert!(parse_args(&args("--frames")).is_err());
+        assert!(parse_args(&args("--platform nes rom.ch8")).is_err());
+        assert!(parse_args(&args("--keys 1:g rom.ch8")).is_err());
+        assert!(parse_args(&args("--errors ignore rom.ch8")).is_err());
+        assert!(parse_args(&args("--timing exact rom.ch8")).is_err());
+        assert!(parse_args(&args("a.ch8 b.ch8")).is_err());
+        assert!(parse_args(&args("")).is_err());
+    }
+
+    #[test]
+    fn test_run_keys() {
+        // waits for key, then stores it in V1 & halts
+        let rom = [0xF1, 0x0A, 0x00, 0xFD];
+
+        let mut machine = Machine::new();
+        machine.load_rom(&rom);
+
+        let mut options = parse_args(&args("--keys 2:7:3 rom.ch8")).unwrap().unwrap();
+        options.frames = 10;
+
+        let (status, frames) = run(&mut machine, &options);
+
+        // key is released on frame 5
+        assert_eq!((status, frames), (ExecutionStatus::Halt, 6));
+        assert_eq!(machine.get_register(1), 7);
+    }
+}
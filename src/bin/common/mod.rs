@@ -0,0 +1,101 @@
+/****
+ * Shared by the command line tools: number, platform & key script parsing
+ */
+
+use chip8_rs::{Machine, Platform};
+
+pub const PLATFORMS : [(&str, Platform); 6] = [
+    ("vip", Platform::CosmacVip),
+    ("chip48", Platform::Chip48),
+    ("schip10", Platform::SChip10),
+    ("schip11", Platform::SChip11),
+    ("schip-modern", Platform::SChipModern),
+    ("xochip", Platform::XoChip),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyPress {
+    pub frame: usize,
+    pub key: u8,
+    pub duration: usize,
+}
+
+// KeyScript gives scripted key presses to the machine frame by frame
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyScript {
+    pub presses: Vec<KeyPress>,
+    held: u16,
+}
+
+impl KeyScript {
+    pub fn new(presses: Vec<KeyPress>) -> KeyScript {
+        KeyScript { presses, held: 0 }
+    }
+
+    // apply sets keys of `frame`, frames must be applied in order
+    pub fn apply(&mut self, machine: &mut Machine, frame: usize) {
+        let mut pressed = 0u16;
+        let mut released = 0u16;
+
+        for press in self.presses.iter() {
+            let bit = 1 << press.key;
+
+            if frame >= press.frame && frame < press.frame + press.duration {
+                pressed |= bit;
+            } else if frame == press.frame + press.duration {
+                released |= bit;
+            }
+        }
+
+        // a key still held by another press is not released
+        released &= !pressed & self.held;
+        self.held = pressed;
+
+        machine.set_keys(pressed, released);
+    }
+}
+
+pub fn parse_number(text: &str) -> Result<usize, String> {
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+
+    parsed.map_err(|_| format!("invalid number {}", text))
+}
+
+pub fn parse_platform(name: &str) -> Result<Platform, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "cosmac-vip" => Ok(Platform::CosmacVip),
+        "schip" => Ok(Platform::SChip11),
+        "xo-chip" => Ok(Platform::XoChip),
+        lower => PLATFORMS.iter()
+            .find(|(short, _)| *short == lower)
+            .map(|&(_, platform)| platform)
+            .ok_or(format!("unknown platform {}", name)),
+    }
+}
+
+// parse_keys reads comma separated FRAME:KEY[:DURATION] presses
+pub fn parse_keys(script: &str) -> Result<Vec<KeyPress>, String> {
+    script.split(',')
+        .filter(|press| !press.trim().is_empty())
+        .map(|press| {
+            let fields: Vec<&str> = press.trim().split(':').collect();
+            let invalid = || format!("invalid key press {}", press);
+
+            if fields.len() < 2 || fields.len() > 3 {
+                return Err(invalid());
+            }
+
+            let frame = fields[0].parse().map_err(|_| invalid())?;
+            let key = u8::from_str_radix(fields[1], 16).ok().filter(|&k| k < 16).ok_or_else(invalid)?;
+            let duration = match fields.get(2) {
+                Some(duration) => duration.parse().ok().filter(|&d| d > 0).ok_or_else(invalid)?,
+                None => 1,
+            };
+
+            Ok(KeyPress { frame, key, duration })
+        })
+        .collect()
+}
@@ -0,0 +1,565 @@
+/****
+ * Conformance runner for CHIP-8 test rom suites
+ *
+ *   conformance [--platform NAME]... <manifest>
+ *
+ * Runs each rom of the manifest under every platform profile, then reads its result screen by looking for
+ * pass & fail glyphs in the display, and prints a compatibility report. Roms are not bundled: put them next
+ * to the manifest. Exit code is 0 when every check passes, 1 when some fail and 2 on invalid manifest or rom.
+ *
+ * Manifest is line based, `#` starts a comment:
+ *
+ *   glyph pass             glyph bitmap, followed by rows of `#` (set) and `.` (clear) pixels.
+ *   ..#                    a glyph name can be defined more than once for variants.
+ *   #..                    `pass` & `fail` glyphs are required, a glyph only matches with a clear border
+ *
+ *   rom 4-flags.ch8 frames=300 keys@vip=10:1 poke=0x1FF:1
+ *                          rom file relative to the manifest, then options:
+ *                            frames=N              frames to run (default 300), stops early on halt
+ *                            checks=N              glyphs expected on screen without named checks, each
+ *                                                  one short of N is a missing check
+ *                            memory=SIZE           memory size
+ *                            platforms=vip,...     only run under these platforms
+ *                            keys=SCRIPT           key input, same as `chip8 --keys`
+ *                            poke=ADDR:VALUE       write memory after loading, e.g. preselect a menu item
+ *                          `keys` & `poke` can be limited to a platform with `@platform`, e.g. `poke@xochip=...`
+ *
+ *   check carry 0 8 64 6   named check of the previous rom: region x y width height should hold a pass glyph.
+ *                          without named checks, every glyph found on screen is a check named by its position.
+ *                          a missing glyph never counts as a pass
+ */
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use chip8_rs::{Machine, Platform, ExecutionStatus, NullLogger};
+use common::{KeyPress, KeyScript, PLATFORMS, parse_number, parse_platform, parse_keys};
+
+mod common;
+
+const USAGE : &str = "usage: conformance [--platform NAME]... <manifest>
+
+options:
+  --platform NAME     only run under this platform, can be repeated
+  --verbose           list every check, not only failures
+  --help              show this message";
+
+const DEFAULT_FRAMES : usize = 300;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Glyph {
+    name: String,
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Region {
+    name: String,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct RomSpec {
+    file: String,
+    frames: usize,
+    memory: Option<usize>,
+    platforms: Vec<Platform>,                   // empty for every platform
+    keys: Vec<(Option<Platform>, KeyPress)>,
+    pokes: Vec<(Option<Platform>, usize, u8)>,
+    checks: Vec<Region>,
+    expected: Option<usize>,                    // glyph count without named checks
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Manifest {
+    glyphs: Vec<Glyph>,
+    roms: Vec<RomSpec>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    Pass,
+    Fail,
+    Missing,    // no glyph where a result was expected
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct CheckResult {
+    name: String,
+    outcome: Outcome,
+}
+
+// RunResult is the checks of one rom under one platform
+#[derive(Debug)]
+struct RunResult {
+    status: ExecutionStatus,
+    checks: Vec<CheckResult>,
+}
+
+impl RunResult {
+    fn passed(&self) -> usize {
+        self.checks.iter().filter(|check| check.outcome == Outcome::Pass).count()
+    }
+
+    fn all_passed(&self) -> bool {
+        self.status != ExecutionStatus::RuntimeError && self.passed() == self.checks.len()
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let mut manifest_path = None;
+    let mut platforms = Vec::new();
+    let mut verbose = false;
+    let mut args_iter = args.iter();
+
+    while let Some(arg) = args_iter.next() {
+        let result = match arg.as_str() {
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return;
+            },
+            "--verbose" | "-v" => {
+                verbose = true;
+                Ok(())
+            },
+            "--platform" => args_iter.next()
+                .ok_or("missing value for --platform".to_string())
+                .and_then(|name| parse_platform(name))
+                .map(|platform| platforms.push(platform)),
+            flag if flag.starts_with('-') => Err(format!("unknown option {}", flag)),
+            path if manifest_path.is_none() => {
+                manifest_path = Some(path.to_string());
+                Ok(())
+            },
+            extra => Err(format!("unexpected argument {}", extra)),
+        };
+
+        if let Err(err) = result {
+            usage_error(&err);
+        }
+    }
+
+    let manifest_path = manifest_path.unwrap_or_else(|| usage_error("missing manifest file"));
+    let source = fs::read_to_string(&manifest_path).unwrap_or_else(|err| {
+        eprintln!("conformance: cannot read {}: {}", manifest_path, err);
+        process::exit(2);
+    });
+
+    let manifest = parse_manifest(&source).unwrap_or_else(|err| {
+        eprintln!("conformance: {}: {}", manifest_path, err);
+        process::exit(2);
+    });
+
+    let dir = Path::new(&manifest_path).parent().unwrap_or_else(|| Path::new("."));
+    let platforms: Vec<(&str, Platform)> = PLATFORMS.iter()
+        .filter(|(_, platform)| platforms.is_empty() || platforms.contains(platform))
+        .copied()
+        .collect();
+
+    let mut all_passed = true;
+    let mut report = Vec::new();
+
+    for spec in manifest.roms.iter() {
+        let rom = fs::read(dir.join(&spec.file)).unwrap_or_else(|err| {
+            eprintln!("conformance: cannot read {}: {}", spec.file, err);
+            process::exit(2);
+        });
+
+        let results: Vec<Option<RunResult>> = platforms.iter()
+            .map(|&(_, platform)| {
+                let enabled = spec.platforms.is_empty() || spec.platforms.contains(&platform);
+                enabled.then(|| run_rom(spec, &rom, platform, &manifest.glyphs))
+            })
+            .collect();
+
+        all_passed &= results.iter().flatten().all(RunResult::all_passed);
+        report.push((spec, results));
+    }
+
+    print_report(&platforms, &report, verbose);
+
+    if !all_passed {
+        process::exit(1);
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("conformance: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+// run_rom runs test rom under platform, then checks its display
+fn run_rom(spec: &RomSpec, rom: &[u8], platform: Platform, glyphs: &[Glyph]) -> RunResult {
+    let for_platform = |target: &Option<Platform>| target.is_none_or(|p| p == platform);
+
+    let mut machine = Machine::new();
+    machine.set_platform(platform);
+    machine.set_seed(0);
+    machine.set_logger(Box::new(NullLogger));
+
+    if let Some(size) = spec.memory {
+        machine.set_memory_size(size);
+    }
+
+    if !machine.load_rom(rom) {
+        return RunResult { status: ExecutionStatus::RuntimeError, checks: Vec::new() };
+    }
+
+    for &(_, addr, value) in spec.pokes.iter().filter(|(target, _, _)| for_platform(target)) {
+        if let Some(byte) = machine.memory_mut().get_mut(addr) {
+            *byte = value;
+        }
+    }
+
+    let presses = spec.keys.iter().filter(|(target, _)| for_platform(target)).map(|&(_, press)| press);
+    let mut keys = KeyScript::new(presses.collect());
+    let mut status = ExecutionStatus::OK;
+
+    for frame in 0..spec.frames {
+        keys.apply(&mut machine, frame);
+        status = machine.update();
+
+        // test roms end in an infinite loop, reported as halt
+        if status == ExecutionStatus::Halt || status == ExecutionStatus::RuntimeError {
+            break;
+        }
+    }
+
+    let width = machine.get_display_update().width;
+    let checks = check_display(machine.display(), width, glyphs, &spec.checks, spec.expected);
+
+    RunResult { status, checks }
+}
+
+// check_display finds pass & fail glyphs in display, then turns them into check results.
+// without regions, glyphs short of `expected` (at least one) are missing checks
+fn check_display(display: &[u8], width: usize, glyphs: &[Glyph], regions: &[Region], expected: Option<usize>)
+    -> Vec<CheckResult>
+{
+    let found = find_glyphs(display, width, glyphs);
+
+    if regions.is_empty() {
+        let mut checks: Vec<CheckResult> = found.iter()
+            .map(|&(x, y, outcome)| CheckResult { name: format!("{},{}", x, y), outcome })
+            .collect();
+
+        for number in checks.len()..expected.unwrap_or(1) {
+            checks.push(CheckResult { name: format!("missing #{}", number + 1), outcome: Outcome::Missing });
+        }
+
+        return checks;
+    }
+
+    regions.iter().map(|region| {
+        let inside = found.iter().filter(|&&(x, y, _)| {
+            x >= region.x && x < region.x + region.width && y >= region.y && y < region.y + region.height
+        });
+
+        let outcome = inside.fold(Outcome::Missing, |outcome, &(_, _, glyph)| match (outcome, glyph) {
+            (Outcome::Fail, _) | (_, Outcome::Fail) => Outcome::Fail,
+            _ => Outcome::Pass,
+        });
+
+        CheckResult { name: region.name.clone(), outcome }
+    }).collect()
+}
+
+// find_glyphs returns (x, y, Pass or Fail) of every isolated pass & fail glyph in display, row by row
+fn find_glyphs(display: &[u8], width: usize, glyphs: &[Glyph]) -> Vec<(usize, usize, Outcome)> {
+    let height = display.len() / width;
+    let mut found = Vec::new();
+
+    for y in 0..height {
+        for x in 0..width {
+            for glyph in glyphs.iter() {
+                let outcome = match glyph.name.as_str() {
+                    "pass" => Outcome::Pass,
+                    "fail" => Outcome::Fail,
+                    _ => continue,
+                };
+
+                if x + glyph.width > width || y + glyph.height > height {
+                    continue;
+                }
+
+                // glyph must match exactly, with a clear border so it is not part of a larger shape
+                let matches = (y.saturating_sub(1)..(y + glyph.height + 1).min(height)).all(|py| {
+                    (x.saturating_sub(1)..(x + glyph.width + 1).min(width)).all(|px| {
+                        let inside = px >= x && px < x + glyph.width && py >= y && py < y + glyph.height;
+                        let expected = inside && glyph.pixels[(py - y) * glyph.width + px - x];
+
+                        (display[py * width + px] != 0) == expected
+                    })
+                });
+
+                if matches {
+                    found.push((x, y, outcome));
+                }
+            }
+        }
+    }
+
+    found
+}
+
+fn print_report(platforms: &[(&str, Platform)], report: &[(&RomSpec, Vec<Option<RunResult>>)], verbose: bool) {
+    let name_width = report.iter().map(|(spec, _)| spec.file.len()).max().unwrap_or(0).max(3);
+
+    let header: Vec<String> = platforms.iter().map(|(name, _)| format!("{:>12}", name)).collect();
+    println!("{:<width$} {}", "rom", header.join(""), width = name_width);
+
+    for (spec, results) in report.iter() {
+        let cells: Vec<String> = results.iter().map(|result| {
+            let cell = match result {
+                Some(result) if result.status == ExecutionStatus::RuntimeError => "error".to_string(),
+                Some(result) => format!("{}/{}", result.passed(), result.checks.len()),
+                None => "-".to_string(),
+            };
+
+            format!("{:>12}", cell)
+        }).collect();
+
+        println!("{:<width$} {}", spec.file, cells.join(""), width = name_width);
+    }
+
+    println!();
+
+    for (spec, results) in report.iter() {
+        for (&(platform, _), result) in platforms.iter().zip(results.iter()) {
+            let result = match result {
+                Some(result) => result,
+                None => continue,
+            };
+
+            if result.status == ExecutionStatus::RuntimeError {
+                println!("{} [{}]: runtime error", spec.file, platform);
+            }
+
+            for check in result.checks.iter().filter(|check| verbose || check.outcome != Outcome::Pass) {
+                println!("{} [{}] {}: {:?}", spec.file, platform, check.name, check.outcome);
+            }
+        }
+    }
+}
+
+fn parse_manifest(source: &str) -> Result<Manifest, String> {
+    let mut glyphs: Vec<Glyph> = Vec::new();
+    let mut roms: Vec<RomSpec> = Vec::new();
+    let mut in_glyph = false;
+
+    for (number, raw) in source.lines().enumerate() {
+        let raw = raw.trim();
+        let error = |message: String| format!("line {}: {}", number + 1, message);
+
+        // glyph rows use `#`, so they are checked before stripping comment
+        if in_glyph && !raw.is_empty() && raw.chars().all(|c| c == '#' || c == '.') {
+            let glyph = glyphs.last_mut().unwrap();
+
+            if glyph.width != 0 && glyph.width != raw.len() {
+                return Err(error("glyph rows must have the same width".to_string()));
+            }
+
+            glyph.width = raw.len();
+            glyph.height += 1;
+            glyph.pixels.extend(raw.chars().map(|c| c == '#'));
+            continue;
+        }
+
+        in_glyph = false;
+
+        let line = raw.split('#').next().unwrap_or("").trim();
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        match fields.as_slice() {
+            [] => {},
+            ["glyph", name] => {
+                glyphs.push(Glyph { name: name.to_string(), width: 0, height: 0, pixels: Vec::new() });
+                in_glyph = true;
+            },
+            ["rom", file, options @ ..] => roms.push(parse_rom(file, options).map_err(error)?),
+            ["check", name, x, y, width, height] => {
+                let rom = roms.last_mut().ok_or_else(|| error("check before any rom".to_string()))?;
+                let number = |text: &str| parse_number(text).map_err(error);
+
+                rom.checks.push(Region {
+                    name: name.to_string(),
+                    x: number(x)?,
+                    y: number(y)?,
+                    width: number(width)?,
+                    height: number(height)?,
+                });
+            },
+            _ => return Err(error(format!("invalid line `{}`", line))),
+        }
+    }
+
+    for required in ["pass", "fail"] {
+        if !glyphs.iter().any(|glyph| glyph.name == required && glyph.height > 0) {
+            return Err(format!("missing `{}` glyph", required));
+        }
+    }
+
+    Ok(Manifest { glyphs, roms })
+}
+
+fn parse_rom(file: &str, options: &[&str]) -> Result<RomSpec, String> {
+    let mut spec = RomSpec {
+        file: file.to_string(),
+        frames: DEFAULT_FRAMES,
+        memory: None,
+        platforms: Vec::new(),
+        keys: Vec::new(),
+        pokes: Vec::new(),
+        checks: Vec::new(),
+        expected: None,
+    };
+
+    for option in options {
+        let (key, value) = option.split_once('=').ok_or(format!("invalid option {}", option))?;
+
+        let (key, platform) = match key.split_once('@') {
+            Some((key, platform)) => (key, Some(parse_platform(platform)?)),
+            None => (key, None),
+        };
+
+        match (key, platform) {
+            ("frames", None) => spec.frames = parse_number(value)?,
+            ("checks", None) => spec.expected = Some(parse_number(value)?),
+            ("memory", None) => spec.memory = Some(parse_number(value)?),
+            ("platforms", None) => {
+                spec.platforms = value.split(',').map(parse_platform).collect::<Result<_, _>>()?;
+            },
+            ("keys", platform) => spec.keys.extend(parse_keys(value)?.into_iter().map(|press| (platform, press))),
+            ("poke", platform) => {
+                let (addr, byte) = value.split_once(':').ok_or(format!("invalid poke {}", value))?;
+                let byte = parse_number(byte).ok().filter(|&b| b < 256).ok_or(format!("invalid poke {}", value))?;
+
+                spec.pokes.push((platform, parse_number(addr)?, byte as u8));
+            },
+            _ => return Err(format!("invalid option {}", option)),
+        }
+    }
+
+    Ok(spec)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chip8_rs::assembler::assemble;
+
+    const MANIFEST : &str = "
+        # tick & cross of the test program below
+        glyph pass
+        .#
+        #.
+        glyph fail
+        #.#
+        .#.
+        #.#
+
+        rom test.ch8 frames=10 platforms=vip,schip11 poke@schip11=0x1FF:1
+        check first 0 0 8 8
+        check second 8 0 8 8
+        check third 16 0 8 8
+    ";
+
+    // draws pass at (0, 0), fail at (8, 0) unless M[0x1FF] is set, and nothing at (16, 0)
+    const PROGRAM : &str = "
+            LD I, tick
+            LD V0, 0
+            DRW V0, V0, 2
+            LD I, 0x1FF
+            LD V0, [I]
+            LD I, cross
+            SE V0, 0
+            LD I, tick
+            LD V0, 8
+            LD V1, 0
+            DRW V0, V1, 3
+        end:
+            JP end
+        tick:
+            :byte 0x40 0x80 0x00
+        cross:
+            :byte 0xA0 0x40 0xA0
+    ";
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = parse_manifest(MANIFEST).unwrap();
+
+        assert_eq!(manifest.glyphs.len(), 2);
+        assert_eq!((manifest.glyphs[1].width, manifest.glyphs[1].height), (3, 3));
+        assert_eq!(manifest.glyphs[0].pixels, vec![false, true, true, false]);
+
+        let rom = &manifest.roms[0];
+        assert_eq!((rom.file.as_str(), rom.frames), ("test.ch8", 10));
+        assert_eq!(rom.platforms, vec![Platform::CosmacVip, Platform::SChip11]);
+        assert_eq!(rom.pokes, vec![(Some(Platform::SChip11), 0x1FF, 1)]);
+        assert_eq!(rom.checks[1], Region { name: "second".to_string(), x: 8, y: 0, width: 8, height: 8 });
+
+        assert!(parse_manifest("glyph pass\n#\n").is_err());
+        assert!(parse_manifest("check a 0 0 1 1").is_err());
+        assert!(parse_manifest("glyph pass\n#\nglyph fail\n.\nrom a.ch8 speed=2").is_err());
+    }
+
+    #[test]
+    fn test_timendus_manifest() {
+        let manifest = parse_manifest(include_str!("../../conformance/timendus.manifest")).unwrap();
+        let files: Vec<&str> = manifest.roms.iter().map(|rom| rom.file.as_str()).collect();
+
+        assert_eq!(files, vec!["3-corax+.ch8", "4-flags.ch8", "5-quirks.ch8", "6-keypad.ch8"]);
+        assert!(manifest.roms.iter().all(|rom| rom.checks.is_empty() && rom.expected.is_some()));
+
+        // quirks test runs the mode of each platform
+        let quirks = &manifest.roms[2];
+        assert_eq!(quirks.platforms.len(), 4);
+        assert!(quirks.platforms.iter().all(|&platform| quirks.pokes.iter().any(|&(p, _, _)| p == Some(platform))));
+
+        let keypad = &manifest.roms[3];
+        assert_eq!(keypad.pokes, vec![(None, 0x1FF, 3)]);
+        assert_eq!(keypad.keys, vec![(None, KeyPress { frame: 60, key: 5, duration: 20 })]);
+    }
+
+    #[test]
+    fn test_run_rom() {
+        let manifest = parse_manifest(MANIFEST).unwrap();
+        let rom = assemble(PROGRAM).unwrap().rom;
+        let spec = &manifest.roms[0];
+
+        let outcomes = |platform| {
+            let result = run_rom(spec, &rom, platform, &manifest.glyphs);
+            assert_eq!(result.status, ExecutionStatus::Halt);
+            result.checks.iter().map(|check| check.outcome).collect::<Vec<_>>()
+        };
+
+        assert_eq!(outcomes(Platform::CosmacVip), vec![Outcome::Pass, Outcome::Fail, Outcome::Missing]);
+        assert_eq!(outcomes(Platform::SChip11), vec![Outcome::Pass, Outcome::Pass, Outcome::Missing]);
+
+        // without named checks, each glyph is a check
+        let unnamed = RomSpec { checks: Vec::new(), ..spec.clone() };
+        let result = run_rom(&unnamed, &rom, Platform::CosmacVip, &manifest.glyphs);
+
+        assert_eq!(result.checks, vec![
+            CheckResult { name: "0,0".to_string(), outcome: Outcome::Pass },
+            CheckResult { name: "8,0".to_string(), outcome: Outcome::Fail },
+        ]);
+
+        // glyphs short of the expected count are missing, so the run does not pass
+        let expected = RomSpec { expected: Some(3), ..unnamed.clone() };
+        let result = run_rom(&expected, &rom, Platform::SChip11, &manifest.glyphs);
+
+        assert_eq!(result.checks[2], CheckResult { name: "missing #3".to_string(), outcome: Outcome::Missing });
+        assert_eq!((result.passed(), result.checks.len()), (2, 3));
+        assert!(!result.all_passed());
+    }
+}
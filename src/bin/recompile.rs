@@ -0,0 +1,127 @@
+/****
+ * Ahead-of-time recompiler command line
+ *
+ *   recompile [options] <rom.ch8>
+ *
+ * Recovers the code of rom and writes a Rust module running it, see chip8_rs::aot. The module needs chip8-rs as
+ * a dependency, and is driven by calling its frame() 60 times per second with a core from new_core().
+ * Exit code is 0 on success and 2 on invalid arguments, rom or output file.
+ */
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use chip8_rs::Platform;
+use chip8_rs::aot::{recover, emit};
+use common::parse_platform;
+
+#[allow(dead_code)]     // key scripts are only used by the runners
+mod common;
+
+const USAGE : &str = "usage: recompile [options] <rom.ch8>
+
+options:
+  --platform NAME     quirk profile of the module: vip, chip48, schip10, schip11, schip-modern, xochip
+                      (default: the default quirks)
+  --output FILE       write the module to FILE instead of standard output
+  --help              show this message";
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    rom: String,
+    platform: Option<Platform>,
+    output: Option<String>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let options = match parse_args(&args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        },
+        Err(err) => {
+            eprintln!("recompile: {}\n\n{}", err, USAGE);
+            process::exit(2);
+        },
+    };
+
+    let rom = match fs::read(&options.rom) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("recompile: cannot read {}: {}", options.rom, err);
+            process::exit(2);
+        },
+    };
+
+    let source = Path::new(&options.rom).file_name().map_or(options.rom.clone(), |name| name.to_string_lossy().into_owned());
+    let recovery = recover(&rom);
+    let module = emit(&rom, &recovery, options.platform, &source);
+
+    match &options.output {
+        Some(path) => {
+            if let Err(err) = fs::write(path, module) {
+                eprintln!("recompile: cannot write {}: {}", path, err);
+                process::exit(2);
+            }
+        },
+        None => print!("{}", module),
+    }
+
+    let instructions: usize = recovery.blocks.iter().map(|block| block.instructions.len()).sum();
+
+    eprintln!("recompile: {} blocks, {} instructions, {} indirect jumps, {} targets outside of the rom",
+        recovery.blocks.len(), instructions, recovery.indirect_jumps.len(), recovery.external.len());
+}
+
+fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut rom = None;
+    let mut options = Options { rom: String::new(), platform: None, output: None };
+
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("missing value for {}", name));
+
+        match arg.as_str() {
+            "--help" | "-h" => return Ok(None),
+            "--platform" => options.platform = Some(parse_platform(value(arg)?)?),
+            "--output" | "-o" => options.output = Some(value(arg)?.to_string()),
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}", flag)),
+            path if rom.is_none() => rom = Some(path.to_string()),
+            extra => return Err(format!("unexpected argument {}", extra)),
+        }
+    }
+
+    options.rom = rom.ok_or("missing rom file")?;
+    Ok(Some(options))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(&args("--platform schip11 -o pong.rs pong.ch8")).unwrap().unwrap();
+
+        assert_eq!(options, Options {
+            rom: "pong.ch8".to_string(),
+            platform: Some(Platform::SChip11),
+            output: Some("pong.rs".to_string()),
+        });
+
+        assert_eq!(parse_args(&args("--help")), Ok(None));
+        assert!(parse_args(&args("--output")).is_err());
+        assert!(parse_args(&args("--platform nes rom.ch8")).is_err());
+        assert!(parse_args(&args("a.ch8 b.ch8")).is_err());
+        assert!(parse_args(&args("")).is_err());
+    }
+}
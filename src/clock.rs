@@ -0,0 +1,224 @@
+/****
+ * Clock: converts host time into instructions and 60 Hz timer ticks
+ *
+ * Time within a frame is counted in FRAME_TIME units per 1/60 s, so host microseconds convert exactly (x 60).
+ * The cpu budget due at any point of a frame is derived from the rate per second, carrying the remainder of
+ * (rate / 60) across frames. A frame then runs the same instructions whether it is run in one `update` call
+ * or split over many `advance` calls.
+ *
+ * Budget units depend on timing mode: instructions with fixed timing, machine cycles with VIP timing. An
+ * instruction overrunning the frame budget, and the VIP's own work each frame, are paid by the next frame.
+ */
+
+use crate::cpu::{TimingMode, VIP_CYCLES_PER_SECOND, VIP_FRAME_OVERHEAD};
+
+pub const DEFAULT_IPS : u32 = 540;          // 9 instructions per frame
+pub const FRAME_TIME : u64 = 1_000_000;     // one 60 Hz frame, in 1/60 microseconds
+
+pub struct Clock {
+    ips: u32,
+    timing: TimingMode,
+    credit: u32,        // (frames * rate) % 60, budget carried into the current frame in 1/60 units
+    frame_time: u64,    // time elapsed in the current frame
+    spent: u32,         // budget spent in the current frame, including debt of the previous one
+    idle: bool,         // waiting for key or vblank, nothing runs until the frame ends
+}
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock { ips: DEFAULT_IPS, timing: TimingMode::Fixed, credit: 0, frame_time: 0, spent: 0, idle: false }
+    }
+
+    // restart goes back to the start of a frame, keeping rate & timing mode
+    pub fn restart(&mut self) {
+        self.credit = 0;
+        self.frame_time = 0;
+        self.spent = 0;
+        self.idle = false;
+    }
+
+    pub fn ips(&self) -> u32 {
+        self.ips
+    }
+
+    pub fn set_ips(&mut self, ips: u32) {
+        self.ips = ips;
+    }
+
+    pub fn set_timing(&mut self, timing: TimingMode) {
+        self.timing = timing;
+    }
+
+    // rate returns budget units per second
+    fn rate(&self) -> u32 {
+        match self.timing {
+            TimingMode::Fixed => self.ips,
+            TimingMode::CosmacVip => VIP_CYCLES_PER_SECOND,
+        }
+    }
+
+    pub fn frame_time(&self) -> u64 {
+        self.frame_time
+    }
+
+    // pending returns the budget left to reach `time` of the current frame
+    pub fn pending(&self, time: u64) -> u32 {
+        if self.idle {
+            return 0;
+        }
+
+        self.due(time).saturating_sub(self.spent)
+    }
+
+    fn due(&self, time: u64) -> u32 {
+        ((self.credit as u64 * FRAME_TIME + time * self.rate() as u64) / (60 * FRAME_TIME)) as u32
+    }
+
+    pub fn spend(&mut self, units: u32) {
+        self.spent += units;
+    }
+
+    pub fn set_idle(&mut self) {
+        self.idle = true;
+    }
+
+    pub fn set_frame_time(&mut self, time: u64) {
+        self.frame_time = time;
+    }
+
+    pub fn end_frame(&mut self) {
+        let overhead = match self.timing {
+            TimingMode::Fixed => 0,
+            TimingMode::CosmacVip => VIP_FRAME_OVERHEAD,
+        };
+
+        self.spent = self.spent.saturating_sub(self.due(FRAME_TIME)) + overhead;
+        self.credit = ((self.credit as u64 + self.rate() as u64) % 60) as u32;
+        self.frame_time = 0;
+        self.idle = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+    use crate::{Machine, ExecutionStatus, TimingMode, PROGRAM_START};
+    use crate::assembler::assemble;
+
+    // counts loop iterations in V1:V0 and reads delay timer, started at 0xFF, into V2
+    const PROGRAM : &str = "
+            LD V0, 0xFF
+            LD DT, V0
+            LD V0, 0
+        loop:
+            LD V2, DT
+            ADD V0, 1
+            SE V0, 0
+            JP loop
+            ADD V1, 1
+            JP loop
+    ";
+
+    // machines are seeded alike, random generator state is part of the compared state
+    fn program_machine(ips: u32) -> Machine {
+        let mut machine = Machine::new();
+        machine.set_seed(0);
+        machine.load_rom(&assemble(PROGRAM).unwrap().rom);
+        machine.set_instructions_per_second(ips);
+        machine
+    }
+
+    #[wasm_bindgen_test]
+    fn test_pending() {
+        let mut clock = Clock::new();
+        clock.set_ips(100);
+
+        // 100 / 60 = 1.67 instructions per frame, so frames run 1, 2, 2, 1, 2, 2... instructions
+        let mut counts = Vec::new();
+
+        for _ in 0..6 {
+            let half = clock.pending(FRAME_TIME / 2);
+            let count = clock.pending(FRAME_TIME);
+            assert!(half <= count);
+
+            counts.push(count);
+            clock.end_frame();
+        }
+
+        assert_eq!(counts, vec![1, 2, 2, 1, 2, 2]);
+
+        // spent budget is not pending anymore, idle frame runs nothing
+        clock.spend(1);
+        assert_eq!(clock.pending(FRAME_TIME), 0);
+
+        clock.set_idle();
+        assert_eq!(clock.pending(FRAME_TIME), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_advance_host_rate() {
+        // one second at 60 Hz, at 144 Hz and in uneven steps give the same state
+        let mut by_update = program_machine(1000);
+        for _ in 0..60 {
+            assert_eq!(by_update.update(), ExecutionStatus::OK);
+        }
+
+        let mut by_144hz = program_machine(1000);
+        for _ in 0..144 {
+            assert_eq!(by_144hz.advance(6944), ExecutionStatus::OK);
+        }
+        by_144hz.advance(1_000_000 - 144 * 6944);
+
+        let mut by_steps = program_machine(1000);
+        for &micros in [1, 40_000, 3, 459_996, 500_000].iter() {
+            by_steps.advance(micros);
+        }
+
+        assert!(by_update.save_state() == by_144hz.save_state());
+        assert!(by_update.save_state() == by_steps.save_state());
+
+        // delay timer dropped 59 times before the last frame's instructions, and 60 times after
+        assert_eq!(by_update.get_register(2), 0xFF - 59);
+
+        // twice the speed runs twice the iterations
+        let mut fast = program_machine(2000);
+        fast.advance(1_000_000);
+
+        let iterations = |m: &Machine| m.get_register(1) as usize * 256 + m.get_register(0) as usize;
+        assert!(iterations(&fast) >= 2 * iterations(&by_update) - 1);
+        assert_eq!(fast.get_register(2), 0xFF - 59);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_vip_timing() {
+        // draws in a loop counted by V0, each draw waits for the next frame
+        let mut machine = Machine::new();
+        machine.set_seed(0);
+        machine.load_rom(&assemble("
+            loop:
+                ADD V0, 1
+                DRW V1, V1, 15
+                JP loop
+        ").unwrap().rom);
+
+        machine.set_timing_mode(TimingMode::CosmacVip);
+        assert_eq!(machine.get_timing_mode(), TimingMode::CosmacVip);
+
+        for _ in 0..10 {
+            machine.update();
+        }
+
+        // first frame has no vertical blank, then one draw per frame
+        assert_eq!(machine.get_register(0), 10);
+
+        // same frames by advance
+        let mut advanced = Machine::new();
+        advanced.set_seed(0);
+        advanced.load_rom(&machine.memory()[PROGRAM_START..PROGRAM_START + 6]);
+        advanced.set_timing_mode(TimingMode::CosmacVip);
+        advanced.advance(166_667);
+
+        assert!(advanced.save_state() == machine.save_state());
+    }
+}
@@ -1,13 +1,36 @@
+pub mod instruction;
+mod block;
+mod decode_cache;
+mod timing;
+
 use wasm_bindgen::prelude::*;
-use crate::memory::{PROGRAM_START, MEM_SIZE, Memory, allocate_memory};
-use crate::utils;
-use crate::iodevice::{IOInterface, NO_KEY};
+use crate::memory::{PROGRAM_START, MEM_SIZE, MAX_MEM_SIZE, BIG_DIGIT_SPRITE_START, Memory, allocate_memory};
+use crate::backend::{self, Rng, Logger};
+use crate::error::{Chip8Error, Fault, ErrorPolicy};
+use std::cell::RefCell;
+use crate::trace::{Tracer, TraceEntry, Snapshot, DeviceProbe};
+use crate::quirks::{Quirks, LoadStore};
+use crate::savestate::{StateReader, StateWriter, StateError};
+use instruction::{Instruction, decode_at};
+use decode_cache::DecodeCache;
+use block::BlockCache;
+pub use block::Engine;
+pub(crate) use block::ends_block;
+pub use timing::{TimingMode, VIP_CYCLES_PER_SECOND, VIP_FRAME_OVERHEAD};
+pub(crate) use timing::{vip_cost, SKIP_CYCLES, BCD_DIGIT_CYCLES};
+use crate::iodevice::{Display, Keypad, Audio, NO_KEY, PLANE_1, PLANE_2, DISPLAY_WIDTH, DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT};
 
 const STACK_SIZE : usize = 64;
 const REGISTER_SIZE : usize = 16;
+const RPL_SIZE : usize = 16;
+
+pub const AUDIO_PATTERN_SIZE : usize = 16;
+pub const DEFAULT_PITCH : u8 = 64;
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
     memory: Memory,
+    decode_cache: DecodeCache,  // decoded instructions of memory
 
     register: [u8; REGISTER_SIZE],
     ir: usize,                  // index register
@@ -19,18 +42,38 @@ pub struct CPU {
     dt: u8,                     // delay timer
     st: u8,                     // sound timer
 
+    rpl: [u8; RPL_SIZE],        // SCHIP RPL user flags, persists through reset
+
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],    // XO-CHIP 1-bit audio samples
+    audio_pattern_loaded: bool,
+    pitch: u8,                                  // XO-CHIP audio playback pitch
+
+    vblank: bool,               // set on each timer tick, cleared when drawing with display_wait quirk
+
+    instruction_pc: usize,      // address of the instruction being executed
+    error: Option<Chip8Error>,  // last runtime error, cleared on reset
+    error_policy: ErrorPolicy,  // how unknown opcodes & out of bounds accesses are handled
+    tracer: Option<Tracer>,     // execution trace, None when disabled
+
     // MODES:
-    quirk_shift: bool,
-    quirk_loadstore_reg: bool
+    quirks: Quirks,
+    timing: TimingMode,
+    cycles: u32,                // cost of the last executed instruction, in timing mode units
+    engine: Engine,
+    blocks: BlockCache,         // compiled blocks of the basic block engine
+
+    rng: Box<dyn Rng>,          // cxkk random source
+    logger: Box<dyn Logger>,    // runtime error messages
 }
 
 impl CPU {
     pub fn new() -> CPU {
         let register = [0; REGISTER_SIZE];
         let stack = [0; STACK_SIZE];
-        let memory = allocate_memory();
+        let memory = allocate_memory(MEM_SIZE);
 
         CPU {
+            decode_cache: DecodeCache::new(memory.len()),
             memory,
             register,
             ir: 0,
@@ -40,13 +83,35 @@ impl CPU {
             dt: 0,
             st: 0,
 
-            quirk_shift: false,
-            quirk_loadstore_reg: false,
+            rpl: [0; RPL_SIZE],
 
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            audio_pattern_loaded: false,
+            pitch: DEFAULT_PITCH,
+
+            vblank: false,
+
+            instruction_pc: PROGRAM_START,
+            error: None,
+            error_policy: ErrorPolicy::Strict,
+            tracer: None,
+
+            quirks: Quirks::new(),
+            timing: TimingMode::Fixed,
+            cycles: 1,
+            engine: Engine::Interpreter,
+            blocks: BlockCache::default(),
+
+            rng: backend::default_rng(),
+            logger: backend::default_logger(),
         }
     }
 
+    #[allow(clippy::let_and_return, clippy::ptr_offset_with_cast)]
     pub fn rom_ptr(&mut self) -> *mut u8 {
+        // memory is written through the pointer unseen
+        self.decode_cache.clear();
+
         let ptr = unsafe {
             self.memory.as_mut_ptr().offset(PROGRAM_START as isize)
         };
@@ -54,9 +119,120 @@ impl CPU {
         ptr
     }
 
-    pub fn set_quirks(&mut self, shift: bool, loadstore: bool) {
-        self.quirk_shift = shift;
-        self.quirk_loadstore_reg = loadstore;
+    // load_rom copies rom to PROGRAM_START, returns false if it does not fit in memory
+    pub fn load_rom(&mut self, rom: &[u8]) -> bool {
+        if rom.len() > self.memory.len() - PROGRAM_START {
+            return false;
+        }
+
+        self.memory[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+        self.decode_cache.clear();
+        true
+    }
+
+    // set_memory_size resizes memory, up to 64 KiB for XO-CHIP programs. rom_ptr must be requested again after resize
+    pub fn set_memory_size(&mut self, size: usize) {
+        if size != self.memory.len() {
+            self.memory = allocate_memory(size);
+            self.decode_cache.resize(self.memory.len());
+        }
+    }
+
+    pub fn memory_size(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        self.decode_cache.clear();
+        &mut self.memory
+    }
+
+    // set_decode_cache turns caching of decoded instructions on or off, on by default
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache.set_enabled(enabled);
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+        self.blocks = BlockCache::default();
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.error_policy = policy;
+    }
+
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.error_policy
+    }
+
+    pub fn set_timing(&mut self, timing: TimingMode) {
+        self.timing = timing;
+    }
+
+    pub fn timing(&self) -> TimingMode {
+        self.timing
+    }
+
+    // cycles returns the cost of the last executed instruction: 1 with fixed timing, machine cycles with VIP timing
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+
+    // adopt_host takes rng, logger, error policy, timing, tracer & engine settings from previous cpu: they are host
+    // settings, not machine state. compiled blocks are kept, they are checked against memory before running
+    pub fn adopt_host(&mut self, previous: CPU) {
+        self.rng = previous.rng;
+        self.logger = previous.logger;
+        self.error_policy = previous.error_policy;
+        self.timing = previous.timing;
+        self.tracer = previous.tracer;
+        self.decode_cache.set_enabled(previous.decode_cache.enabled());
+        self.engine = previous.engine;
+        self.blocks = previous.blocks;
+    }
+
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
+    pub fn set_rng(&mut self, rng: Box<dyn Rng>) {
+        self.rng = rng;
+    }
+
+    pub fn rng_state(&self) -> Option<u32> {
+        self.rng.state()
+    }
+
+    pub fn set_rng_state(&mut self, state: u32) {
+        self.rng.set_state(state);
+    }
+
+    pub fn set_logger(&mut self, logger: Box<dyn Logger>) {
+        self.logger = logger;
     }
 
     pub fn reset(&mut self) {
@@ -65,192 +241,560 @@ impl CPU {
         self.sp = 0;
         self.dt = 0;
         self.st = 0;
+        self.vblank = false;
+        self.error = None;
 
-        for i in 0..REGISTER_SIZE {
-            self.register[i] = 0;
-        }
+        self.register = [0; REGISTER_SIZE];
+
+        self.audio_pattern = [0; AUDIO_PATTERN_SIZE];
+        self.audio_pattern_loaded = false;
+        self.pitch = DEFAULT_PITCH;
+
+        // a rom is usually written to memory after reset
+        self.decode_cache.clear();
     }
 
-    pub fn update_timer(&mut self) {
+    // update_timer runs at display refresh rate, so it also marks vertical blank
+    pub fn update_timer(&mut self, audio: &mut impl Audio) {
+        self.vblank = true;
+
         if self.dt > 0 {
             self.dt -= 1;
         }
 
         if self.st > 0 {
             self.st -= 1;
+            audio.set_beeping(self.st > 0);
+        }
+    }
+
+    // sync_audio sets audio to the current sound state, e.g. after loading a state
+    pub fn sync_audio(&self, audio: &mut impl Audio) {
+        audio.set_beeping(self.st > 0);
+        audio.set_pitch(self.pitch);
+
+        if self.audio_pattern_loaded {
+            audio.set_pattern(&self.audio_pattern);
+        }
+    }
+
+    // save_state writes everything but quirks, which are saved by Machine
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_usize(self.memory.len());
+        writer.write_bytes(&self.memory);
+
+        writer.write_bytes(&self.register);
+        writer.write_usize(self.ir);
+        writer.write_usize(self.pc);
+
+        for &addr in self.stack.iter() {
+            writer.write_usize(addr);
+        }
+
+        writer.write_usize(self.sp);
+        writer.write_u8(self.dt);
+        writer.write_u8(self.st);
+
+        writer.write_bytes(&self.rpl);
+        writer.write_bytes(&self.audio_pattern);
+        writer.write_bool(self.audio_pattern_loaded);
+        writer.write_u8(self.pitch);
+        writer.write_bool(self.vblank);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let size = reader.read_usize()?;
+
+        if !(MEM_SIZE..=MAX_MEM_SIZE).contains(&size) {
+            return Err(StateError::InvalidData("memory size"));
+        }
+
+        self.memory = reader.read_bytes(size)?.to_vec();
+        self.decode_cache.resize(size);
+
+        reader.read_into(&mut self.register)?;
+        self.ir = reader.read_usize()?;
+        self.pc = reader.read_usize()?;
+
+        for addr in self.stack.iter_mut() {
+            *addr = reader.read_usize()?;
+        }
+
+        self.sp = reader.read_usize()?;
+
+        if self.sp > STACK_SIZE {
+            return Err(StateError::InvalidData("stack pointer"));
+        }
+
+        self.dt = reader.read_u8()?;
+        self.st = reader.read_u8()?;
+
+        // version 1 & 2 states had 8 RPL flags
+        let flags = if reader.version() >= 3 { RPL_SIZE } else { 8 };
+        reader.read_into(&mut self.rpl[..flags])?;
+        reader.read_into(&mut self.audio_pattern)?;
+        self.audio_pattern_loaded = reader.read_bool()?;
+        self.pitch = reader.read_u8()?;
+        self.vblank = reader.read_bool()?;
+
+        Ok(())
+    }
+
+    // error returns the cause of the last RuntimeError status
+    pub fn error(&self) -> Option<&Chip8Error> {
+        self.error.as_ref()
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn ir(&self) -> usize {
+        self.ir
+    }
+
+    pub fn register(&self, x: usize) -> u8 {
+        self.register[x]
+    }
+
+    // sp returns call stack depth
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    // state access for recompiled code, see aot::Core. callers check bounds & stack depth first
+
+    pub(crate) fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    pub(crate) fn set_ir(&mut self, ir: usize) {
+        self.ir = ir;
+    }
+
+    pub(crate) fn set_register(&mut self, x: usize, value: u8) {
+        self.register[x] = value;
+    }
+
+    pub(crate) fn dt(&self) -> u8 {
+        self.dt
+    }
+
+    pub(crate) fn set_dt(&mut self, dt: u8) {
+        self.dt = dt;
+    }
+
+    pub(crate) fn rpl(&self, x: usize) -> u8 {
+        self.rpl[x]
+    }
+
+    pub(crate) fn set_rpl(&mut self, x: usize, value: u8) {
+        self.rpl[x] = value;
+    }
+
+    // push returns false if the stack is full
+    pub(crate) fn push(&mut self, addr: usize) -> bool {
+        if self.sp == STACK_SIZE {
+            return false;
+        }
+
+        self.stack[self.sp] = addr;
+        self.sp += 1;
+        true
+    }
+
+    // pop returns None if the stack is empty
+    pub(crate) fn pop(&mut self) -> Option<usize> {
+        if self.sp == 0 {
+            return None;
         }
+
+        self.sp -= 1;
+        Some(self.stack[self.sp])
+    }
+
+    pub(crate) fn next_random(&mut self) -> u8 {
+        self.rng.next_u8()
+    }
+
+    // write_memory writes one byte, dropping its decoded instruction
+    pub(crate) fn write_memory(&mut self, addr: usize, value: u8) {
+        self.memory[addr] = value;
+        self.decode_cache.invalidate(addr);
     }
 
-    pub fn beeping(&self) -> bool {
-        self.st > 0
+    // next_instruction returns the instruction at pc, which is executed on the next tick
+    pub fn next_instruction(&self) -> Instruction {
+        decode_at(&self.memory, self.pc)
+    }
+
+    // memory_access returns the data memory range read or written by instruction, given current I register
+    // and selected planes. instruction fetch is not included
+    pub fn memory_access(&self, instruction: Instruction, planes: u8) -> Option<MemoryAccess> {
+        use Instruction::*;
+
+        let (write, len) = match instruction {
+            SaveRange(x, y) => (true, x.abs_diff(y) as usize + 1),
+            LoadRange(x, y) => (false, x.abs_diff(y) as usize + 1),
+            Draw(_, _, n) => {
+                let sprite_size = if n == 0 { 32 } else { n as usize };
+                (false, sprite_size * (planes & (PLANE_1 | PLANE_2)).count_ones() as usize)
+            },
+            Audio => (false, AUDIO_PATTERN_SIZE),
+            Bcd(_) => (true, 3),
+            Store(x) => (true, x as usize + 1),
+            LoadReg(x) => (false, x as usize + 1),
+            _ => return None,
+        };
+
+        Some(MemoryAccess { write, addr: self.ir, len })
     }
 
-    pub fn tick(&mut self, device: &mut impl IOInterface) -> ExecutionStatus {
+    // tick executes one instruction on display, keypad & audio devices
+    pub fn tick(&mut self, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) -> ExecutionStatus {
         // fetch
-        if self.pc > MEM_SIZE - 2 {
-            log!("Reaching end of rom");
+        if self.pc > self.memory.len() - 2 {
+            self.logger.log("Reaching end of rom");
             return ExecutionStatus::Halt;
         }
 
-        let high = self.memory[self.pc];
-        let low = self.memory[self.pc + 1];
-        self.pc += 2;
-
         // parse
-        let result = match get1(high, low) {
-            0x0 => match (high, low) {
-                // 00e0 clear display
-                (0, 0xE0) => self.op_00e0_cls(device),
+        let instruction = self.decode_cache.decode_at(&self.memory, self.pc);
+        self.exec_at(self.pc, instruction, display, keypad, audio)
+    }
 
-                // 00ee return
-                (0, 0xEE) => self.op_00ee_ret(),
+    // exec_at executes instruction decoded at pc, setting pc & cycles like tick
+    #[inline]
+    pub fn exec_at(&mut self, pc: usize, instruction: Instruction, display: &mut impl Display, keypad: &mut impl Keypad,
+        audio: &mut impl Audio) -> ExecutionStatus
+    {
+        self.instruction_pc = pc;
+        self.pc = pc + instruction.size();
+
+        let cycles = match self.timing {
+            TimingMode::Fixed => 1,
+            TimingMode::CosmacVip => self.vip_cycles(instruction),
+        };
 
-                // -- 0nnn syscall, ignored
-                _ => ExecutionStatus::OK
-            },
+        let status = if self.tracer.as_ref().is_some_and(|tracer| tracer.matches(pc, instruction)) {
+            self.execute_traced(instruction, display, keypad, audio)
+        } else {
+            self.execute(instruction, display, keypad, audio)
+        };
+
+        self.cycles = match self.timing {
+            TimingMode::Fixed => cycles,
+            TimingMode::CosmacVip => cycles + self.skip_cycles(instruction),
+        };
+
+        status
+    }
+
+    // execute_traced executes instruction through device probes, and records its changes & effects
+    fn execute_traced(&mut self, instruction: Instruction, display: &mut impl Display, keypad: &mut impl Keypad,
+        audio: &mut impl Audio) -> ExecutionStatus
+    {
+        let before = self.snapshot();
+        let effects = RefCell::new(Vec::new());
+
+        let status = self.execute(instruction, &mut DeviceProbe::new(display, &effects),
+            &mut DeviceProbe::new(keypad, &effects), &mut DeviceProbe::new(audio, &effects));
+
+        let entry = TraceEntry {
+            pc: self.instruction_pc,
+            opcode: instruction.encode(),
+            instruction,
+            status,
+            changes: before.changes(&self.snapshot()),
+            effects: effects.into_inner(),
+        };
+
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.record(entry);
+        }
+
+        status
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot { registers: self.register, ir: self.ir, dt: self.dt, st: self.st }
+    }
+
+    fn execute(&mut self, instruction: Instruction, display: &mut impl Display, keypad: &mut impl Keypad,
+        audio: &mut impl Audio) -> ExecutionStatus
+    {
+        use Instruction::*;
+
+        match instruction {
+            // 00cn scroll down n lines
+            ScrollDown(n) => self.op_00cn_scrolldown(n, display),
+
+            // 00dn scroll up n lines
+            ScrollUp(n) => self.op_00dn_scrollup(n, display),
+
+            // 00e0 clear display
+            Cls => self.op_00e0_cls(display),
+
+            // 00ee return
+            Ret => self.op_00ee_ret(),
+
+            // 00fb scroll right 4 pixels
+            ScrollRight => self.op_00fb_scrollright(display),
+
+            // 00fc scroll left 4 pixels
+            ScrollLeft => self.op_00fc_scrollleft(display),
+
+            // 00fd exit interpreter
+            Exit => self.op_00fd_exit(),
+
+            // 00fe disable high resolution mode
+            LowRes => self.op_00fe_lowres(display),
+
+            // 00ff enable high resolution mode
+            HighRes => self.op_00ff_highres(display),
+
+            // -- 0nnn syscall, ignored
+            Sys(_) => ExecutionStatus::OK,
 
             // 1nnn jump
-            0x1 => self.op_1nnn_jump(high, low),
+            Jump(addr) => self.op_1nnn_jump(addr as usize),
 
             // 2nnn call
-            0x2 => self.op_2nnn_call(high, low),
+            Call(addr) => self.op_2nnn_call(addr as usize),
 
             // 3xkk skip eq Vx, byte
-            0x3 => self.op_3xkk_skipeq(high, low),
+            SkipEq(x, kk) => self.op_3xkk_skipeq(x as usize, kk),
 
             // 4xkk skip neq Vx, byte
-            0x4 => self.op_4xkk_skipneq(high, low),
+            SkipNeq(x, kk) => self.op_4xkk_skipneq(x as usize, kk),
 
             // 5xy0 skip eq Vx, Vy
-            0x5 => self.op_5xy0_skipeqv(high, low),
+            SkipEqV(x, y) => self.op_5xy0_skipeqv(x as usize, y as usize),
 
-            // 6xkk load Vx = byte
-            0x6 => self.op_6xkk_load(high, low),
+            // 5xy2 saverange M[I..] = [Vx..Vy]
+            SaveRange(x, y) => self.op_5xy2_saverange(x as usize, y as usize),
 
-            // 7xkk incr Vx += byte
-            0x7 => self.op_7xkk_incr(high, low),
+            // 5xy3 loadrange [Vx..Vy] = M[I..]
+            LoadRange(x, y) => self.op_5xy3_loadrange(x as usize, y as usize),
 
-            // binary operator instructions
-            0x8 => {
-                let x = get2(high, low) as usize;
-                let y = get3(high, low) as usize;
-
-                match get4(high, low) {
-                    // 8xy0 set Vx = Vy
-                    0x0 => self.op_8xy0_set(x, y),
+            // 6xkk load Vx = byte
+            Load(x, kk) => self.op_6xkk_load(x as usize, kk),
 
-                    // 8xy1 or Vx |=Vy
-                    0x1 => self.op_8xy1_or(x, y),
+            // 7xkk incr Vx += byte
+            Incr(x, kk) => self.op_7xkk_incr(x as usize, kk),
 
-                    // 8xy2 and Vx &= Vy
-                    0x2 => self.op_8xy2_and(x, y),
+            // 8xy0 set Vx = Vy
+            Set(x, y) => self.op_8xy0_set(x as usize, y as usize),
 
-                    // 8xy3 xor Vx ^= Vy
-                    0x3 => self.op_8xy3_xor(x, y),
+            // 8xy1 or Vx |=Vy
+            Or(x, y) => self.op_8xy1_or(x as usize, y as usize),
 
-	                // 8xy4 add Vx = Vx + Vy, VF = carry
-                    0x4 => self.op_8xy4_add(x, y),
+            // 8xy2 and Vx &= Vy
+            And(x, y) => self.op_8xy2_and(x as usize, y as usize),
 
-                    // 8xy5 sub Vx = Vx - Vy, VF = not borrow (Vx >= Vy)
-                    0x5 => self.op_8xy5_sub(x, y),
+            // 8xy3 xor Vx ^= Vy
+            Xor(x, y) => self.op_8xy3_xor(x as usize, y as usize),
 
-	                // 8xy6 shr Vx = Vx >> 1. VF = last bit
-                    0x6 => self.op_8xy6_shr(x, y),
+            // 8xy4 add Vx = Vx + Vy, VF = carry
+            Add(x, y) => self.op_8xy4_add(x as usize, y as usize),
 
-                    // 8xy7 subn Vx = Vy - Vx, VF = not borrow (Vy >= Vx)
-                    0x7 => self.op_8xy7_subn(x, y),
+            // 8xy5 sub Vx = Vx - Vy, VF = not borrow (Vx >= Vy)
+            Sub(x, y) => self.op_8xy5_sub(x as usize, y as usize),
 
-                    // 8xyE shl Vx = Vx << 1. VF = first bit
-                    0xE => self.op_8xye_shl(x, y),
+            // 8xy6 shr Vx = Vx >> 1. VF = last bit
+            Shr(x, y) => self.op_8xy6_shr(x as usize, y as usize),
 
+            // 8xy7 subn Vx = Vy - Vx, VF = not borrow (Vy >= Vx)
+            SubN(x, y) => self.op_8xy7_subn(x as usize, y as usize),
 
-                    _ => unknown_opcode(high, low)
-                }
-            }
+            // 8xyE shl Vx = Vx << 1. VF = first bit
+            Shl(x, y) => self.op_8xye_shl(x as usize, y as usize),
 
             // 9xy0 skip ne v Vx != Vy
-            0x9 => self.op_9xy0_skipnev(high, low),
+            SkipNeqV(x, y) => self.op_9xy0_skipnev(x as usize, y as usize),
 
             // annn loadi I = annn
-            0xA => self.op_annn_loadi(high, low),
+            LoadI(addr) => self.op_annn_loadi(addr as usize),
 
             // bnnn jumpv v0 + nnn
-            0xB => self.op_bnnn_jumpv(high, low),
+            JumpV(addr) => self.op_bnnn_jumpv(addr as usize),
 
             // cxkk rand Vx = rand() & byte
-            0xC => self.op_cxkk_rand(high, low),
+            Rand(x, kk) => self.op_cxkk_rand(x as usize, kk),
 
-            // dxyn - draw Vx, Vy, nibble
-            0xD => self.op_dxyn_draw(high, low, device),
+            // dxyn - draw Vx, Vy, nibble. dxy0 draws 16x16 sprite
+            Draw(x, y, n) => self.op_dxyn_draw(x as usize, y as usize, n, display),
 
-            0xE => {
-                let x = get2(high, low) as usize;
+            // Ex9E - SKP Vx
+            SkipKey(x) => self.op_ex9e_skey(x as usize, keypad),
 
-                match low {
-                    // Ex9E - SKP Vx
-                    0x9E => self.op_ex9e_skey(x, device),
-                    // ExA1 - SKNP Vx
-                    0xA1 => self.op_exa1_snkey(x, device),
-                    _ => unknown_opcode(high, low)
-                }
-            },
+            // ExA1 - SKNP Vx
+            SkipNotKey(x) => self.op_exa1_snkey(x as usize, keypad),
 
-            0xF => {
-                let x = get2(high, low) as usize;
+            // f000 nnnn loadlong I = nnnn
+            LoadLong(addr) => self.op_f000_loadlong(addr as usize),
 
-                match low {
-                    // fx07 readdt Vx = DT
-                    0x07 => self.op_fx07_readdt(x),
+            // fn01 plane select plane n
+            Plane(n) => self.op_fn01_plane(n as usize, display),
 
-                    // fx0a waitkey LD Vx, K
-                    0x0A => self.op_fx0a_waitkey(x, device),
+            // f002 audio pattern = M[I..I+16]
+            Audio => self.op_f002_audio(audio),
 
-                    // fx15 loaddt DT = Vx
-                    0x15 => self.op_fx15_loaddt(x),
+            // fx07 readdt Vx = DT
+            ReadDt(x) => self.op_fx07_readdt(x as usize),
 
-                    // Fx18 load st ST = Vx
-                    0x18 => self.op_fx18_loadst(x),
+            // fx0a waitkey LD Vx, K
+            WaitKey(x) => self.op_fx0a_waitkey(x as usize, keypad),
 
-                    // fx1e addi I += Vx
-                    0x1E => self.op_fx1e_addi(x),
+            // fx15 loaddt DT = Vx
+            LoadDt(x) => self.op_fx15_loaddt(x as usize),
 
-                    // fx29 digit I = 5 * Vx
-                    0x29 => self.op_fx29_digit(x),
+            // Fx18 load st ST = Vx
+            LoadSt(x) => self.op_fx18_loadst(x as usize, audio),
 
-                    // fx33 bcd M[I..I+2] = bcd(Vx)
-                    0x33 => self.op_fx33_bcd(x),
+            // fx1e addi I += Vx
+            AddI(x) => self.op_fx1e_addi(x as usize),
 
-                    // fx55 storeg M[I..I+x] = [V0..Vx], I += x + 1
-                    0x55 => self.op_fx55_storeg(x),
+            // fx29 digit I = 5 * Vx
+            Digit(x) => self.op_fx29_digit(x as usize),
 
-                    // fx65 ldreg [V0..Vx] = M[I..I+x], I += x + 1
-                    0x65 =>self.op_fx65_ldreg(x),
+            // fx30 bigdigit I = big digit sprite of Vx
+            BigDigit(x) => self.op_fx30_bigdigit(x as usize),
 
-                    _ => unknown_opcode(high, low)
-                }
-            },
+            // fx33 bcd M[I..I+2] = bcd(Vx)
+            Bcd(x) => self.op_fx33_bcd(x as usize),
 
-            _ => {
-                unknown_opcode(high, low)
-            }
+            // fx3a pitch = Vx
+            Pitch(x) => self.op_fx3a_pitch(x as usize, audio),
+
+            // fx55 storeg M[I..I+x] = [V0..Vx], I += x + 1
+            Store(x) => self.op_fx55_storeg(x as usize),
+
+            // fx65 ldreg [V0..Vx] = M[I..I+x], I += x + 1
+            LoadReg(x) => self.op_fx65_ldreg(x as usize),
+
+            // fx75 saverpl RPL[0..x] = [V0..Vx]
+            SaveRpl(x) => self.op_fx75_saverpl(x as usize),
+
+            // fx85 loadrpl [V0..Vx] = RPL[0..x]
+            LoadRpl(x) => self.op_fx85_loadrpl(x as usize),
+
+            Unknown(_) => self.op_unknown(),
+        }
+    }
+
+    // fail records & logs error built from current instruction state, and stops execution
+    fn fail(&mut self, error: impl FnOnce(Fault) -> Chip8Error) -> ExecutionStatus {
+        let at = Fault {
+            pc: self.instruction_pc,
+            opcode: decode_at(&self.memory, self.instruction_pc).encode(),
+            registers: self.register,
+            ir: self.ir,
+            sp: self.sp,
         };
 
-        result
+        let error = error(at);
+        self.logger.log(&format!("Runtime Error: {}", error));
+        self.error = Some(error);
+
+        ExecutionStatus::RuntimeError
+    }
+
+    // check_access applies error policy to an access of M[addr..addr+len] outside memory.
+    // Err holds the status to return when the instruction must not be executed
+    fn check_access(&mut self, addr: usize, len: usize) -> Result<(), ExecutionStatus> {
+        if addr + len <= self.memory.len() {
+            return Ok(());
+        }
+
+        match self.error_policy {
+            ErrorPolicy::Strict => Err(self.fail(|at| Chip8Error::MemoryOutOfBounds { addr, len, at })),
+            ErrorPolicy::Lenient => Err(ExecutionStatus::OK),
+            ErrorPolicy::Wrap => Ok(()),
+        }
+    }
+
+    // check_operand applies error policy to an operand value outside 0..limit. Ok holds the operand to use,
+    // `wrapped` with the Wrap policy. Err holds the status to return when the instruction must not be executed
+    fn check_operand(&mut self, operand: &'static str, value: usize, limit: usize, wrapped: usize)
+        -> Result<usize, ExecutionStatus>
+    {
+        if value < limit {
+            return Ok(value);
+        }
+
+        match self.error_policy {
+            ErrorPolicy::Strict => Err(self.fail(|at| Chip8Error::InvalidOperand { operand, value, at })),
+            ErrorPolicy::Lenient => Err(ExecutionStatus::OK),
+            ErrorPolicy::Wrap => Ok(wrapped),
+        }
+    }
+
+    // wrap masks addr to memory size, accesses must use it so the Wrap policy can go past the end
+    fn wrap(&self, addr: usize) -> usize {
+        addr % self.memory.len()
+    }
+
+    // unknown opcode is skipped unless policy is strict
+    fn op_unknown(&mut self) -> ExecutionStatus {
+        match self.error_policy {
+            ErrorPolicy::Strict => self.fail(|at| Chip8Error::UnknownOpcode { at }),
+            ErrorPolicy::Lenient | ErrorPolicy::Wrap => ExecutionStatus::OK,
+        }
+    }
+
+    // skip_next skips the next instruction. f000 nnnn is 4 bytes long, so it is skipped entirely
+    pub(crate) fn skip_next(&mut self) {
+        if self.pc + 1 < self.memory.len() {
+            self.pc += decode_at(&self.memory, self.pc).size();
+        } else {
+            self.pc += 2;
+        }
+    }
+
+    // reset_vf resets VF after logical operations with vf_reset quirk
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.register[0xF] = 0;
+        }
+    }
+
+    // increment_loadstore updates I after fx55 & fx65 according to loadstore quirk
+    fn increment_loadstore(&mut self, x: usize) {
+        match self.quirks.loadstore {
+            LoadStore::IncrementXPlusOne => self.ir += x + 1,
+            LoadStore::IncrementX => self.ir += x,
+            LoadStore::Unchanged => (),
+        }
     }
 
     // OPCODES
 
+    // 00Cn scroll down n lines
+    fn op_00cn_scrolldown(&mut self, n: u8, display: &mut impl Display) -> ExecutionStatus {
+        display.scroll_down(n);
+
+        ExecutionStatus::OK
+    }
+
+    // 00Dn scroll up n lines
+    fn op_00dn_scrollup(&mut self, n: u8, display: &mut impl Display) -> ExecutionStatus {
+        display.scroll_up(n);
+
+        ExecutionStatus::OK
+    }
+
     // 00E0 clear screen
-    fn op_00e0_cls(&mut self, device: &mut impl IOInterface) -> ExecutionStatus {
-        device.clear_display();
+    fn op_00e0_cls(&mut self, display: &mut impl Display) -> ExecutionStatus {
+        display.clear_display();
 
         ExecutionStatus::OK
     }
 
     // 00EE return
     fn op_00ee_ret(&mut self) -> ExecutionStatus {
-        if self.sp == 0{
-            return runtime_error("Stack underflow");
+        if self.sp == 0 {
+            return self.fail(|at| Chip8Error::StackUnderflow { at });
         }
 
         self.sp -= 1;
@@ -260,10 +804,41 @@ impl CPU {
         ExecutionStatus::OK
     }
 
-    // 1nnn jump
-    fn op_1nnn_jump(&mut self, high: u8, low: u8) -> ExecutionStatus {
-        let addr = get_nnn(high, low) as usize;
+    // 00FB scroll right 4 pixels
+    fn op_00fb_scrollright(&mut self, display: &mut impl Display) -> ExecutionStatus {
+        display.scroll_right();
+
+        ExecutionStatus::OK
+    }
 
+    // 00FC scroll left 4 pixels
+    fn op_00fc_scrollleft(&mut self, display: &mut impl Display) -> ExecutionStatus {
+        display.scroll_left();
+
+        ExecutionStatus::OK
+    }
+
+    // 00FD exit interpreter
+    fn op_00fd_exit(&mut self) -> ExecutionStatus {
+        ExecutionStatus::Halt
+    }
+
+    // 00FE disable high resolution mode
+    fn op_00fe_lowres(&mut self, display: &mut impl Display) -> ExecutionStatus {
+        display.set_high_res(false);
+
+        ExecutionStatus::OK
+    }
+
+    // 00FF enable high resolution mode
+    fn op_00ff_highres(&mut self, display: &mut impl Display) -> ExecutionStatus {
+        display.set_high_res(true);
+
+        ExecutionStatus::OK
+    }
+
+    // 1nnn jump
+    fn op_1nnn_jump(&mut self, addr: usize) -> ExecutionStatus {
         // handle trap jump: prev_pc (current_pc - 2) = addr
         if self.pc == addr + 2 {
             return ExecutionStatus::Halt;
@@ -275,71 +850,89 @@ impl CPU {
     }
 
     // 2nnn call
-    fn op_2nnn_call(&mut self, high: u8, low: u8) -> ExecutionStatus {
+    fn op_2nnn_call(&mut self, addr: usize) -> ExecutionStatus {
         if self.sp == STACK_SIZE {
-            return runtime_error("Stack overflow");
+            return self.fail(|at| Chip8Error::StackOverflow { at });
         }
 
         self.stack[self.sp] = self.pc;
         self.sp += 1;
 
-        let addr = get_nnn(high, low) as usize;
         self.pc = addr;
 
         ExecutionStatus::OK
     }
 
     // 3xkk skip eq Vx, byte
-    fn op_3xkk_skipeq(&mut self, high: u8, low: u8) -> ExecutionStatus {
-        let x = get2(high, low) as usize;
-        let kk = get_kk(high, low);
-
+    fn op_3xkk_skipeq(&mut self, x: usize, kk: u8) -> ExecutionStatus {
         if self.register[x] == kk {
-            self.pc += 2;
+            self.skip_next();
         }
 
         ExecutionStatus::OK
     }
 
     // 4xkk skip neq Vx, byte
-    fn op_4xkk_skipneq(&mut self, high: u8, low: u8) -> ExecutionStatus {
-        let x = get2(high, low) as usize;
-        let kk = get_kk(high, low);
-
+    fn op_4xkk_skipneq(&mut self, x: usize, kk: u8) -> ExecutionStatus {
         if self.register[x] != kk {
-            self.pc += 2;
+            self.skip_next();
         }
 
         ExecutionStatus::OK
     }
 
     // 5xy0 skip eq Vx, Vy
-    fn op_5xy0_skipeqv(&mut self, high: u8, low: u8) -> ExecutionStatus {
-        let x = get2(high, low) as usize;
-        let y = get3(high, low) as usize;
-
+    fn op_5xy0_skipeqv(&mut self, x: usize, y: usize) -> ExecutionStatus {
         if self.register[x] == self.register[y] {
-            self.pc += 2;
+            self.skip_next();
         }
 
         ExecutionStatus::OK
     }
 
-    // 6xkk load Vx = byte
-    fn op_6xkk_load(&mut self, high: u8, low: u8) -> ExecutionStatus {
-        let x = get2(high, low) as usize;
-        let kk = get_kk(high, low);
+    // 5xy2 saverange M[I..] = [Vx..Vy], in reverse order if x > y
+    fn op_5xy2_saverange(&mut self, x: usize, y: usize) -> ExecutionStatus {
+        let count = x.abs_diff(y) + 1;
 
+        if let Err(status) = self.check_access(self.ir, count) {
+            return status;
+        }
+
+        for i in 0..count {
+            let reg = if x <= y { x + i } else { x - i };
+            let addr = self.wrap(self.ir + i);
+            self.memory[addr] = self.register[reg];
+            self.decode_cache.invalidate(addr);
+        }
+
+        ExecutionStatus::OK
+    }
+
+    // 5xy3 loadrange [Vx..Vy] = M[I..], in reverse order if x > y
+    fn op_5xy3_loadrange(&mut self, x: usize, y: usize) -> ExecutionStatus {
+        let count = x.abs_diff(y) + 1;
+
+        if let Err(status) = self.check_access(self.ir, count) {
+            return status;
+        }
+
+        for i in 0..count {
+            let reg = if x <= y { x + i } else { x - i };
+            self.register[reg] = self.memory[self.wrap(self.ir + i)];
+        }
+
+        ExecutionStatus::OK
+    }
+
+    // 6xkk load Vx = byte
+    fn op_6xkk_load(&mut self, x: usize, kk: u8) -> ExecutionStatus {
         self.register[x] = kk;
 
         ExecutionStatus::OK
     }
 
     // 7xkk incr Vx += byte
-    fn op_7xkk_incr(&mut self, high: u8, low: u8) -> ExecutionStatus {
-        let x = get2(high, low) as usize;
-        let kk = get_kk(high, low);
-
+    fn op_7xkk_incr(&mut self, x: usize, kk: u8) -> ExecutionStatus {
         self.register[x] += kk;
 
         ExecutionStatus::OK
@@ -354,18 +947,21 @@ impl CPU {
     // 8xy1 or Vx |=Vy
     fn op_8xy1_or(&mut self, x: usize, y: usize) -> ExecutionStatus {
         self.register[x] |= self.register[y];
+        self.reset_vf();
         ExecutionStatus::OK
     }
 
     // 8xy2 and Vx &= Vy
     fn op_8xy2_and(&mut self, x: usize, y: usize) -> ExecutionStatus {
         self.register[x] &= self.register[y];
+        self.reset_vf();
         ExecutionStatus::OK
     }
 
     // 8xy3 xor Vx ^= Vy
     fn op_8xy3_xor(&mut self, x: usize, y: usize) -> ExecutionStatus {
         self.register[x] ^= self.register[y];
+        self.reset_vf();
         ExecutionStatus::OK
     }
 
@@ -391,7 +987,7 @@ impl CPU {
 
     // 8xy6 shr Vx = Vx >> 1. VF = last bit
     fn op_8xy6_shr(&mut self, x: usize, y: usize) -> ExecutionStatus {
-        let reg = if self.quirk_shift {
+        let reg = if self.quirks.shift_vx {
             x
         } else {
             y
@@ -416,7 +1012,7 @@ impl CPU {
 
     // 8xyE shl Vx = Vx << 1. VF = first bit
     fn op_8xye_shl(&mut self, x: usize, y: usize) -> ExecutionStatus {
-        let reg = if self.quirk_shift {
+        let reg = if self.quirks.shift_vx {
             x
         } else {
             y
@@ -431,70 +1027,104 @@ impl CPU {
     }
 
     // 9xy0 skip ne v Vx != Vy
-    fn op_9xy0_skipnev(&mut self, high: u8, low: u8) -> ExecutionStatus {
-        let x = get2(high, low) as usize;
-        let y = get3(high, low) as usize;
-
+    fn op_9xy0_skipnev(&mut self, x: usize, y: usize) -> ExecutionStatus {
         if self.register[x] != self.register[y] {
-            self.pc += 2;
+            self.skip_next();
         }
 
         ExecutionStatus::OK
     }
 
     // annn loadi I = annn
-    fn op_annn_loadi(&mut self, high: u8, low: u8) -> ExecutionStatus {
-        self.ir = get_nnn(high, low) as usize;
+    fn op_annn_loadi(&mut self, addr: usize) -> ExecutionStatus {
+        self.ir = addr;
 
         ExecutionStatus::OK
     }
 
-    // bnnn jumpv v0 + nnn
-    fn op_bnnn_jumpv(&mut self, high: u8, low: u8) -> ExecutionStatus {
-        let addr = self.register[0] as usize + get_nnn(high, low) as usize;
+    // bnnn jumpv v0 + nnn, or vx + xnn with jump_vx quirk
+    fn op_bnnn_jumpv(&mut self, nnn: usize) -> ExecutionStatus {
+        let reg = if self.quirks.jump_vx {
+            nnn >> 8
+        } else {
+            0
+        };
 
-        self.pc = addr;
+        let mut target = self.register[reg] as usize + nnn;
+
+        if target > self.memory.len() - 2 {
+            match self.error_policy {
+                ErrorPolicy::Strict => return self.fail(|at| Chip8Error::PcOutOfRange { target, at }),
+                ErrorPolicy::Lenient => return ExecutionStatus::OK,
+                ErrorPolicy::Wrap => target = self.wrap(target),
+            }
+        }
+
+        self.pc = target;
 
         ExecutionStatus::OK
     }
 
     // cxkk rand Vx = rand() & byte
-    fn op_cxkk_rand(&mut self, high: u8, low: u8) -> ExecutionStatus {
-        let x = get2(high, low) as usize;
-        let kk = get_kk(high, low);
-
-        self.register[x] = utils::random() & kk;
+    fn op_cxkk_rand(&mut self, x: usize, kk: u8) -> ExecutionStatus {
+        self.register[x] = self.rng.next_u8() & kk;
 
         ExecutionStatus::OK
     }
 
-    // dxyn draw vx, vy, n
-    fn op_dxyn_draw(&mut self, high: u8, low: u8, device: &mut impl IOInterface) -> ExecutionStatus {
-        let vx = get2(high, low) as usize;
-        let vy = get3(high, low) as usize;
-        let x_start = self.register[vx] % 64;
-        let y_start = self.register[vy] % 32;
+    // dxyn draw vx, vy, n. n = 0 draws 16x16 sprite
+    fn op_dxyn_draw(&mut self, vx: usize, vy: usize, n: u8, display: &mut impl Display) -> ExecutionStatus {
+        // the VIP interpreter always waits for the display interrupt before drawing, SCHIP only in low resolution
+        if (self.quirks.display_wait && !display.is_high_res()) || self.timing == TimingMode::CosmacVip {
+            if !self.vblank {
+                self.pc -= 2;
+                return ExecutionStatus::WaitForVBlank;
+            }
+
+            self.vblank = false;
+        }
+
+        let (width, height) = if display.is_high_res() {
+            (HIRES_DISPLAY_WIDTH as u8, HIRES_DISPLAY_HEIGHT as u8)
+        } else {
+            (DISPLAY_WIDTH as u8, DISPLAY_HEIGHT as u8)
+        };
+
+        let x_start = self.register[vx] % width;
+        let y_start = self.register[vy] % height;
+
+        let (rows, cols) = if n == 0 { (16, 16) } else { (n, 8) };
+        let sprite_size = rows as usize * cols as usize / 8;
 
         let mut vf = 0;
 
-        let n = get4(high, low);
+        // each selected plane is drawn with consecutive sprite data, starting from plane 1
+        let planes = display.selected_planes();
+        let mut addr = self.ir;
 
+        if let Err(status) = self.check_access(addr, sprite_size * planes.count_ones() as usize) {
+            return status;
+        }
 
-        for dy in 0..n {
-            let row = self.memory[self.ir + dy as usize];
-            let mut mask = 0x80;
+        for &plane in [PLANE_1, PLANE_2].iter() {
+            if planes & plane == 0 {
+                continue;
+            }
 
-            for dx in 0..8 {
-                if row & mask != 0 {
-                    // draw
-                    let erased = device.draw_pixel(x_start + dx, y_start + dy);
+            // 16 pixel wide sprites are drawn as left & right 8 pixel columns
+            let columns = cols as usize / 8;
+            let mut column = [0; 16];
 
-                    // update vf check erased
-                    vf |= erased;
+            for c in 0..columns {
+                for (dy, row) in column.iter_mut().enumerate().take(rows as usize) {
+                    *row = self.memory[self.wrap(addr + dy * columns + c)];
                 }
 
-                mask >>= 1;
+                let erased = display.draw_sprite(x_start + 8 * c as u8, y_start, &column[..rows as usize], plane);
+                vf |= erased as u8;
             }
+
+            addr += sprite_size;
         }
 
         self.register[0xF] = vf;
@@ -503,24 +1133,61 @@ impl CPU {
     }
 
     // ex9e skey skip key pressed Vx
-    fn op_ex9e_skey(&mut self, x: usize, device: &mut impl IOInterface) -> ExecutionStatus {
+    fn op_ex9e_skey(&mut self, x: usize, keypad: &mut impl Keypad) -> ExecutionStatus {
         let key = self.register[x];
 
-        if device.key_pressed(key) {
-            self.pc += 2;
+        if keypad.key_pressed(key) {
+            self.skip_next();
         }
 
         ExecutionStatus::OK
     }
 
     // exa1 snkey skip key not pressed Vx
-    fn op_exa1_snkey(&mut self, x: usize, device: &mut impl IOInterface) -> ExecutionStatus {
+    fn op_exa1_snkey(&mut self, x: usize, keypad: &mut impl Keypad) -> ExecutionStatus {
         let key = self.register[x];
 
-        if !device.key_pressed(key) {
-            self.pc += 2;
+        if !keypad.key_pressed(key) {
+            self.skip_next();
+        }
+
+        ExecutionStatus::OK
+    }
+
+    // f000 nnnn loadlong I = nnnn
+    fn op_f000_loadlong(&mut self, addr: usize) -> ExecutionStatus {
+        self.ir = addr;
+
+        ExecutionStatus::OK
+    }
+
+    // fn01 plane select plane n
+    fn op_fn01_plane(&mut self, n: usize, display: &mut impl Display) -> ExecutionStatus {
+        let n = match self.check_operand("plane", n, 4, n & 3) {
+            Ok(n) => n,
+            Err(status) => return status,
+        };
+
+        display.select_planes(n as u8);
+
+        ExecutionStatus::OK
+    }
+
+    // f002 audio pattern = M[I..I+16]
+    fn op_f002_audio(&mut self, audio: &mut impl Audio) -> ExecutionStatus {
+        let ir = self.ir;
+
+        if let Err(status) = self.check_access(ir, AUDIO_PATTERN_SIZE) {
+            return status;
+        }
+
+        for i in 0..AUDIO_PATTERN_SIZE {
+            self.audio_pattern[i] = self.memory[self.wrap(ir + i)];
         }
 
+        self.audio_pattern_loaded = true;
+        audio.set_pattern(&self.audio_pattern);
+
         ExecutionStatus::OK
     }
 
@@ -531,8 +1198,8 @@ impl CPU {
     }
 
     // fx0a waitkey LD Vx, K
-    fn op_fx0a_waitkey(&mut self, x: usize, device: &mut impl IOInterface) -> ExecutionStatus {
-        let key = device.read_any_key();
+    fn op_fx0a_waitkey(&mut self, x: usize, keypad: &mut impl Keypad) -> ExecutionStatus {
+        let key = keypad.read_any_key();
 
         if key == NO_KEY {
             self.pc -= 2;
@@ -550,14 +1217,21 @@ impl CPU {
     }
 
     // fx18 load st ST = Vx
-    fn op_fx18_loadst(&mut self, x: usize) -> ExecutionStatus {
+    fn op_fx18_loadst(&mut self, x: usize, audio: &mut impl Audio) -> ExecutionStatus {
         self.st = self.register[x];
+        audio.set_beeping(self.st > 0);
         ExecutionStatus::OK
     }
 
-    // fx1e addi I += Vx
+    // fx1e addi I += Vx, I must stay inside memory
     fn op_fx1e_addi(&mut self, x: usize) -> ExecutionStatus {
-        self.ir += self.register[x] as usize;
+        let ir = self.ir + self.register[x] as usize;
+
+        if let Err(status) = self.check_access(ir, 1) {
+            return status;
+        }
+
+        self.ir = self.wrap(ir);
         ExecutionStatus::OK
     }
 
@@ -567,18 +1241,48 @@ impl CPU {
         ExecutionStatus::OK
     }
 
+    // fx30 bigdigit I = big digit sprite of Vx
+    fn op_fx30_bigdigit(&mut self, x: usize) -> ExecutionStatus {
+        let digit = self.register[x] as usize;
+
+        let digit = match self.check_operand("big digit", digit, 10, digit % 10) {
+            Ok(digit) => digit,
+            Err(status) => return status,
+        };
+
+        self.ir = BIG_DIGIT_SPRITE_START + 10 * digit;
+        ExecutionStatus::OK
+    }
+
+    // fx3a pitch = Vx
+    fn op_fx3a_pitch(&mut self, x: usize, audio: &mut impl Audio) -> ExecutionStatus {
+        self.pitch = self.register[x];
+        audio.set_pitch(self.pitch);
+        ExecutionStatus::OK
+    }
+
     // fx33 bcd M[I..I+2] = bcd(Vx)
     fn op_fx33_bcd(&mut self, x: usize) -> ExecutionStatus {
         let ir = self.ir;
         let mut vx = self.register[x];
 
-        self.memory[ir + 2] = vx % 10;
-        vx = vx / 10;
+        if let Err(status) = self.check_access(ir, 3) {
+            return status;
+        }
+
+        let addr = [self.wrap(ir), self.wrap(ir + 1), self.wrap(ir + 2)];
+
+        self.memory[addr[2]] = vx % 10;
+        vx /= 10;
 
-        self.memory[ir + 1] = vx % 10;
-        vx = vx / 10;
+        self.memory[addr[1]] = vx % 10;
+        vx /= 10;
 
-        self.memory[ir] = vx; // vx is u8, so no need to modulo here
+        self.memory[addr[0]] = vx; // vx is u8, so no need to modulo here
+
+        for &addr in addr.iter() {
+            self.decode_cache.invalidate(addr);
+        }
 
         ExecutionStatus::OK
     }
@@ -587,20 +1291,17 @@ impl CPU {
     fn op_fx55_storeg(&mut self, x: usize) -> ExecutionStatus {
         let ir = self.ir;
 
-        if x > 15 || ir + x >= MEM_SIZE {
-            return runtime_error("Invalid memory access");
+        if let Err(status) = self.check_access(ir, x + 1) {
+            return status;
         }
 
-        unsafe {
-            let src = self.register.as_ptr();
-            let dest = self.memory.as_mut_ptr().offset(ir as isize);
-
-            std::ptr::copy(src, dest, x + 1);
+        for i in 0..=x {
+            let addr = self.wrap(ir + i);
+            self.memory[addr] = self.register[i];
+            self.decode_cache.invalidate(addr);
         }
 
-        if !self.quirk_loadstore_reg {
-            self.ir += x + 1;
-        }
+        self.increment_loadstore(x);
 
         ExecutionStatus::OK
     }
@@ -609,20 +1310,43 @@ impl CPU {
     fn op_fx65_ldreg(&mut self, x: usize) -> ExecutionStatus {
         let ir = self.ir;
 
-        if x > 15 || ir + x >= MEM_SIZE {
-            return runtime_error("Invalid memory access");
+        if let Err(status) = self.check_access(ir, x + 1) {
+            return status;
         }
 
-        unsafe {
-            let src = self.memory.as_ptr().offset(ir as isize);
-            let dest = self.register.as_mut_ptr();
-
-            std::ptr::copy(src, dest, x + 1);
+        for i in 0..=x {
+            self.register[i] = self.memory[self.wrap(ir + i)];
         }
 
-        if !self.quirk_loadstore_reg {
-            self.ir += x + 1;
-        }
+        self.increment_loadstore(x);
+
+        ExecutionStatus::OK
+    }
+
+    // fx75 saverpl RPL[0..x] = [V0..Vx]
+    fn op_fx75_saverpl(&mut self, x: usize) -> ExecutionStatus {
+        // with the Wrap policy, the available flags are copied
+        let flags = self.quirks.rpl_flags();
+        let count = match self.check_operand("RPL flag count", x + 1, flags + 1, flags) {
+            Ok(count) => count,
+            Err(status) => return status,
+        };
+
+        self.rpl[..count].copy_from_slice(&self.register[..count]);
+
+        ExecutionStatus::OK
+    }
+
+    // fx85 loadrpl [V0..Vx] = RPL[0..x]
+    fn op_fx85_loadrpl(&mut self, x: usize) -> ExecutionStatus {
+        // with the Wrap policy, the available flags are copied
+        let flags = self.quirks.rpl_flags();
+        let count = match self.check_operand("RPL flag count", x + 1, flags + 1, flags) {
+            Ok(count) => count,
+            Err(status) => return status,
+        };
+
+        self.register[..count].copy_from_slice(&self.rpl[..count]);
 
         ExecutionStatus::OK
     }
@@ -637,49 +1361,16 @@ pub enum ExecutionStatus {
     Halt,
     RuntimeError,
     WaitForKey,
+    WaitForVBlank,
+    Breakpoint,     // stopped by debugger before executing instruction at pc
 }
 
-fn runtime_error(_s : &str) -> ExecutionStatus {
-    log!("Runtime Error: {}", _s);
-    ExecutionStatus::RuntimeError
-}
-
-fn unknown_opcode(_high: u8, _low: u8) -> ExecutionStatus {
-    log!("Runtime Error: unknown opcode 0x{:02x}{:02x}", _high, _low);
-    ExecutionStatus::RuntimeError
-}
-
-#[inline]
-fn get1(high: u8, _low: u8) -> u8 {
-    (high >> 4) & 0x0F
-}
-
-#[inline]
-fn get2(high: u8, _low: u8) -> u8 {
-    high & 0x0F
-}
-
-#[inline]
-fn get3(_high: u8, low: u8) -> u8 {
-	(low >> 4) & 0x0F
-}
-
-#[inline]
-fn get4(_high: u8, low: u8) -> u8 {
-    low & 0x0F
-}
-
-#[inline]
-fn get_kk(_high: u8, low: u8) -> u8 {
-    low
-}
-
-#[inline]
-fn get_nnn(high: u8, low: u8) -> u16 {
-    let front = get2(high, low) as u16;
-    let back = get_kk(high, low) as u16;
-
-    (front << 8) | back
+// MemoryAccess is a data memory range accessed by an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub write: bool,
+    pub addr: usize,
+    pub len: usize,
 }
 
 // UNIT TEST MODULE
@@ -0,0 +1,202 @@
+/****
+ * Basic block engine: straight-line runs of instructions compiled once, then executed without fetch & decode
+ *
+ * Blocks are discovered as execution reaches them, starting from PROGRAM_START. A block ends after an
+ * instruction which may change the control flow or write memory, so its remaining instructions can't be stale.
+ * Each block keeps a copy of its code bytes, checked when entering it: a block whose code was rewritten, by the
+ * program itself or by the host, is dropped and the instruction is interpreted, then compiled again when reached.
+ *
+ * Instructions run through the same CPU::execute as the interpreter, costing the same budget units, so both
+ * engines give bit-for-bit identical runs.
+ */
+
+use super::{CPU, ExecutionStatus};
+use super::instruction::{Instruction, decode_at};
+use crate::iodevice::{Display, Keypad, Audio};
+
+const MAX_BLOCK_SIZE : usize = 64;     // instructions
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Engine {
+    Interpreter,    // fetch & decode every instruction
+    BasicBlock,     // run compiled basic blocks, see CPU::run
+}
+
+#[derive(Clone, Copy)]
+struct Op {
+    pc: usize,
+    instruction: Instruction,
+}
+
+struct Block {
+    code: Vec<u8>,      // memory the block was compiled from
+    ops: Vec<Op>,
+}
+
+// BlockCache holds compiled blocks by start address
+#[derive(Default)]
+pub struct BlockCache {
+    blocks: Vec<Option<Box<Block>>>,
+}
+
+impl BlockCache {
+    // block returns the compiled block at pc, None if its code was rewritten or there is no instruction at pc
+    fn block(&mut self, memory: &[u8], pc: usize) -> Option<&Block> {
+        if self.blocks.len() != memory.len() {
+            self.blocks.clear();
+            self.blocks.resize_with(memory.len(), || None);
+        }
+
+        let entry = self.blocks.get_mut(pc)?;
+
+        match entry {
+            Some(block) if memory[pc..].starts_with(&block.code) => {},
+
+            // rewritten code is interpreted this time
+            Some(_) => {
+                *entry = None;
+                return None;
+            },
+            None => *entry = compile(memory, pc),
+        }
+
+        entry.as_deref()
+    }
+}
+
+fn compile(memory: &[u8], start: usize) -> Option<Box<Block>> {
+    let mut ops = Vec::new();
+    let mut pc = start;
+
+    while ops.len() < MAX_BLOCK_SIZE && pc + 2 <= memory.len() {
+        let instruction = decode_at(memory, pc);
+        ops.push(Op { pc, instruction });
+        pc += instruction.size();
+
+        if ends_block(instruction) {
+            break;
+        }
+    }
+
+    if ops.is_empty() {
+        return None;
+    }
+
+    Some(Box::new(Block { code: memory[start..pc].to_vec(), ops }))
+}
+
+// ends_block returns true if instructions after `instruction` may not run next, or may have been rewritten by it
+pub(crate) fn ends_block(instruction: Instruction) -> bool {
+    use Instruction::*;
+
+    matches!(instruction,
+        Ret | Exit | Sys(_) | Jump(_) | Call(_) | JumpV(_) | WaitKey(_) | Unknown(_) |
+        SkipEq(_, _) | SkipNeq(_, _) | SkipEqV(_, _) | SkipNeqV(_, _) | SkipKey(_) | SkipNotKey(_) |
+        SaveRange(_, _) | Bcd(_) | Store(_))
+}
+
+impl CPU {
+    // run executes instructions from pc until `budget` units are spent or an instruction stops with a status.
+    // returns the status of the last instruction and the units spent by instructions which completed
+    pub fn run(&mut self, budget: u32, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio)
+        -> (ExecutionStatus, u32)
+    {
+        if self.engine == Engine::Interpreter {
+            return self.step(display, keypad, audio);
+        }
+
+        let mut blocks = std::mem::take(&mut self.blocks);
+        let mut spent = 0;
+        let mut status = ExecutionStatus::OK;
+
+        // blocks are chained until the budget is spent
+        while status == ExecutionStatus::OK && spent < budget {
+            let (block_status, block_spent) = match blocks.block(&self.memory, self.pc) {
+                Some(block) => self.run_block(block, budget - spent, display, keypad, audio),
+                None => self.step(display, keypad, audio),
+            };
+
+            status = block_status;
+            spent += block_spent;
+        }
+
+        self.blocks = blocks;
+        (status, spent)
+    }
+
+    // step runs one instruction by tick, returning it like run
+    pub fn step(&mut self, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio)
+        -> (ExecutionStatus, u32)
+    {
+        match self.tick(display, keypad, audio) {
+            ExecutionStatus::OK => (ExecutionStatus::OK, self.cycles),
+            status => (status, 0),
+        }
+    }
+
+    // run_block runs instructions of block, skipping fetch & decode
+    fn run_block(&mut self, block: &Block, budget: u32, display: &mut impl Display, keypad: &mut impl Keypad,
+        audio: &mut impl Audio) -> (ExecutionStatus, u32)
+    {
+        let mut spent = 0;
+
+        for op in block.ops.iter() {
+            // left the block before its end, e.g. waiting for vblank
+            if spent >= budget || self.pc != op.pc {
+                break;
+            }
+
+            let status = self.exec_at(op.pc, op.instruction, display, keypad, audio);
+
+            if status != ExecutionStatus::OK {
+                return (status, spent);
+            }
+
+            spent += self.cycles;
+        }
+
+        (ExecutionStatus::OK, spent)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_compile() {
+        let mut memory = vec![
+            0x60, 0x01,     // LD V0, 1
+            0x70, 0x02,     // ADD V0, 2
+            0x30, 0x03,     // SE V0, 3
+            0x12, 0x00,     // JP 200
+            0xA0, 0x00,     // LD I, 0
+            0xF1, 0x55,     // LD [I], V1
+            0x00, 0xE0,     // CLS
+        ];
+
+        // ends after a skip, or after a memory write
+        let mut blocks = BlockCache::default();
+        let block = blocks.block(&memory, 0).unwrap();
+        assert_eq!(block.ops.len(), 3);
+        assert_eq!(block.ops[2].instruction, Instruction::SkipEq(0, 3));
+
+        let block = blocks.block(&memory, 8).unwrap();
+        assert_eq!(block.ops.len(), 2);
+        assert_eq!(block.code, vec![0xA0, 0x00, 0xF1, 0x55]);
+
+        // ends at the end of memory
+        assert_eq!(blocks.block(&memory, 12).unwrap().ops.len(), 1);
+        assert!(blocks.block(&memory, 13).is_none());
+
+        // rewritten block is dropped once, then compiled again
+        memory[3] = 0x05;
+        assert!(blocks.block(&memory, 0).is_none());
+        assert_eq!(blocks.block(&memory, 0).unwrap().ops[1].instruction, Instruction::Incr(0, 5));
+
+        // writes outside of the block keep it
+        memory[6] = 0x13;
+        assert!(blocks.block(&memory, 0).is_some());
+    }
+}
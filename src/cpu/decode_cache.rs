@@ -0,0 +1,151 @@
+/****
+ * Decode cache: instructions decoded once per address
+ *
+ * An entry is decoded on first run and kept until memory under it is written: fx33, fx55 & 5xy2 invalidate the
+ * entries of the bytes they write, host access through memory_mut or rom_ptr invalidates every entry.
+ * f000 nnnn is decoded every time, as its address operand lies outside the entry's two bytes.
+ */
+
+use super::instruction::{Instruction, decode, decode_at};
+
+#[derive(Clone, Copy)]
+struct Entry {
+    valid: bool,
+    instruction: Instruction,
+}
+
+pub struct DecodeCache {
+    entries: Vec<Entry>,        // one per memory address
+    enabled: bool,
+}
+
+impl DecodeCache {
+    pub fn new(size: usize) -> DecodeCache {
+        let mut cache = DecodeCache { entries: Vec::new(), enabled: true };
+        cache.resize(size);
+        cache
+    }
+
+    // resize drops all entries for a memory of `size` bytes
+    pub fn resize(&mut self, size: usize) {
+        let empty = Entry { valid: false, instruction: decode(0, 0) };
+
+        self.entries.clear();
+        self.entries.resize(size, empty);
+    }
+
+    // invalidate drops the entries of the instructions covering the byte at addr
+    #[inline]
+    pub fn invalidate(&mut self, addr: usize) {
+        self.entries[addr].valid = false;
+
+        if addr > 0 {
+            self.entries[addr - 1].valid = false;
+        }
+    }
+
+    // clear drops every entry
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            entry.valid = false;
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // decode_at returns the instruction at addr, decoding it only when not cached or invalidated since
+    pub fn decode_at(&mut self, memory: &[u8], addr: usize) -> Instruction {
+        if !self.enabled {
+            return decode_at(memory, addr);
+        }
+
+        let entry = &mut self.entries[addr];
+
+        if !entry.valid {
+            *entry = Entry { valid: true, instruction: decode(memory[addr], memory[addr + 1]) };
+        }
+
+        match entry.instruction {
+            Instruction::LoadLong(_) => decode_at(memory, addr),
+            instruction => instruction,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+    use crate::Machine;
+    use crate::assembler::assemble;
+
+    #[wasm_bindgen_test]
+    fn test_decode_cache() {
+        let mut memory = vec![0x60, 0x12, 0x00, 0xE0, 0xF0, 0x00, 0x12, 0x34];
+        let mut cache = DecodeCache::new(memory.len());
+
+        assert_eq!(cache.decode_at(&memory, 0), Instruction::Load(0, 0x12));
+        assert_eq!(cache.decode_at(&memory, 2), Instruction::Cls);
+
+        // memory changed without invalidation is not decoded again
+        memory[1] = 0x34;
+        assert_eq!(cache.decode_at(&memory, 0), Instruction::Load(0, 0x12));
+
+        // invalidated byte drops the instructions at it & before it
+        cache.invalidate(1);
+        memory[2] = 0x00;
+        memory[3] = 0xEE;
+        cache.invalidate(3);
+        assert_eq!(cache.decode_at(&memory, 0), Instruction::Load(0, 0x34));
+        assert_eq!(cache.decode_at(&memory, 2), Instruction::Ret);
+
+        memory[0] = 0x00;
+        memory[1] = 0xE0;
+        cache.clear();
+        assert_eq!(cache.decode_at(&memory, 0), Instruction::Cls);
+
+        // long load address is always read from memory
+        assert_eq!(cache.decode_at(&memory, 4), Instruction::LoadLong(0x1234));
+        memory[7] = 0x56;
+        assert_eq!(cache.decode_at(&memory, 4), Instruction::LoadLong(0x1256));
+
+        // each address is cached, also unaligned ones
+        assert_eq!(cache.decode_at(&memory, 1), decode(0xE0, 0x00));
+
+        cache.set_enabled(false);
+        memory[0] = 0x60;
+        assert_eq!(cache.decode_at(&memory, 0), Instruction::Load(0, 0xE0));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_self_modifying_program() {
+        // patch is run once, then rewritten by fx55 to load 2 into V3
+        let mut machine = Machine::new();
+        machine.load_rom(&assemble("
+            patch:
+                LD V3, 1
+                ADD V2, 1
+                SE V2, 1
+                JP end
+                LD V0, 0x63
+                LD V1, 0x02
+                LD I, patch
+                LD [I], V1
+                JP patch
+            end:
+                JP end
+        ").unwrap().rom);
+
+        machine.update();
+        machine.update();
+
+        assert_eq!(machine.get_register(2), 2);
+        assert_eq!(machine.get_register(3), 2);
+    }
+}
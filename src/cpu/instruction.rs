@@ -0,0 +1,350 @@
+/****
+ * Instruction decoding & encoding, shared by the interpreter, disassembler and assembler
+ */
+
+// Instruction is a decoded opcode with its operands extracted.
+// x & y are register indexes, kk is a byte, addr is an address, n is a nibble
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    ScrollDown(u8),         // 00cn
+    ScrollUp(u8),           // 00dn
+    Cls,                    // 00e0
+    Ret,                    // 00ee
+    ScrollRight,            // 00fb
+    ScrollLeft,             // 00fc
+    Exit,                   // 00fd
+    LowRes,                 // 00fe
+    HighRes,                // 00ff
+    Sys(u16),               // 0nnn
+    Jump(u16),              // 1nnn
+    Call(u16),              // 2nnn
+    SkipEq(u8, u8),         // 3xkk
+    SkipNeq(u8, u8),        // 4xkk
+    SkipEqV(u8, u8),        // 5xy0
+    SaveRange(u8, u8),      // 5xy2
+    LoadRange(u8, u8),      // 5xy3
+    Load(u8, u8),           // 6xkk
+    Incr(u8, u8),           // 7xkk
+    Set(u8, u8),            // 8xy0
+    Or(u8, u8),             // 8xy1
+    And(u8, u8),            // 8xy2
+    Xor(u8, u8),            // 8xy3
+    Add(u8, u8),            // 8xy4
+    Sub(u8, u8),            // 8xy5
+    Shr(u8, u8),            // 8xy6
+    SubN(u8, u8),           // 8xy7
+    Shl(u8, u8),            // 8xye
+    SkipNeqV(u8, u8),       // 9xy0
+    LoadI(u16),             // annn
+    JumpV(u16),             // bnnn
+    Rand(u8, u8),           // cxkk
+    Draw(u8, u8, u8),       // dxyn
+    SkipKey(u8),            // ex9e
+    SkipNotKey(u8),         // exa1
+    LoadLong(u16),          // f000 nnnn
+    Plane(u8),              // fn01
+    Audio,                  // f002
+    ReadDt(u8),             // fx07
+    WaitKey(u8),            // fx0a
+    LoadDt(u8),             // fx15
+    LoadSt(u8),             // fx18
+    AddI(u8),               // fx1e
+    Digit(u8),              // fx29
+    BigDigit(u8),           // fx30
+    Bcd(u8),                // fx33
+    Pitch(u8),              // fx3a
+    Store(u8),              // fx55
+    LoadReg(u8),            // fx65
+    SaveRpl(u8),            // fx75
+    LoadRpl(u8),            // fx85
+    Unknown(u16),
+}
+
+impl Instruction {
+    // size returns instruction length in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LoadLong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    // encode returns the opcode of instruction, the reverse of decode. f000 nnnn is returned as 4 bytes.
+    // operands are masked to their field width
+    pub fn encode(&self) -> u32 {
+        use Instruction::*;
+
+        let xy = |op: u16, x: u8, y: u8, n: u16| op | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | n;
+        let xkk = |op: u16, x: u8, kk: u8| op | (x as u16 & 0xF) << 8 | kk as u16;
+        let nnn = |op: u16, addr: u16| op | (addr & 0xFFF);
+
+        let opcode = match *self {
+            ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            LowRes => 0x00FE,
+            HighRes => 0x00FF,
+            Sys(addr) => nnn(0x0000, addr),
+            Jump(addr) => nnn(0x1000, addr),
+            Call(addr) => nnn(0x2000, addr),
+            SkipEq(x, kk) => xkk(0x3000, x, kk),
+            SkipNeq(x, kk) => xkk(0x4000, x, kk),
+            SkipEqV(x, y) => xy(0x5000, x, y, 0x0),
+            SaveRange(x, y) => xy(0x5000, x, y, 0x2),
+            LoadRange(x, y) => xy(0x5000, x, y, 0x3),
+            Load(x, kk) => xkk(0x6000, x, kk),
+            Incr(x, kk) => xkk(0x7000, x, kk),
+            Set(x, y) => xy(0x8000, x, y, 0x0),
+            Or(x, y) => xy(0x8000, x, y, 0x1),
+            And(x, y) => xy(0x8000, x, y, 0x2),
+            Xor(x, y) => xy(0x8000, x, y, 0x3),
+            Add(x, y) => xy(0x8000, x, y, 0x4),
+            Sub(x, y) => xy(0x8000, x, y, 0x5),
+            Shr(x, y) => xy(0x8000, x, y, 0x6),
+            SubN(x, y) => xy(0x8000, x, y, 0x7),
+            Shl(x, y) => xy(0x8000, x, y, 0xE),
+            SkipNeqV(x, y) => xy(0x9000, x, y, 0x0),
+            LoadI(addr) => nnn(0xA000, addr),
+            JumpV(addr) => nnn(0xB000, addr),
+            Rand(x, kk) => xkk(0xC000, x, kk),
+            Draw(x, y, n) => xy(0xD000, x, y, n as u16 & 0xF),
+            SkipKey(x) => xkk(0xE000, x, 0x9E),
+            SkipNotKey(x) => xkk(0xE000, x, 0xA1),
+            LoadLong(addr) => return 0xF000_0000 | addr as u32,
+            Plane(n) => xkk(0xF000, n, 0x01),
+            Audio => 0xF002,
+            ReadDt(x) => xkk(0xF000, x, 0x07),
+            WaitKey(x) => xkk(0xF000, x, 0x0A),
+            LoadDt(x) => xkk(0xF000, x, 0x15),
+            LoadSt(x) => xkk(0xF000, x, 0x18),
+            AddI(x) => xkk(0xF000, x, 0x1E),
+            Digit(x) => xkk(0xF000, x, 0x29),
+            BigDigit(x) => xkk(0xF000, x, 0x30),
+            Bcd(x) => xkk(0xF000, x, 0x33),
+            Pitch(x) => xkk(0xF000, x, 0x3A),
+            Store(x) => xkk(0xF000, x, 0x55),
+            LoadReg(x) => xkk(0xF000, x, 0x65),
+            SaveRpl(x) => xkk(0xF000, x, 0x75),
+            LoadRpl(x) => xkk(0xF000, x, 0x85),
+            Unknown(opcode) => opcode,
+        };
+
+        opcode as u32
+    }
+}
+
+// decode decodes a 2 bytes opcode. f000 is decoded as LoadLong(0), use decode_at to read its operand
+pub fn decode(high: u8, low: u8) -> Instruction {
+    use Instruction::*;
+
+    let x = get2(high, low);
+    let y = get3(high, low);
+    let n = get4(high, low);
+    let kk = get_kk(high, low);
+    let nnn = get_nnn(high, low);
+
+    match get1(high, low) {
+        0x0 => match (high, low) {
+            (0, 0xC0..=0xCF) => ScrollDown(n),
+            (0, 0xD0..=0xDF) => ScrollUp(n),
+            (0, 0xE0) => Cls,
+            (0, 0xEE) => Ret,
+            (0, 0xFB) => ScrollRight,
+            (0, 0xFC) => ScrollLeft,
+            (0, 0xFD) => Exit,
+            (0, 0xFE) => LowRes,
+            (0, 0xFF) => HighRes,
+            _ => Sys(nnn),
+        },
+
+        0x1 => Jump(nnn),
+        0x2 => Call(nnn),
+        0x3 => SkipEq(x, kk),
+        0x4 => SkipNeq(x, kk),
+
+        0x5 => match n {
+            0x0 => SkipEqV(x, y),
+            0x2 => SaveRange(x, y),
+            0x3 => LoadRange(x, y),
+            _ => Unknown(opcode(high, low)),
+        },
+
+        0x6 => Load(x, kk),
+        0x7 => Incr(x, kk),
+
+        0x8 => match n {
+            0x0 => Set(x, y),
+            0x1 => Or(x, y),
+            0x2 => And(x, y),
+            0x3 => Xor(x, y),
+            0x4 => Add(x, y),
+            0x5 => Sub(x, y),
+            0x6 => Shr(x, y),
+            0x7 => SubN(x, y),
+            0xE => Shl(x, y),
+            _ => Unknown(opcode(high, low)),
+        },
+
+        0x9 => SkipNeqV(x, y),
+        0xA => LoadI(nnn),
+        0xB => JumpV(nnn),
+        0xC => Rand(x, kk),
+        0xD => Draw(x, y, n),
+
+        0xE => match low {
+            0x9E => SkipKey(x),
+            0xA1 => SkipNotKey(x),
+            _ => Unknown(opcode(high, low)),
+        },
+
+        0xF => match low {
+            0x00 if x == 0 => LoadLong(0),
+            0x01 => Plane(x),
+            0x02 if x == 0 => Audio,
+            0x07 => ReadDt(x),
+            0x0A => WaitKey(x),
+            0x15 => LoadDt(x),
+            0x18 => LoadSt(x),
+            0x1E => AddI(x),
+            0x29 => Digit(x),
+            0x30 => BigDigit(x),
+            0x33 => Bcd(x),
+            0x3A => Pitch(x),
+            0x55 => Store(x),
+            0x65 => LoadReg(x),
+            0x75 => SaveRpl(x),
+            0x85 => LoadRpl(x),
+            _ => Unknown(opcode(high, low)),
+        },
+
+        _ => Unknown(opcode(high, low)),
+    }
+}
+
+// decode_at decodes instruction at `addr`, including the operand of f000 nnnn.
+// `addr + 1` must be inside memory
+pub fn decode_at(memory: &[u8], addr: usize) -> Instruction {
+    match decode(memory[addr], memory[addr + 1]) {
+        Instruction::LoadLong(_) => {
+            if addr + 3 >= memory.len() {
+                return Instruction::Unknown(0xF000);
+            }
+
+            Instruction::LoadLong(opcode(memory[addr + 2], memory[addr + 3]))
+        },
+
+        instruction => instruction,
+    }
+}
+
+#[inline]
+pub fn opcode(high: u8, low: u8) -> u16 {
+    (high as u16) << 8 | low as u16
+}
+
+#[inline]
+pub fn get1(high: u8, _low: u8) -> u8 {
+    (high >> 4) & 0x0F
+}
+
+#[inline]
+pub fn get2(high: u8, _low: u8) -> u8 {
+    high & 0x0F
+}
+
+#[inline]
+pub fn get3(_high: u8, low: u8) -> u8 {
+    (low >> 4) & 0x0F
+}
+
+#[inline]
+pub fn get4(_high: u8, low: u8) -> u8 {
+    low & 0x0F
+}
+
+#[inline]
+pub fn get_kk(_high: u8, low: u8) -> u8 {
+    low
+}
+
+#[inline]
+pub fn get_nnn(high: u8, low: u8) -> u16 {
+    let front = get2(high, low) as u16;
+    let back = get_kk(high, low) as u16;
+
+    (front << 8) | back
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+    use super::Instruction::*;
+
+    #[wasm_bindgen_test]
+    fn test_get_utils() {
+        let high = 0x1F;
+        let low = 0x2A;
+
+        assert_eq!(0x1, get1(high, low));
+        assert_eq!(0xF, get2(high, low));
+        assert_eq!(0x2, get3(high, low));
+        assert_eq!(0xA, get4(high, low));
+        assert_eq!(0x2A, get_kk(high, low));
+        assert_eq!(0xF2A, get_nnn(high, low));
+        assert_eq!(0x1F2A, opcode(high, low));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_decode() {
+        assert_eq!(decode(0x00, 0xE0), Cls);
+        assert_eq!(decode(0x00, 0xC4), ScrollDown(4));
+        assert_eq!(decode(0x01, 0x23), Sys(0x123));
+        assert_eq!(decode(0x3A, 0x12), SkipEq(0xA, 0x12));
+        assert_eq!(decode(0x5A, 0xB2), SaveRange(0xA, 0xB));
+        assert_eq!(decode(0x8A, 0xBE), Shl(0xA, 0xB));
+        assert_eq!(decode(0xD1, 0x2F), Draw(1, 2, 0xF));
+        assert_eq!(decode(0xF3, 0x01), Plane(3));
+
+        // unknown opcodes keep raw value
+        assert_eq!(decode(0x8A, 0xB9), Unknown(0x8AB9));
+        assert_eq!(decode(0xE1, 0x00), Unknown(0xE100));
+        assert_eq!(decode(0xF1, 0x02), Unknown(0xF102));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_encode() {
+        assert_eq!(Draw(1, 2, 0xF).encode(), 0xD12F);
+        assert_eq!(SkipNotKey(0xA).encode(), 0xEAA1);
+        assert_eq!(LoadLong(0xBEEF).encode(), 0xF000BEEF);
+
+        // every decoded 2 bytes opcode encodes back to an opcode with the same meaning.
+        // f000 is skipped since it needs its operand
+        for opcode in 0..=0xFFFFu16 {
+            let instruction = decode((opcode >> 8) as u8, opcode as u8);
+
+            if let LoadLong(_) = instruction {
+                continue;
+            }
+
+            let encoded = instruction.encode();
+            assert_eq!(decode((encoded >> 8) as u8, encoded as u8), instruction);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_decode_at() {
+        let memory = [0xF0, 0x00, 0x12, 0x34, 0xF0, 0x00];
+
+        let instruction = decode_at(&memory, 0);
+        assert_eq!(instruction, LoadLong(0x1234));
+        assert_eq!(instruction.size(), 4);
+
+        // truncated long instruction
+        assert_eq!(decode_at(&memory, 4), Unknown(0xF000));
+    }
+}
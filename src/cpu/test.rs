@@ -4,21 +4,18 @@
 
 use wasm_bindgen_test::*;
 use super::*;
-use crate::iodevice::IOInterface;
+use crate::iodevice::{Display, Keypad, AudioState, PLANE_1, PLANE_2};
+use crate::assembler::assemble;
+use crate::backend::{NullLogger, XorShiftRng};
 
-// TEST UTILS
+const MOCK_RANDOM : u8 = 0xBD;
 
-#[wasm_bindgen_test]
-fn test_get_utils() {
-    let high = 0x1F;
-    let low = 0x2A;
-
-    assert_eq!(0x1, get1(high, low));
-    assert_eq!(0xF, get2(high, low));
-    assert_eq!(0x2, get3(high, low));
-    assert_eq!(0xA, get4(high, low));
-    assert_eq!(0x2A, get_kk(high, low));
-    assert_eq!(0xF2A, get_nnn(high, low));
+struct MockRng;
+
+impl Rng for MockRng {
+    fn next_u8(&mut self) -> u8 {
+        MOCK_RANDOM
+    }
 }
 
 // TEST CPU PUBLIC METHOD
@@ -39,15 +36,68 @@ fn test_get_program_memory() {
 
 // TEST CPU INSTRUCTIONS
 
-struct MockDevice {}
+struct MockDevice {
+    high_res: bool,
+    planes: u8,
+    scrolled: (u8, u8, u8),     // (down, left, right) scroll counts
+    scrolled_up: u8,
+    drawn: Vec<(u8, u8)>,
+    drawn_planes: u8,
+}
 
-impl IOInterface for MockDevice {
+impl Display for MockDevice {
     fn clear_display(&mut self) {}
 
-    fn draw_pixel(&mut self, _x: u8, _y: u8) -> u8 {
+    fn draw_pixel(&mut self, x: u8, y: u8, plane: u8) -> u8 {
+        self.drawn.push((x, y));
+        self.drawn_planes |= plane;
         0
     }
 
+    fn draw_row(&mut self, x: u8, y: u8, row: u8, plane: u8) -> bool {
+        for dx in (0..8).filter(|dx| row & (0x80 >> dx) != 0) {
+            self.draw_pixel(x + dx, y, plane);
+        }
+
+        false
+    }
+
+    fn select_planes(&mut self, planes: u8) {
+        self.planes = planes;
+    }
+
+    fn selected_planes(&self) -> u8 {
+        self.planes
+    }
+
+    fn set_high_res(&mut self, enabled: bool) {
+        self.high_res = enabled;
+    }
+
+    fn is_high_res(&self) -> bool {
+        self.high_res
+    }
+
+    fn scroll_down(&mut self, n: u8) {
+        self.scrolled.0 += n;
+    }
+
+    fn scroll_up(&mut self, n: u8) {
+        self.scrolled_up += n;
+    }
+
+    fn scroll_left(&mut self) {
+        self.scrolled.1 += 1;
+    }
+
+    fn scroll_right(&mut self) {
+        self.scrolled.2 += 1;
+    }
+}
+
+struct MockKeypad;
+
+impl Keypad for MockKeypad {
     fn key_pressed(&self, _key: u8) -> bool {
         false
     }
@@ -60,11 +110,26 @@ impl IOInterface for MockDevice {
 struct CPUTester {
     cpu: CPU,
     device: MockDevice,
+    keypad: MockKeypad,
+    audio: AudioState,
 }
 
 impl CPUTester {
     pub fn new() -> CPUTester {
-        CPUTester { cpu: CPU::new(), device: MockDevice{} }
+        let device = MockDevice {
+            high_res: false,
+            planes: PLANE_1,
+            scrolled: (0, 0, 0),
+            scrolled_up: 0,
+            drawn: Vec::new(),
+            drawn_planes: 0,
+        };
+
+        let mut cpu = CPU::new();
+        cpu.set_rng(Box::new(MockRng));
+        cpu.set_logger(Box::new(NullLogger));
+
+        CPUTester { cpu, device, keypad: MockKeypad, audio: AudioState::new() }
     }
 
     fn reset(&mut self) {
@@ -78,13 +143,39 @@ impl CPUTester {
     }
 
     fn tick_cpu(&mut self) -> ExecutionStatus {
-        self.cpu.tick(&mut self.device)
+        self.cpu.tick(&mut self.device, &mut self.keypad, &mut self.audio)
+    }
+
+    fn tick_cpu_at(&mut self, pc: usize) -> ExecutionStatus {
+        self.cpu.pc = pc;
+        self.tick_cpu()
+    }
+
+    // load_asm resets cpu and loads the assembled program at PROGRAM_START
+    fn load_asm(&mut self, source: &str) {
+        let program = assemble(source).unwrap();
+
+        self.cpu.reset();
+        self.cpu.memory[PROGRAM_START..PROGRAM_START + program.rom.len()].copy_from_slice(&program.rom);
+    }
+
+    fn tick_cpu_n(&mut self, n: usize) -> ExecutionStatus {
+        for _ in 0..n {
+            let result = self.tick_cpu();
+
+            if result != ExecutionStatus::OK {
+                return result;
+            }
+        }
+
+        ExecutionStatus::OK
     }
 }
 
 // TODO: tests these: 00e0, dxyn, Ex9E, ExA1, fx0a, Fx18
 
 #[wasm_bindgen_test]
+#[allow(clippy::unnecessary_cast)]
 fn test_op_00ee_ret() {
     let mut tester = CPUTester::new();
 
@@ -103,9 +194,81 @@ fn test_op_00ee_ret() {
 
     // stack underflow case
     tester.reset();
+    tester.cpu.register[3] = 0x42;
 
     let result = tester.tick_cpu();
     assert_eq!(result, ExecutionStatus::RuntimeError);
+
+    match tester.cpu.error() {
+        Some(Chip8Error::StackUnderflow { at }) => {
+            assert_eq!((at.pc, at.opcode, at.sp), (PROGRAM_START, 0x00EE, 0));
+            assert_eq!(at.registers[3], 0x42);
+        },
+        error => panic!("unexpected error {:?}", error),
+    }
+
+    // error is cleared on reset
+    tester.reset();
+    assert_eq!(tester.cpu.error(), None);
+}
+
+#[wasm_bindgen_test]
+fn test_op_schip_display() {
+    let mut tester = CPUTester::new();
+
+    // 00cn scroll down
+    tester.set_ops(0x00, 0xC5);
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.device.scrolled, (5, 0, 0));
+
+    // 00dn scroll up
+    tester.set_ops(0x00, 0xD3);
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.device.scrolled_up, 3);
+
+    // 00fb scroll right
+    tester.set_ops(0x00, 0xFB);
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.device.scrolled, (5, 0, 1));
+
+    // 00fc scroll left
+    tester.set_ops(0x00, 0xFC);
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.device.scrolled, (5, 1, 1));
+
+    // 00ff high res
+    tester.set_ops(0x00, 0xFF);
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert!(tester.device.high_res);
+
+    // 00fe low res
+    tester.set_ops(0x00, 0xFE);
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert!(!tester.device.high_res);
+
+    // 00fd exit
+    tester.set_ops(0x00, 0xFD);
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::Halt);
 }
 
 #[wasm_bindgen_test]
@@ -241,6 +404,64 @@ fn test_op_5xy0_skipeqv() {
     assert_eq!(tester.cpu.pc, pc);
 }
 
+#[wasm_bindgen_test]
+fn test_op_5xy2_5xy3_range() {
+    let mut tester = CPUTester::new();
+    let ir = 0x500;
+
+    // 5xy2 save V2..V4
+    tester.set_ops(0x52, 0x42);
+    tester.cpu.ir = ir;
+    tester.cpu.register[2] = 7;
+    tester.cpu.register[3] = 8;
+    tester.cpu.register[4] = 9;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.memory[ir..ir + 3], [7, 8, 9]);
+    assert_eq!(tester.cpu.ir, ir);
+
+    // 5xy3 load reversed V5..V3
+    tester.set_ops(0x55, 0x33);
+    tester.cpu.ir = ir;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.register[3..6], [9, 8, 7]);
+    assert_eq!(tester.cpu.ir, ir);
+
+    // out of memory
+    tester.set_ops(0x50, 0xF2);
+    tester.cpu.ir = MEM_SIZE - 4;
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::RuntimeError);
+
+    // unknown 5xy opcode
+    tester.set_ops(0x50, 0x01);
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::RuntimeError);
+}
+
+#[wasm_bindgen_test]
+fn test_skip_long_instruction() {
+    let mut tester = CPUTester::new();
+
+    // 3xkk skips over f000 nnnn entirely
+    tester.set_ops(0x30, 0x00);
+    tester.cpu.memory[PROGRAM_START + 2] = 0xF0;
+    tester.cpu.memory[PROGRAM_START + 3] = 0x00;
+
+    let pc = tester.cpu.pc + 2;
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.pc, pc + 4);
+}
+
 #[wasm_bindgen_test]
 fn test_op_6xkk_load() {
     let mut tester = CPUTester::new();
@@ -357,6 +578,16 @@ fn test_op_8xy1_or() {
 
     assert_eq!(result, ExecutionStatus::OK);
     assert_eq!(tester.cpu.register[0], val);
+
+    // quirk case: VF is reset
+    tester.set_ops(0x80, 0x11);
+    tester.cpu.quirks.vf_reset = true;
+    tester.cpu.register[0xF] = 1;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.register[0xF], 0);
 }
 
 #[wasm_bindgen_test]
@@ -607,7 +838,7 @@ fn test_op_8xy6_shr() {
     let expect_vf = 1;
 
     tester.set_ops(0x80, 0x16);
-    tester.cpu.quirk_shift = true;
+    tester.cpu.quirks.shift_vx = true;
     tester.cpu.register[0] = val;
 
     let result = tester.tick_cpu();
@@ -721,7 +952,7 @@ fn test_op_8xye_shl() {
     let expect_vf = 1;
 
     tester.set_ops(0x80, 0x1E);
-    tester.cpu.quirk_shift = true;
+    tester.cpu.quirks.shift_vx = true;
     tester.cpu.register[0] = val;
 
     let result = tester.tick_cpu();
@@ -783,17 +1014,39 @@ fn test_op_bnnn_jumpv() {
 
     // success case
     tester.set_ops(0xb4, 0x56);
+    tester.cpu.register[4] = 0x10;
 
     let result = tester.tick_cpu();
 
     assert_eq!(result, ExecutionStatus::OK);
     assert_eq!(tester.cpu.pc, addr);
+
+    // quirk case: jump to xnn + Vx
+    tester.set_ops(0xb4, 0x56);
+    tester.cpu.quirks.jump_vx = true;
+    tester.cpu.register[4] = 0x10;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.pc, addr + 0x10);
+
+    // jump out of memory
+    tester.set_ops(0xbf, 0xff);
+    tester.cpu.quirks.jump_vx = false;
+    tester.cpu.register[0] = 0xff;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::RuntimeError);
+    assert_eq!(tester.cpu.pc, PROGRAM_START + 2);
+    assert!(matches!(tester.cpu.error(), Some(Chip8Error::PcOutOfRange { target: 0x10FE, .. })));
 }
 
 
 #[wasm_bindgen_test]
 fn test_op_cxkk_load() {
-    let mock_random_value = utils::random();
+    let mock_random_value = MOCK_RANDOM;
     let mut tester = CPUTester::new();
 
     // reg 0 case
@@ -815,6 +1068,344 @@ fn test_op_cxkk_load() {
     assert_eq!(tester.cpu.register[3], val);
 }
 
+#[wasm_bindgen_test]
+fn test_op_cxkk_seeded() {
+    let run = |seed| {
+        let mut tester = CPUTester::new();
+        tester.cpu.set_rng(Box::new(XorShiftRng::new(seed)));
+        tester.load_asm("
+            RND V0, 0xFF
+            RND V1, 0xFF
+            RND V2, 0xFF
+            RND V3, 0xFF
+        ");
+
+        tester.tick_cpu_n(4);
+        tester.cpu.register
+    };
+
+    assert_eq!(run(42), run(42));
+    assert_ne!(run(42), run(43));
+}
+
+#[wasm_bindgen_test]
+fn test_op_dxyn_draw() {
+    let mut tester = CPUTester::new();
+    let ir = 0x500;
+
+    // 8 pixels wide sprite, 2 rows
+    tester.set_ops(0xD0, 0x12);
+    tester.cpu.ir = ir;
+    tester.cpu.memory[ir] = 0x81;
+    tester.cpu.memory[ir + 1] = 0x10;
+    tester.cpu.register[0] = 66; // wraps to 2 in low res
+    tester.cpu.register[1] = 4;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.device.drawn, vec![(2, 4), (9, 4), (5, 5)]);
+
+    // same position in high res does not wrap
+    tester.device.drawn.clear();
+    tester.device.high_res = true;
+
+    let result = tester.tick_cpu_at(PROGRAM_START);
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.device.drawn, vec![(66, 4), (73, 4), (69, 5)]);
+
+    // dxy0 draws 16x16 sprite
+    tester.device.drawn.clear();
+    tester.set_ops(0xD0, 0x10);
+    tester.cpu.ir = ir;
+
+    for i in 0..32 {
+        tester.cpu.memory[ir + i] = 0;
+    }
+
+    tester.cpu.memory[ir] = 0x80;
+    tester.cpu.memory[ir + 1] = 0x01;
+    tester.cpu.memory[ir + 31] = 0x01;
+    tester.cpu.register[0] = 0;
+    tester.cpu.register[1] = 0;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.device.drawn, vec![(0, 0), (15, 0), (15, 15)]);
+}
+
+#[wasm_bindgen_test]
+fn test_op_dxyn_display_wait() {
+    let mut tester = CPUTester::new();
+
+    // quirk case: draw waits for vertical blank
+    tester.set_ops(0xD0, 0x11);
+    tester.cpu.quirks.display_wait = true;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::WaitForVBlank);
+    assert_eq!(tester.cpu.pc, PROGRAM_START);
+
+    // draw proceeds once after vertical blank
+    tester.cpu.update_timer(&mut tester.audio);
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.pc, PROGRAM_START + 2);
+
+    let result = tester.tick_cpu_at(PROGRAM_START);
+    assert_eq!(result, ExecutionStatus::WaitForVBlank);
+
+    // high resolution draws don't wait
+    tester.device.high_res = true;
+
+    let result = tester.tick_cpu_at(PROGRAM_START);
+    assert_eq!(result, ExecutionStatus::OK);
+}
+
+#[wasm_bindgen_test]
+fn test_vip_timing() {
+    let mut tester = CPUTester::new();
+
+    // fixed timing counts instructions
+    tester.set_ops(0x60, 0x12);
+    tester.tick_cpu();
+    assert_eq!(tester.cpu.cycles, 1);
+
+    tester.cpu.set_timing(TimingMode::CosmacVip);
+    tester.set_ops(0x60, 0x12);
+    tester.tick_cpu();
+    assert_eq!(tester.cpu.cycles, 46);
+
+    // taken skip costs more
+    tester.set_ops(0x30, 0x12);
+    tester.tick_cpu();
+    assert_eq!(tester.cpu.cycles, 50);
+
+    tester.set_ops(0x30, 0x12);
+    tester.cpu.register[0] = 0x12;
+    tester.tick_cpu();
+    assert_eq!(tester.cpu.cycles, 54);
+
+    // draw waits for vertical blank without display_wait quirk, and costs more for unaligned sprites
+    tester.set_ops(0xD0, 0x15);
+    assert_eq!(tester.tick_cpu(), ExecutionStatus::WaitForVBlank);
+
+    tester.cpu.update_timer(&mut tester.audio);
+    assert_eq!(tester.tick_cpu(), ExecutionStatus::OK);
+    assert_eq!(tester.cpu.cycles, 236);
+
+    tester.set_ops(0xD0, 0x15);
+    tester.cpu.register[0] = 3;
+    tester.cpu.update_timer(&mut tester.audio);
+    assert_eq!(tester.tick_cpu(), ExecutionStatus::OK);
+    assert_eq!(tester.cpu.cycles, 436);
+
+    // bcd subtracts once per digit unit
+    tester.set_ops(0xF0, 0x33);
+    tester.cpu.register[0] = 123;
+    tester.tick_cpu();
+    assert_eq!(tester.cpu.cycles, 40 + 80 + 16 * 6);
+}
+
+#[wasm_bindgen_test]
+fn test_op_dxyn_draw_planes() {
+    let mut tester = CPUTester::new();
+    let ir = 0x500;
+
+    // both planes: plane 1 uses M[I..I+n], plane 2 uses M[I+n..I+2n]
+    tester.set_ops(0xD0, 0x01);
+    tester.device.planes = PLANE_1 | PLANE_2;
+    tester.cpu.ir = ir;
+    tester.cpu.memory[ir] = 0x80;
+    tester.cpu.memory[ir + 1] = 0x01;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.device.drawn, vec![(0, 0), (7, 0)]);
+    assert_eq!(tester.device.drawn_planes, PLANE_1 | PLANE_2);
+
+    // no plane selected draws nothing
+    tester.device.drawn.clear();
+    tester.set_ops(0xD0, 0x01);
+    tester.device.planes = 0;
+
+    tester.tick_cpu();
+    assert!(tester.device.drawn.is_empty());
+}
+
+#[wasm_bindgen_test]
+fn test_op_f000_loadlong() {
+    let mut tester = CPUTester::new();
+
+    tester.set_ops(0xF0, 0x00);
+    tester.cpu.memory[PROGRAM_START + 2] = 0xAB;
+    tester.cpu.memory[PROGRAM_START + 3] = 0xCD;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.ir, 0xABCD);
+    assert_eq!(tester.cpu.pc, PROGRAM_START + 4);
+}
+
+#[wasm_bindgen_test]
+fn test_op_fn01_plane() {
+    let mut tester = CPUTester::new();
+
+    tester.set_ops(0xF3, 0x01);
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.device.planes, 3);
+
+    // only 2 planes available
+    tester.set_ops(0xF4, 0x01);
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::RuntimeError);
+
+    // lenient skips, wrap masks to the planes
+    tester.cpu.set_error_policy(ErrorPolicy::Lenient);
+    tester.set_ops(0xF6, 0x01);
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.device.planes, 3);
+
+    tester.cpu.set_error_policy(ErrorPolicy::Wrap);
+    tester.set_ops(0xF6, 0x01);
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.device.planes, 2);
+}
+
+#[wasm_bindgen_test]
+fn test_op_f002_fx3a_audio() {
+    let mut tester = CPUTester::new();
+    let ir = 0x500;
+
+    assert!(tester.audio.pattern().is_none());
+
+    // f002 load audio pattern
+    tester.set_ops(0xF0, 0x02);
+    tester.cpu.ir = ir;
+
+    for i in 0..AUDIO_PATTERN_SIZE {
+        tester.cpu.memory[ir + i] = i as u8;
+    }
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.audio_pattern[..], tester.cpu.memory[ir..ir + AUDIO_PATTERN_SIZE]);
+    assert_eq!(tester.audio.pattern(), Some(&tester.cpu.audio_pattern));
+
+    // fx3a pitch
+    tester.set_ops(0xF2, 0x3A);
+    tester.cpu.register[2] = 112;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.pitch, 112);
+    assert_eq!(tester.audio.pitch(), 112);
+}
+
+#[wasm_bindgen_test]
+fn test_error_policy() {
+    let mut tester = CPUTester::new();
+    let end = MEM_SIZE - 1;
+
+    // status of each failing instruction below
+    let cases = [
+        (ErrorPolicy::Strict, ExecutionStatus::RuntimeError),
+        (ErrorPolicy::Lenient, ExecutionStatus::OK),
+        (ErrorPolicy::Wrap, ExecutionStatus::OK),
+    ];
+
+    for &(policy, expected) in cases.iter() {
+        // fx33 bcd
+        tester.set_ops(0xf0, 0x33);
+        tester.cpu.set_error_policy(policy);
+        tester.cpu.register[0] = 123;
+        tester.cpu.ir = end;
+
+        assert_eq!(tester.tick_cpu(), expected);
+
+        let written = (tester.cpu.memory[end], tester.cpu.memory[0], tester.cpu.memory[1]);
+        if policy == ErrorPolicy::Wrap {
+            assert_eq!(written, (1, 2, 3));
+        } else {
+            assert_ne!(written, (1, 2, 3));
+        }
+
+        // dxyn draw
+        tester.set_ops(0xd0, 0x02);
+        tester.cpu.ir = end;
+        tester.device.drawn.clear();
+
+        assert_eq!(tester.tick_cpu(), expected);
+        assert_eq!(tester.device.drawn.is_empty(), policy != ErrorPolicy::Wrap);
+
+        // fx1e addi
+        tester.set_ops(0xf0, 0x1e);
+        tester.cpu.ir = end;
+        tester.cpu.register[0] = 3;
+
+        assert_eq!(tester.tick_cpu(), expected);
+        assert_eq!(tester.cpu.ir, if policy == ErrorPolicy::Wrap { 2 } else { end });
+
+        // unknown opcode
+        tester.set_ops(0xff, 0xff);
+
+        assert_eq!(tester.tick_cpu(), expected);
+        assert_eq!(tester.cpu.pc, PROGRAM_START + 2);
+    }
+
+    // policy is kept on reset
+    tester.reset();
+    assert_eq!(tester.cpu.error_policy(), ErrorPolicy::Wrap);
+}
+
+#[wasm_bindgen_test]
+fn test_memory_size() {
+    let mut tester = CPUTester::new();
+
+    tester.cpu.set_memory_size(0x10000);
+    assert_eq!(tester.cpu.memory_size(), 0x10000);
+
+    // fx55 may write above 4 KiB
+    tester.set_ops(0xF1, 0x55);
+    tester.cpu.ir = 0xFFF0;
+    tester.cpu.register[1] = 0x42;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.memory[0xFFF1], 0x42);
+
+    // smaller sizes are rounded up to 4 KiB, all of it writable
+    tester.cpu.set_memory_size(PROGRAM_START + 0x10);
+    assert_eq!(tester.cpu.memory_size(), MEM_SIZE);
+
+    tester.set_ops(0xF1, 0x55);
+    tester.cpu.ir = 0xFF0;
+    tester.cpu.register[1] = 0x42;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.memory[0xFF1], 0x42);
+}
+
 #[wasm_bindgen_test]
 fn test_op_fx07_readdt() {
     let mut tester = CPUTester::new();
@@ -885,6 +1476,12 @@ fn test_op_fx18_loadst() {
 
     assert_eq!(result, ExecutionStatus::OK);
     assert_eq!(tester.cpu.st, val);
+    assert!(tester.audio.beeping());
+
+    // audio stops when sound timer runs out
+    tester.cpu.st = 1;
+    tester.cpu.update_timer(&mut tester.audio);
+    assert!(!tester.audio.beeping());
 }
 
 #[wasm_bindgen_test]
@@ -943,6 +1540,7 @@ fn test_op_fx29_digit() {
 
 
 #[wasm_bindgen_test]
+#[allow(clippy::needless_range_loop, clippy::manual_memcpy)]
 fn test_op_fx33_bcd() {
     let mut tester = CPUTester::new();
 
@@ -1038,11 +1636,63 @@ fn test_op_fx33_bcd() {
     for i in 0..3 {
         assert_eq!(tester.cpu.memory[ir + i], bcd[i]);
     }
+
+    // out of memory
+    let ir = MEM_SIZE - 2;
+
+    tester.set_ops(0xf0, 0x33);
+    tester.cpu.ir = ir;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::RuntimeError);
+    assert!(matches!(tester.cpu.error(), Some(Chip8Error::MemoryOutOfBounds { addr, len: 3, .. }) if *addr == ir));
+}
+
+#[wasm_bindgen_test]
+fn test_op_fx30_bigdigit() {
+    let mut tester = CPUTester::new();
+
+    // reg 0 case
+    let val: usize = 0x07;
+    tester.set_ops(0xf0, 0x30);
+    tester.cpu.register[0] = val as u8;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.ir, BIG_DIGIT_SPRITE_START + 10 * val);
+
+    // only decimal digit has big sprite
+    tester.set_ops(0xf0, 0x30);
+    tester.cpu.register[0] = 0x0A;
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::RuntimeError);
+
+    // lenient skips, wrap takes the digit modulo 10
+    tester.cpu.set_error_policy(ErrorPolicy::Lenient);
+    tester.set_ops(0xf0, 0x30);
+    tester.cpu.ir = 0x123;
+    tester.cpu.register[0] = 0x0C;
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.ir, 0x123);
+
+    tester.cpu.set_error_policy(ErrorPolicy::Wrap);
+    tester.set_ops(0xf0, 0x30);
+    tester.cpu.register[0] = 0x0C;
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.ir, BIG_DIGIT_SPRITE_START + 10 * 2);
 }
 
 // fx55 storeg M[I..I+x] = [V0..Vx], I += x + 1
 
 #[wasm_bindgen_test]
+#[allow(clippy::needless_range_loop, clippy::manual_memcpy)]
 fn test_op_fx55_storeg() {
     let mut tester = CPUTester::new();
     let ir = 0x500;
@@ -1095,7 +1745,7 @@ fn test_op_fx55_storeg() {
     let x = (nvals - 1) as u8;
 
     tester.set_ops(0xf0 | x, 0x55);
-    tester.cpu.quirk_loadstore_reg = true;
+    tester.cpu.quirks.loadstore = LoadStore::Unchanged;
     tester.cpu.ir = ir;
 
     for i in 0..nvals {
@@ -1110,10 +1760,21 @@ fn test_op_fx55_storeg() {
     }
 
     assert_eq!(tester.cpu.ir, ir);
+
+    // quirk case: I is increased by x
+    tester.set_ops(0xf0 | x, 0x55);
+    tester.cpu.quirks.loadstore = LoadStore::IncrementX;
+    tester.cpu.ir = ir;
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.ir, ir + x as usize);
 }
 
 // fx65 ldreg [V0..Vx] = M[I..I+x], I += x + 1
 #[wasm_bindgen_test]
+#[allow(clippy::needless_range_loop, clippy::manual_memcpy)]
 fn test_op_fx65_ldreg() {
     let mut tester = CPUTester::new();
 
@@ -1177,7 +1838,7 @@ fn test_op_fx65_ldreg() {
     let x = (nvals - 1) as u8;
 
     tester.set_ops(0xf0 | x, 0x65);
-    tester.cpu.quirk_loadstore_reg = true;
+    tester.cpu.quirks.loadstore = LoadStore::Unchanged;
     tester.cpu.ir = ir;
 
     for i in 0..nvals {
@@ -1197,3 +1858,106 @@ fn test_op_fx65_ldreg() {
         assert_eq!(tester.cpu.register[i], 0);
     }
 }
+
+#[wasm_bindgen_test]
+fn test_op_fx75_fx85_rpl() {
+    let mut tester = CPUTester::new();
+    let vals = [9, 3, 6, 1];
+    let x = (vals.len() - 1) as u8;
+
+    // fx75 saverpl
+    tester.set_ops(0xf0 | x, 0x75);
+    tester.cpu.register[..vals.len()].copy_from_slice(&vals);
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.rpl[..vals.len()], vals);
+
+    // fx85 loadrpl, flags persist through reset
+    tester.reset();
+    assert_eq!(tester.cpu.register[..vals.len()], [0; 4]);
+
+    tester.set_ops(0xf0 | x, 0x85);
+
+    let result = tester.tick_cpu();
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.register[..vals.len()], vals);
+
+    // HP48 has only 8 flags
+    tester.cpu.quirks.rpl_limit = true;
+    tester.set_ops(0xf8, 0x75);
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::RuntimeError);
+
+    // lenient skips, wrap copies the 8 flags
+    tester.cpu.set_error_policy(ErrorPolicy::Lenient);
+    tester.set_ops(0xf9, 0x75);
+    tester.cpu.register = [7; 16];
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.rpl[..vals.len()], vals);
+
+    tester.cpu.set_error_policy(ErrorPolicy::Wrap);
+    tester.set_ops(0xf9, 0x75);
+    tester.cpu.register = [7; 16];
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.rpl[..8], [7; 8]);
+    assert_eq!(tester.cpu.rpl[8..], [0; 8]);
+
+    tester.set_ops(0xff, 0x85);
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.register[..8], [7; 8]);
+    assert_eq!(tester.cpu.register[8..], [0; 8]);
+
+    // otherwise all 16 flags, like XO-CHIP
+    tester.cpu.set_error_policy(ErrorPolicy::Strict);
+    tester.cpu.quirks.rpl_limit = false;
+    tester.set_ops(0xff, 0x75);
+    tester.cpu.register = [5; 16];
+
+    let result = tester.tick_cpu();
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.rpl, [5; 16]);
+}
+
+#[wasm_bindgen_test]
+fn test_asm_program() {
+    let mut tester = CPUTester::new();
+
+    // sum 1..=5 in a subroutine and store the bcd of the result
+    tester.load_asm("
+            CALL sum
+            LD I, result
+            LD B, V1
+        halt:
+            JP halt
+
+        sum:
+            LD V0, 5
+            LD V1, 0
+        loop:
+            ADD V1, V0
+            ADD V0, 0xFF        ; V0 -= 1
+            SE V0, 0
+            JP loop
+            RET
+
+        result:
+            :byte 0 0 0
+    ");
+
+    let result = tester.tick_cpu_n(25);
+
+    assert_eq!(result, ExecutionStatus::OK);
+    assert_eq!(tester.cpu.register[1], 15);
+    assert_eq!(tester.cpu.ir, 0x216);
+    assert_eq!(tester.cpu.memory[0x216..0x219], [0, 1, 5]);
+}
@@ -0,0 +1,109 @@
+/****
+ * Instruction timing models
+ *
+ * Fixed timing counts every instruction as one unit, run at the configured instructions per second.
+ * COSMAC VIP timing approximates the original interpreter: each instruction costs machine cycles of the
+ * CDP1802 (8 clocks at 1.76 MHz, 3668 per 60 Hz frame), minus the cycles taken every frame by display DMA
+ * and the timer interrupt. Costs are the interpreter's fetch & decode loop plus the opcode routine, with
+ * draw cost depending on sprite height and how far the sprite is shifted from a byte boundary.
+ */
+
+use wasm_bindgen::prelude::*;
+use super::CPU;
+use super::instruction::Instruction;
+
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimingMode {
+    Fixed,          // same instruction count every frame, see Machine::set_instructions_per_second
+    CosmacVip,      // per-opcode machine cycles with a budget per frame, draws wait for vertical blank
+}
+
+pub const VIP_CYCLES_PER_SECOND : u32 = 3668 * 60;
+pub const VIP_FRAME_OVERHEAD : u32 = 1024 + 46;     // display DMA (128 lines x 8 bytes) & interrupt routine
+
+const FETCH_CYCLES : u32 = 40;          // fetch, decode & dispatch of each instruction
+pub(crate) const SKIP_CYCLES : u32 = 4;    // extra cost of a taken skip
+const DRAW_SETUP_CYCLES : u32 = 26;
+const DRAW_ROW_CYCLES : u32 = 34;       // byte aligned sprite row
+const DRAW_SHIFT_CYCLES : u32 = 8;      // per bit shift of an unaligned row, which also spans a second byte
+const DRAW_SPLIT_CYCLES : u32 = 16;
+pub(crate) const BCD_DIGIT_CYCLES : u32 = 16;    // per unit of each decimal digit
+
+// vip_cost returns machine cycles of instruction on the VIP, given the value of its x register. only draw & bcd
+// costs depend on it
+pub(crate) fn vip_cost(instruction: Instruction, vx: u8) -> u32 {
+    use Instruction::*;
+
+    let execute = match instruction {
+        Cls => 24 + 256 * 4,
+        Ret => 10,
+        Jump(_) => 12,
+        Call(_) => 26,
+        Sys(_) => 18,
+        SkipEq(_, _) | SkipNeq(_, _) => 10,
+        SkipEqV(_, _) | SkipNeqV(_, _) => 14,
+        Load(_, _) => 6,
+        Incr(_, _) => 10,
+        Set(_, _) => 12,
+        Or(_, _) | And(_, _) | Xor(_, _) | Add(_, _) | Sub(_, _) | Shr(_, _) | SubN(_, _) | Shl(_, _) => 44,
+        LoadI(_) => 12,
+        JumpV(_) => 22,
+        Rand(_, _) => 36,
+        Draw(_, _, n) => {
+            let rows = if n == 0 { 16 } else { n as u32 };
+            let shift = (vx % 8) as u32;
+
+            let row = if shift == 0 {
+                DRAW_ROW_CYCLES
+            } else {
+                DRAW_ROW_CYCLES + DRAW_SPLIT_CYCLES + DRAW_SHIFT_CYCLES * shift
+            };
+
+            DRAW_SETUP_CYCLES + rows * row
+        },
+        SkipKey(_) | SkipNotKey(_) => 14,
+        ReadDt(_) | WaitKey(_) | LoadDt(_) | LoadSt(_) => 10,
+        AddI(_) => 16,
+        Digit(_) => 20,
+
+        // digits are computed by repeated subtraction
+        Bcd(_) => {
+            let value = vx as u32;
+            80 + BCD_DIGIT_CYCLES * (value / 100 + value / 10 % 10 + value % 10)
+        },
+        Store(x) | LoadReg(x) => 14 + 14 * (x as u32 + 1),
+
+        // not in the VIP interpreter
+        _ => 40,
+    };
+
+    FETCH_CYCLES + execute
+}
+
+impl CPU {
+    // vip_cycles returns machine cycles of instruction on the VIP, called before executing it
+    pub(super) fn vip_cycles(&self, instruction: Instruction) -> u32 {
+        let vx = match instruction {
+            Instruction::Draw(x, _, _) | Instruction::Bcd(x) => self.register[x as usize],
+            _ => 0,
+        };
+
+        vip_cost(instruction, vx)
+    }
+
+    // skip_cycles returns extra cycles of instruction after executing it
+    pub(super) fn skip_cycles(&self, instruction: Instruction) -> u32 {
+        use Instruction::*;
+
+        let skip = matches!(instruction,
+            SkipEq(_, _) | SkipNeq(_, _) | SkipEqV(_, _) | SkipNeqV(_, _) | SkipKey(_) | SkipNotKey(_));
+
+        if skip && self.pc != self.instruction_pc + instruction.size() {
+            SKIP_CYCLES
+        } else {
+            0
+        }
+    }
+}
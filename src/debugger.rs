@@ -0,0 +1,388 @@
+/****
+ * Debugger: breakpoints, watchpoints & stepping
+ *
+ * Debugger is checked before each instruction. When it stops, the instruction at pc is not executed yet,
+ * and the first instruction after resuming or stepping ignores breakpoints & watchpoints so it can make progress
+ */
+
+use wasm_bindgen::prelude::*;
+use crate::cpu::{CPU, MemoryAccess};
+use crate::cpu::instruction::Instruction;
+
+// BreakReason is why the debugger stopped execution
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakReason {
+    None,
+    Breakpoint,
+    Watchpoint,
+    Step,
+}
+
+// Comparison is how a conditional breakpoint compares register value
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+// WatchKind is which memory access triggers a watchpoint
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub register: u8,
+    pub comparison: Comparison,
+    pub value: u8,
+}
+
+impl Condition {
+    fn matches(&self, cpu: &CPU) -> bool {
+        let vx = cpu.register(self.register as usize & 0xF);
+
+        match self.comparison {
+            Comparison::Equal => vx == self.value,
+            Comparison::NotEqual => vx != self.value,
+            Comparison::Less => vx < self.value,
+            Comparison::LessEqual => vx <= self.value,
+            Comparison::Greater => vx > self.value,
+            Comparison::GreaterEqual => vx >= self.value,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Breakpoint {
+    addr: usize,
+    condition: Option<Condition>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Watchpoint {
+    start: usize,   // inclusive
+    end: usize,     // inclusive
+    kind: WatchKind,
+}
+
+impl Watchpoint {
+    // hit returns the first watched address in access, if any
+    fn hit(&self, access: &MemoryAccess) -> Option<usize> {
+        let kind_match = match self.kind {
+            WatchKind::Read => !access.write,
+            WatchKind::Write => access.write,
+            WatchKind::ReadWrite => true,
+        };
+
+        let first = access.addr.max(self.start);
+        let last = (access.addr + access.len).min(self.end + 1);
+
+        if kind_match && first < last {
+            Some(first)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    Run,
+    Into,                           // stop after one instruction
+    Over { pc: usize, sp: usize },  // stop when returning to pc at the same stack depth
+    Out { sp: usize },              // stop when stack depth goes below sp
+}
+
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    step: Step,
+    resumed: bool,          // next instruction is the first one after resuming or stepping
+    reason: BreakReason,
+    break_addr: usize,      // pc on breakpoint & step, accessed memory address on watchpoint
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            step: Step::Run,
+            resumed: false,
+            reason: BreakReason::None,
+            break_addr: 0,
+        }
+    }
+
+    // reset clears execution state, breakpoints & watchpoints are kept
+    pub fn reset(&mut self) {
+        self.step = Step::Run;
+        self.resumed = false;
+        self.reason = BreakReason::None;
+        self.break_addr = 0;
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize, condition: Option<Condition>) {
+        self.breakpoints.push(Breakpoint { addr, condition });
+    }
+
+    // remove_breakpoint removes every breakpoint at addr
+    pub fn remove_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.retain(|bp| bp.addr != addr);
+    }
+
+    pub fn add_watchpoint(&mut self, start: usize, end: usize, kind: WatchKind) {
+        self.watchpoints.push(Watchpoint { start: start.min(end), end: start.max(end), kind });
+    }
+
+    pub fn remove_watchpoint(&mut self, start: usize, end: usize) {
+        self.watchpoints.retain(|wp| (wp.start, wp.end) != (start.min(end), start.max(end)));
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    pub fn resume(&mut self) {
+        self.start(Step::Run);
+    }
+
+    pub fn step_into(&mut self) {
+        self.start(Step::Into);
+    }
+
+    // step_over steps into anything but a subroutine call, which is run until it returns
+    pub fn step_over(&mut self, cpu: &CPU) {
+        let instruction = cpu.next_instruction();
+
+        self.start(match instruction {
+            Instruction::Call(_) => Step::Over { pc: cpu.pc() + instruction.size(), sp: cpu.sp() },
+            _ => Step::Into,
+        });
+    }
+
+    // step_out runs until current subroutine returns. on top level it is the same as resume
+    pub fn step_out(&mut self, cpu: &CPU) {
+        self.start(Step::Out { sp: cpu.sp() });
+    }
+
+    // is_active returns false if check can be skipped
+    pub fn is_active(&self) -> bool {
+        self.step != Step::Run || !self.breakpoints.is_empty() || !self.watchpoints.is_empty()
+    }
+
+    // check returns true if execution must stop before the next instruction
+    pub fn check(&mut self, cpu: &CPU, planes: u8) -> bool {
+        let pc = cpu.pc();
+
+        let stepped = match self.step {
+            Step::Run => false,
+            Step::Into => !self.resumed,
+            Step::Over { pc: return_pc, sp } => pc == return_pc && cpu.sp() == sp,
+            Step::Out { sp } => cpu.sp() < sp,
+        };
+
+        if stepped {
+            return self.stop(BreakReason::Step, pc);
+        }
+
+        if self.resumed {
+            return false;
+        }
+
+        let breakpoint = self.breakpoints.iter()
+            .any(|bp| bp.addr == pc && bp.condition.is_none_or(|cond| cond.matches(cpu)));
+
+        if breakpoint {
+            return self.stop(BreakReason::Breakpoint, pc);
+        }
+
+        if !self.watchpoints.is_empty() {
+            if let Some(access) = cpu.memory_access(cpu.next_instruction(), planes) {
+                if let Some(addr) = self.watchpoints.iter().find_map(|wp| wp.hit(&access)) {
+                    return self.stop(BreakReason::Watchpoint, addr);
+                }
+            }
+        }
+
+        false
+    }
+
+    // executed marks that an instruction has been executed since the last stop
+    pub fn executed(&mut self) {
+        self.resumed = false;
+    }
+
+    pub fn reason(&self) -> BreakReason {
+        self.reason
+    }
+
+    pub fn break_addr(&self) -> usize {
+        self.break_addr
+    }
+
+    // start continues execution from pc, ignoring breakpoints & watchpoints there
+    fn start(&mut self, step: Step) {
+        self.step = step;
+        self.resumed = true;
+        self.reason = BreakReason::None;
+    }
+
+    fn stop(&mut self, reason: BreakReason, addr: usize) -> bool {
+        self.step = Step::Run;
+        self.resumed = true;
+        self.reason = reason;
+        self.break_addr = addr;
+
+        true
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+    use crate::Machine;
+    use crate::cpu::ExecutionStatus;
+    use crate::assembler::assemble;
+
+    fn load_machine(source: &str) -> Machine {
+        let rom = assemble(source).unwrap().rom;
+        let mut machine = Machine::new();
+
+        assert!(machine.load_rom(&rom));
+
+        machine
+    }
+
+    const PROGRAM: &str = "
+            LD V0, 0
+        loop:
+            ADD V0, 1           ; 0x202
+            CALL sub            ; 0x204
+            LD I, data          ; 0x206
+            LD [I], V0          ; 0x208
+            JP loop             ; 0x20A
+        sub:
+            LD V1, V0           ; 0x20C
+            RET                 ; 0x20E
+        data:
+            :byte 0
+    ";
+
+    #[wasm_bindgen_test]
+    fn test_breakpoints() {
+        let mut machine = load_machine(PROGRAM);
+
+        machine.add_breakpoint(0x204);
+
+        assert_eq!(machine.update(), ExecutionStatus::Breakpoint);
+        assert_eq!(machine.get_break_reason(), BreakReason::Breakpoint);
+        assert_eq!(machine.get_break_addr(), 0x204);
+        assert_eq!(machine.get_register(0), 1);
+
+        // resuming does not stop on the same breakpoint again, the frame runs to its end
+        machine.resume();
+        assert_eq!(machine.update(), ExecutionStatus::OK);
+        assert_eq!(machine.update(), ExecutionStatus::Breakpoint);
+        assert_eq!(machine.get_register(0), 2);
+
+        // conditional breakpoint
+        machine.clear_breakpoints();
+        machine.add_conditional_breakpoint(0x20C, 0, Comparison::GreaterEqual, 5);
+
+        let mut status = ExecutionStatus::OK;
+
+        for _ in 0..10 {
+            status = machine.update();
+
+            if status != ExecutionStatus::OK {
+                break;
+            }
+        }
+
+        assert_eq!(status, ExecutionStatus::Breakpoint);
+        assert_eq!(machine.get_pc(), 0x20C);
+        assert_eq!(machine.get_register(0), 5);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_watchpoints() {
+        let mut machine = load_machine(PROGRAM);
+
+        // LD [I], V0 writes 1 byte at data (0x210)
+        machine.add_watchpoint(0x210, 0x210, WatchKind::Read);
+        machine.add_watchpoint(0x20F, 0x212, WatchKind::Write);
+
+        assert_eq!(machine.update(), ExecutionStatus::Breakpoint);
+        assert_eq!(machine.get_break_reason(), BreakReason::Watchpoint);
+        assert_eq!(machine.get_break_addr(), 0x210);
+        assert_eq!(machine.get_pc(), 0x208);
+        assert_eq!(machine.memory()[0x210], 0);
+
+        // read watchpoint is not triggered by writes
+        machine.remove_watchpoint(0x212, 0x20F);
+        machine.resume();
+        assert_eq!(machine.update(), ExecutionStatus::OK);
+        assert_eq!(machine.get_break_reason(), BreakReason::None);
+        assert_eq!(machine.memory()[0x210], 1);
+
+        assert_eq!(machine.update(), ExecutionStatus::OK);
+        assert_eq!(machine.memory()[0x210], 2);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_stepping() {
+        let mut machine = load_machine(PROGRAM);
+
+        machine.step_into();
+        assert_eq!(machine.update(), ExecutionStatus::Breakpoint);
+        assert_eq!(machine.get_break_reason(), BreakReason::Step);
+        assert_eq!(machine.get_pc(), 0x202);
+
+        // step into the call
+        machine.step_into();
+        machine.update();
+        machine.step_into();
+        machine.update();
+        assert_eq!((machine.get_pc(), machine.get_stack_depth()), (0x20C, 1));
+
+        // step out of it
+        machine.step_out();
+        machine.update();
+        assert_eq!((machine.get_pc(), machine.get_stack_depth()), (0x206, 0));
+
+        // step over until the next call
+        for _ in 0..4 {
+            machine.step_over();
+            machine.update();
+        }
+
+        assert_eq!(machine.get_pc(), 0x204);
+
+        machine.step_over();
+        assert_eq!(machine.update(), ExecutionStatus::Breakpoint);
+        assert_eq!((machine.get_pc(), machine.get_stack_depth()), (0x206, 0));
+        assert_eq!(machine.get_register(1), 2);
+    }
+}
@@ -0,0 +1,287 @@
+/****
+ * Disassembler, using the same instruction decoder as the interpreter
+ */
+
+use std::fmt;
+use wasm_bindgen::prelude::*;
+use crate::cpu::instruction::{Instruction, decode_at};
+
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    Classic,    // Cowgod's reference style, e.g. `LD V1, 0x05`
+    Octo,       // Octo assembly style, e.g. `v1 := 0x05`
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DisasmLine {
+    pub addr: usize,
+    pub opcode: u32,                // raw opcode bytes. f000 nnnn holds all 4 bytes
+    pub instruction: Instruction,
+    pub mnemonic: String,
+    pub operands: String,           // empty if instruction has no operand
+}
+
+impl fmt::Display for DisasmLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let opcode = if self.instruction.size() == 4 {
+            format!("{:08X}", self.opcode)
+        } else {
+            format!("{:04X}", self.opcode)
+        };
+
+        write!(f, "{:04X}: {:<8}  {}", self.addr, opcode, self.mnemonic)?;
+
+        if !self.operands.is_empty() {
+            write!(f, " {}", self.operands)?;
+        }
+
+        Ok(())
+    }
+}
+
+// disassemble decodes a whole rom, with its first byte located at `origin`.
+// a trailing odd byte is ignored
+pub fn disassemble(bytes: &[u8], origin: usize, syntax: Syntax) -> Vec<DisasmLine> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset + 1 < bytes.len() {
+        let line = disassemble_at(bytes, offset, syntax);
+        offset += line.instruction.size();
+
+        lines.push(DisasmLine { addr: origin + line.addr, ..line });
+    }
+
+    lines
+}
+
+// disassemble_memory decodes up to `count` instructions from memory, starting at `addr`
+pub fn disassemble_memory(memory: &[u8], addr: usize, count: usize, syntax: Syntax) -> Vec<DisasmLine> {
+    let mut lines = Vec::with_capacity(count);
+    let mut addr = addr;
+
+    while lines.len() < count && addr + 1 < memory.len() {
+        let line = disassemble_at(memory, addr, syntax);
+        addr += line.instruction.size();
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+// disassemble_at decodes one instruction at `addr`. `addr + 1` must be inside memory
+pub fn disassemble_at(memory: &[u8], addr: usize, syntax: Syntax) -> DisasmLine {
+    let instruction = decode_at(memory, addr);
+
+    let mut opcode = 0;
+    for i in 0..instruction.size() {
+        opcode = opcode << 8 | memory[addr + i] as u32;
+    }
+
+    let (mnemonic, operands) = statement(instruction, syntax);
+
+    DisasmLine { addr, opcode, instruction, mnemonic, operands }
+}
+
+// format_instruction returns the mnemonic & operands of a decoded instruction as one string
+pub fn format_instruction(instruction: Instruction, syntax: Syntax) -> String {
+    match statement(instruction, syntax) {
+        (mnemonic, operands) if operands.is_empty() => mnemonic,
+        (mnemonic, operands) => format!("{} {}", mnemonic, operands),
+    }
+}
+
+fn statement(instruction: Instruction, syntax: Syntax) -> (String, String) {
+    match syntax {
+        Syntax::Classic => classic(instruction),
+        Syntax::Octo => split_statement(octo(instruction)),
+    }
+}
+
+fn classic(instruction: Instruction) -> (String, String) {
+    use Instruction::*;
+
+    let v = |x: u8| format!("V{:X}", x);
+    let vv = |x: u8, y: u8| format!("V{:X}, V{:X}", x, y);
+    let vkk = |x: u8, kk: u8| format!("V{:X}, 0x{:02X}", x, kk);
+    let addr = |nnn: u16| format!("0x{:03X}", nnn);
+
+    let (mnemonic, operands) = match instruction {
+        ScrollDown(n) => ("SCD", format!("{}", n)),
+        ScrollUp(n) => ("SCU", format!("{}", n)),
+        Cls => ("CLS", String::new()),
+        Ret => ("RET", String::new()),
+        ScrollRight => ("SCR", String::new()),
+        ScrollLeft => ("SCL", String::new()),
+        Exit => ("EXIT", String::new()),
+        LowRes => ("LOW", String::new()),
+        HighRes => ("HIGH", String::new()),
+        Sys(nnn) => ("SYS", addr(nnn)),
+        Jump(nnn) => ("JP", addr(nnn)),
+        Call(nnn) => ("CALL", addr(nnn)),
+        SkipEq(x, kk) => ("SE", vkk(x, kk)),
+        SkipNeq(x, kk) => ("SNE", vkk(x, kk)),
+        SkipEqV(x, y) => ("SE", vv(x, y)),
+        SaveRange(x, y) => ("SAVE", format!("V{:X} - V{:X}", x, y)),
+        LoadRange(x, y) => ("LOAD", format!("V{:X} - V{:X}", x, y)),
+        Load(x, kk) => ("LD", vkk(x, kk)),
+        Incr(x, kk) => ("ADD", vkk(x, kk)),
+        Set(x, y) => ("LD", vv(x, y)),
+        Or(x, y) => ("OR", vv(x, y)),
+        And(x, y) => ("AND", vv(x, y)),
+        Xor(x, y) => ("XOR", vv(x, y)),
+        Add(x, y) => ("ADD", vv(x, y)),
+        Sub(x, y) => ("SUB", vv(x, y)),
+        Shr(x, y) => ("SHR", vv(x, y)),
+        SubN(x, y) => ("SUBN", vv(x, y)),
+        Shl(x, y) => ("SHL", vv(x, y)),
+        SkipNeqV(x, y) => ("SNE", vv(x, y)),
+        LoadI(nnn) => ("LD", format!("I, {}", addr(nnn))),
+        JumpV(nnn) => ("JP", format!("V0, {}", addr(nnn))),
+        Rand(x, kk) => ("RND", vkk(x, kk)),
+        Draw(x, y, n) => ("DRW", format!("{}, {}", vv(x, y), n)),
+        SkipKey(x) => ("SKP", v(x)),
+        SkipNotKey(x) => ("SKNP", v(x)),
+        LoadLong(nnnn) => ("LD", format!("I, LONG 0x{:04X}", nnnn)),
+        Plane(n) => ("PLANE", format!("{}", n)),
+        Audio => ("AUDIO", String::new()),
+        ReadDt(x) => ("LD", format!("{}, DT", v(x))),
+        WaitKey(x) => ("LD", format!("{}, K", v(x))),
+        LoadDt(x) => ("LD", format!("DT, {}", v(x))),
+        LoadSt(x) => ("LD", format!("ST, {}", v(x))),
+        AddI(x) => ("ADD", format!("I, {}", v(x))),
+        Digit(x) => ("LD", format!("F, {}", v(x))),
+        BigDigit(x) => ("LD", format!("HF, {}", v(x))),
+        Bcd(x) => ("LD", format!("B, {}", v(x))),
+        Pitch(x) => ("PITCH", v(x)),
+        Store(x) => ("LD", format!("[I], {}", v(x))),
+        LoadReg(x) => ("LD", format!("{}, [I]", v(x))),
+        SaveRpl(x) => ("LD", format!("R, {}", v(x))),
+        LoadRpl(x) => ("LD", format!("{}, R", v(x))),
+        Unknown(opcode) => ("DW", format!("0x{:04X}", opcode)),
+    };
+
+    (mnemonic.to_string(), operands)
+}
+
+fn octo(instruction: Instruction) -> String {
+    use Instruction::*;
+
+    match instruction {
+        ScrollDown(n) => format!("scroll-down {}", n),
+        ScrollUp(n) => format!("scroll-up {}", n),
+        Cls => "clear".to_string(),
+        Ret => "return".to_string(),
+        ScrollRight => "scroll-right".to_string(),
+        ScrollLeft => "scroll-left".to_string(),
+        Exit => "exit".to_string(),
+        LowRes => "lores".to_string(),
+        HighRes => "hires".to_string(),
+        Sys(nnn) => format!("0x{:02X} 0x{:02X}", nnn >> 8, nnn & 0xFF),
+        Jump(nnn) => format!("jump 0x{:03X}", nnn),
+        Call(nnn) => format!(":call 0x{:03X}", nnn),
+
+        // skip instructions are conditions for the next instruction to run
+        SkipEq(x, kk) => format!("if v{:x} != 0x{:02X} then", x, kk),
+        SkipNeq(x, kk) => format!("if v{:x} == 0x{:02X} then", x, kk),
+        SkipEqV(x, y) => format!("if v{:x} != v{:x} then", x, y),
+        SkipNeqV(x, y) => format!("if v{:x} == v{:x} then", x, y),
+        SkipKey(x) => format!("if v{:x} -key then", x),
+        SkipNotKey(x) => format!("if v{:x} key then", x),
+
+        SaveRange(x, y) => format!("save v{:x} - v{:x}", x, y),
+        LoadRange(x, y) => format!("load v{:x} - v{:x}", x, y),
+        Load(x, kk) => format!("v{:x} := 0x{:02X}", x, kk),
+        Incr(x, kk) => format!("v{:x} += 0x{:02X}", x, kk),
+        Set(x, y) => format!("v{:x} := v{:x}", x, y),
+        Or(x, y) => format!("v{:x} |= v{:x}", x, y),
+        And(x, y) => format!("v{:x} &= v{:x}", x, y),
+        Xor(x, y) => format!("v{:x} ^= v{:x}", x, y),
+        Add(x, y) => format!("v{:x} += v{:x}", x, y),
+        Sub(x, y) => format!("v{:x} -= v{:x}", x, y),
+        Shr(x, y) => format!("v{:x} >>= v{:x}", x, y),
+        SubN(x, y) => format!("v{:x} =- v{:x}", x, y),
+        Shl(x, y) => format!("v{:x} <<= v{:x}", x, y),
+        LoadI(nnn) => format!("i := 0x{:03X}", nnn),
+        JumpV(nnn) => format!("jump0 0x{:03X}", nnn),
+        Rand(x, kk) => format!("v{:x} := random 0x{:02X}", x, kk),
+        Draw(x, y, n) => format!("sprite v{:x} v{:x} 0x{:X}", x, y, n),
+        LoadLong(nnnn) => format!("i := long 0x{:04X}", nnnn),
+        Plane(n) => format!("plane {}", n),
+        Audio => "audio".to_string(),
+        ReadDt(x) => format!("v{:x} := delay", x),
+        WaitKey(x) => format!("v{:x} := key", x),
+        LoadDt(x) => format!("delay := v{:x}", x),
+        LoadSt(x) => format!("buzzer := v{:x}", x),
+        AddI(x) => format!("i += v{:x}", x),
+        Digit(x) => format!("i := hex v{:x}", x),
+        BigDigit(x) => format!("i := bighex v{:x}", x),
+        Bcd(x) => format!("bcd v{:x}", x),
+        Pitch(x) => format!("pitch := v{:x}", x),
+        Store(x) => format!("save v{:x}", x),
+        LoadReg(x) => format!("load v{:x}", x),
+        SaveRpl(x) => format!("saveflags v{:x}", x),
+        LoadRpl(x) => format!("loadflags v{:x}", x),
+
+        // unknown opcodes are emitted as raw bytes
+        Unknown(opcode) => format!("0x{:02X} 0x{:02X}", opcode >> 8, opcode & 0xFF),
+    }
+}
+
+// split_statement splits octo statement into its first token (mnemonic) and the rest (operands)
+fn split_statement(statement: String) -> (String, String) {
+    match statement.find(' ') {
+        Some(i) => (statement[..i].to_string(), statement[i + 1..].to_string()),
+        None => (statement, String::new()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_disassemble_classic() {
+        let rom = [0x00, 0xE0, 0x61, 0x05, 0xD1, 0x25, 0xF0, 0x00, 0x12, 0x34, 0x81, 0x29, 0xFF];
+        let lines = disassemble(&rom, 0x200, Syntax::Classic);
+
+        assert_eq!(lines.len(), 5);
+
+        assert_eq!(lines[0].addr, 0x200);
+        assert_eq!(lines[0].mnemonic, "CLS");
+        assert_eq!(lines[0].operands, "");
+
+        assert_eq!(lines[1].opcode, 0x6105);
+        assert_eq!(lines[1].to_string(), "0202: 6105      LD V1, 0x05");
+
+        assert_eq!(lines[2].operands, "V1, V2, 5");
+
+        // long instruction takes 4 bytes
+        assert_eq!(lines[3].opcode, 0xF0001234);
+        assert_eq!(lines[3].operands, "I, LONG 0x1234");
+        assert_eq!(lines[4].addr, 0x20A);
+
+        // unknown opcode
+        assert_eq!(lines[4].mnemonic, "DW");
+        assert_eq!(lines[4].operands, "0x8129");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_disassemble_octo() {
+        let memory = [0x3A, 0x12, 0x8A, 0xB7, 0xA2, 0x34, 0xFA, 0x65];
+        let lines = disassemble_memory(&memory, 2, 2, Syntax::Octo);
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].addr, 2);
+        assert_eq!(lines[0].mnemonic, "va");
+        assert_eq!(lines[0].operands, "=- vb");
+        assert_eq!(lines[1].to_string(), "0004: A234      i := 0x234");
+
+        let line = disassemble_at(&memory, 0, Syntax::Octo);
+        assert_eq!(line.to_string(), "0000: 3A12      if va != 0x12 then");
+    }
+}
@@ -0,0 +1,143 @@
+/****
+ * Runtime errors, with the machine state at the faulting instruction
+ */
+
+use std::error::Error;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+// ErrorPolicy is how the CPU handles unknown opcodes, memory accesses outside memory and invalid operands, e.g.
+// a big digit above 9. Stack errors always stop execution
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    Strict,     // stop with RuntimeError
+    Lenient,    // skip the instruction and continue
+    Wrap,       // wrap addresses around memory size, wrap or clamp operands, skip unknown opcodes
+}
+
+// Fault is the machine state when an instruction fails. pc points at the failing instruction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fault {
+    pub pc: usize,
+    pub opcode: u32,            // f000 nnnn is 4 bytes, others are 2 bytes
+    pub registers: [u8; 16],
+    pub ir: usize,
+    pub sp: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    StackOverflow { at: Fault },
+    StackUnderflow { at: Fault },
+    UnknownOpcode { at: Fault },
+    MemoryOutOfBounds { addr: usize, len: usize, at: Fault },   // access of M[addr..addr+len]
+    PcOutOfRange { target: usize, at: Fault },
+    InvalidOperand { operand: &'static str, value: usize, at: Fault },
+}
+
+impl Chip8Error {
+    pub fn fault(&self) -> &Fault {
+        match self {
+            Chip8Error::StackOverflow { at }
+            | Chip8Error::StackUnderflow { at }
+            | Chip8Error::UnknownOpcode { at }
+            | Chip8Error::MemoryOutOfBounds { at, .. }
+            | Chip8Error::PcOutOfRange { at, .. }
+            | Chip8Error::InvalidOperand { at, .. } => at,
+        }
+    }
+
+    // kind returns the variant name, e.g. "StackOverflow"
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Chip8Error::StackOverflow { .. } => "StackOverflow",
+            Chip8Error::StackUnderflow { .. } => "StackUnderflow",
+            Chip8Error::UnknownOpcode { .. } => "UnknownOpcode",
+            Chip8Error::MemoryOutOfBounds { .. } => "MemoryOutOfBounds",
+            Chip8Error::PcOutOfRange { .. } => "PcOutOfRange",
+            Chip8Error::InvalidOperand { .. } => "InvalidOperand",
+        }
+    }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::StackOverflow { .. } => write!(f, "stack overflow")?,
+            Chip8Error::StackUnderflow { .. } => write!(f, "stack underflow")?,
+            Chip8Error::UnknownOpcode { .. } => write!(f, "unknown opcode")?,
+            Chip8Error::MemoryOutOfBounds { addr, len, .. } => {
+                write!(f, "memory access out of bounds: 0x{:04X}..0x{:04X}", addr, addr + len)?
+            },
+            Chip8Error::PcOutOfRange { target, .. } => write!(f, "jump out of memory to 0x{:04X}", target)?,
+            Chip8Error::InvalidOperand { operand, value, .. } => write!(f, "invalid {} {}", operand, value)?,
+        }
+
+        let at = self.fault();
+        let width = if at.opcode > 0xFFFF { 8 } else { 4 };
+
+        write!(f, " at 0x{:04X} (opcode 0x{:0width$X})", at.pc, at.opcode, width = width)
+    }
+}
+
+impl Error for Chip8Error {}
+
+// ErrorReport is Chip8Error as a plain JS object
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug)]
+pub struct ErrorReport {
+    pub kind: String,
+    pub message: String,
+    pub pc: usize,
+    pub opcode: u32,
+    pub registers: Vec<u8>,
+    pub ir: usize,
+    pub sp: usize,
+}
+
+impl From<&Chip8Error> for ErrorReport {
+    fn from(err: &Chip8Error) -> ErrorReport {
+        let at = err.fault();
+
+        ErrorReport {
+            kind: err.kind().to_string(),
+            message: err.to_string(),
+            pc: at.pc,
+            opcode: at.opcode,
+            registers: at.registers.to_vec(),
+            ir: at.ir,
+            sp: at.sp,
+        }
+    }
+}
+
+impl From<Chip8Error> for JsValue {
+    fn from(err: Chip8Error) -> JsValue {
+        ErrorReport::from(&err).into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_error_report() {
+        let mut registers = [0; 16];
+        registers[0xF] = 1;
+
+        let at = Fault { pc: 0x204, opcode: 0xF000_1234, registers, ir: 0xFFFE, sp: 2 };
+        let error = Chip8Error::MemoryOutOfBounds { addr: 0xFFFE, len: 4, at };
+
+        assert_eq!(error.to_string(), "memory access out of bounds: 0xFFFE..0x10002 at 0x0204 (opcode 0xF0001234)");
+
+        let report = ErrorReport::from(&error);
+
+        assert_eq!(report.kind, "MemoryOutOfBounds");
+        assert_eq!((report.pc, report.opcode, report.ir, report.sp), (0x204, 0xF000_1234, 0xFFFE, 2));
+        assert_eq!(report.registers[0xF], 1);
+    }
+}
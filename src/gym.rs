@@ -0,0 +1,533 @@
+/****
+ * Gym-style environment: a machine playing one game, stepped by actions, giving rewards & episode end
+ *
+ * The game is described by a spec, a line based text file, `#` starts a comment:
+ *
+ *   name pong                  environment name
+ *   rom Pong.ch8               rom file, the rest of the line
+ *   platform SChip11           quirk profile, as in roms/index.js (default: default quirks)
+ *   actions - 1 4 4+6          keys of each action: hex digits joined by `+`, or `-` for no key
+ *   score bcd 0x314 3 [W]      score term: DIGITS bcd digits at ADDR, `raw LOCATION` for a byte, where
+ *   score raw VA [W]           LOCATION is a register (V0..VF) or a memory address, or `steps LOCATION STEP`
+ *   score steps VE 10 [W]      counting the frames in which LOCATION grew by STEP, for a byte holding several
+ *                              counts. terms are summed, times their weight W (default 1). reward of a step is
+ *                              the change of the score
+ *   lives raw VE               lives counter, same format as score without weight
+ *   game_over 0x232 V1 == 3    episode ends before running ADDR, optionally only when the register
+ *                              comparison (==, !=, <, <=, >, >=) holds. halt & runtime errors also end it
+ *   frames 18000               episode ends after this many frames
+ *
+ * Game over addresses are machine breakpoints, so they are caught mid frame, at the cost of stepping the
+ * machine one instruction at a time.
+ */
+
+use std::error::Error;
+use std::fmt;
+use crate::{Machine, ExecutionStatus, Comparison, Platform, Quirks};
+
+// Location is a byte of machine state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    Register(u8),
+    Memory(usize),
+}
+
+// Counter is a number read from machine state, e.g. score or lives
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Counter {
+    Raw(Location),
+    Bcd { addr: usize, digits: usize },     // one digit per byte, most significant first, as stored by fx33
+    Steps { location: Location, step: u8 }, // frames in which location grew by step since reset, counted by Env
+}
+
+impl Counter {
+    // read returns the counter value. steps depend on past frames, so read gives the value of their location
+    pub fn read(&self, machine: &Machine) -> u32 {
+        let byte = |addr: usize| machine.memory().get(addr).copied().unwrap_or(0) as u32;
+
+        match *self {
+            Counter::Raw(location) | Counter::Steps { location, .. } => match location {
+                Location::Register(x) => machine.get_register(x as usize) as u32,
+                Location::Memory(addr) => byte(addr),
+            },
+            Counter::Bcd { addr, digits } => (addr..addr + digits).fold(0, |value, addr| value * 10 + byte(addr)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScoreTerm {
+    pub counter: Counter,
+    pub weight: f32,
+}
+
+// GameOver is an address ending the episode when reached, with `V[register] comparison value` if any
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameOver {
+    pub addr: usize,
+    pub condition: Option<(u8, Comparison, u8)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnvSpec {
+    pub name: String,
+    pub rom: String,
+    pub quirks: Quirks,
+    pub actions: Vec<u16>,          // key bit mask of each action
+    pub score: Vec<ScoreTerm>,
+    pub lives: Option<Counter>,
+    pub game_over: Vec<GameOver>,
+    pub max_frames: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpecError {
+    pub line: usize,        // 1-based
+    pub message: String,
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.message)
+    }
+}
+
+impl Error for SpecError {}
+
+impl EnvSpec {
+    // parse reads a spec, name, rom & actions are required
+    pub fn parse(source: &str) -> Result<EnvSpec, SpecError> {
+        let mut spec = EnvSpec {
+            name: String::new(),
+            rom: String::new(),
+            quirks: Quirks::new(),
+            actions: Vec::new(),
+            score: Vec::new(),
+            lives: None,
+            game_over: Vec::new(),
+            max_frames: None,
+        };
+
+        for (i, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let error = |message: String| SpecError { line: i + 1, message };
+
+            let (key, rest) = match line.split_once(char::is_whitespace) {
+                Some((key, rest)) => (key, rest.trim()),
+                None if line.is_empty() => continue,
+                None => return Err(error(format!("missing value for {}", line))),
+            };
+
+            let args: Vec<&str> = rest.split_whitespace().collect();
+
+            match key {
+                "name" => spec.name = rest.to_string(),
+                "rom" => spec.rom = rest.to_string(),
+                "platform" => spec.quirks = Quirks::for_platform(parse_platform(rest).map_err(error)?),
+                "actions" => spec.actions = args.iter().map(|action| parse_action(action)).collect::<Result<_, _>>().map_err(error)?,
+                "score" => spec.score.push(parse_score(&args).map_err(error)?),
+                "lives" => spec.lives = Some(parse_lives(&args).map_err(error)?),
+                "game_over" => spec.game_over.push(parse_game_over(&args).map_err(error)?),
+                "frames" => spec.max_frames = Some(parse_number(rest).map_err(error)?),
+                _ => return Err(error(format!("unknown key {}", key))),
+            }
+        }
+
+        let last = source.lines().count();
+
+        if spec.name.is_empty() || spec.rom.is_empty() || spec.actions.is_empty() {
+            return Err(SpecError { line: last, message: "name, rom & actions are required".to_string() });
+        }
+
+        Ok(spec)
+    }
+}
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+
+    parsed.map_err(|_| format!("invalid number {}", text))
+}
+
+fn parse_platform(name: &str) -> Result<Platform, String> {
+    match name {
+        "CosmacVip" => Ok(Platform::CosmacVip),
+        "Chip48" => Ok(Platform::Chip48),
+        "SChip10" => Ok(Platform::SChip10),
+        "SChip11" => Ok(Platform::SChip11),
+        "SChipModern" => Ok(Platform::SChipModern),
+        "XoChip" => Ok(Platform::XoChip),
+        _ => Err(format!("unknown platform {}", name)),
+    }
+}
+
+fn parse_action(action: &str) -> Result<u16, String> {
+    if action == "-" {
+        return Ok(0);
+    }
+
+    action.split('+').try_fold(0, |keys, key| match u8::from_str_radix(key, 16) {
+        Ok(digit) if key.len() == 1 => Ok(keys | 1 << digit),
+        _ => Err(format!("invalid key {} in action {}", key, action)),
+    })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix('V').or_else(|| text.strip_prefix('v'))?;
+
+    match u8::from_str_radix(digit, 16) {
+        Ok(x) if digit.len() == 1 => Some(x),
+        _ => None,
+    }
+}
+
+fn parse_location(text: &str) -> Result<Location, String> {
+    match parse_register(text) {
+        Some(x) => Ok(Location::Register(x)),
+        None => Ok(Location::Memory(parse_number(text)?)),
+    }
+}
+
+// parse_counter reads `raw LOCATION`, `bcd ADDR DIGITS` or `steps LOCATION STEP`, returns it with the unused
+// arguments
+fn parse_counter<'a>(args: &'a [&'a str]) -> Result<(Counter, &'a [&'a str]), String> {
+    match args {
+        ["raw", location, rest @ ..] => Ok((Counter::Raw(parse_location(location)?), rest)),
+        ["bcd", addr, digits, rest @ ..] => {
+            let counter = Counter::Bcd { addr: parse_number(addr)?, digits: parse_number(digits)? };
+            Ok((counter, rest))
+        },
+        ["steps", location, step, rest @ ..] => {
+            let step = match parse_number(step)? {
+                step @ 1..=0xFF => step as u8,
+                _ => return Err(format!("invalid step {}", step)),
+            };
+
+            Ok((Counter::Steps { location: parse_location(location)?, step }, rest))
+        },
+        _ => Err(format!("invalid counter {}, expected raw LOCATION, bcd ADDR DIGITS or steps LOCATION STEP",
+            args.join(" "))),
+    }
+}
+
+fn parse_score(args: &[&str]) -> Result<ScoreTerm, String> {
+    let (counter, rest) = parse_counter(args)?;
+
+    let weight = match rest {
+        [] => 1.0,
+        [weight] => weight.parse().map_err(|_| format!("invalid weight {}", weight))?,
+        _ => return Err(format!("unexpected {}", rest.join(" "))),
+    };
+
+    Ok(ScoreTerm { counter, weight })
+}
+
+fn parse_lives(args: &[&str]) -> Result<Counter, String> {
+    match parse_counter(args)? {
+        (counter, []) => Ok(counter),
+        (_, rest) => Err(format!("unexpected {}", rest.join(" "))),
+    }
+}
+
+fn parse_game_over(args: &[&str]) -> Result<GameOver, String> {
+    let (addr, condition) = match args {
+        [addr] => (addr, None),
+        [addr, register, comparison, value] => {
+            let register = parse_register(register).ok_or(format!("invalid register {}", register))?;
+
+            let comparison = match *comparison {
+                "==" => Comparison::Equal,
+                "!=" => Comparison::NotEqual,
+                "<" => Comparison::Less,
+                "<=" => Comparison::LessEqual,
+                ">" => Comparison::Greater,
+                ">=" => Comparison::GreaterEqual,
+                _ => return Err(format!("invalid comparison {}", comparison)),
+            };
+
+            let value = match parse_number(value)? {
+                value if value <= 0xFF => value as u8,
+                value => return Err(format!("invalid value {}", value)),
+            };
+
+            (addr, Some((register, comparison, value)))
+        },
+        _ => return Err("expected ADDR [Vx COMPARISON VALUE]".to_string()),
+    };
+
+    Ok(GameOver { addr: parse_number(addr)?, condition })
+}
+
+// Step is the result of an action
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub reward: f32,
+    pub done: bool,
+}
+
+// StepCount is the state of a steps counter: the value of its location after the last frame, and the steps
+#[derive(Clone, Copy, Debug, Default)]
+struct StepCount {
+    last: u32,
+    count: u32,
+}
+
+pub struct Env {
+    machine: Machine,
+    spec: EnvSpec,
+    rom: Vec<u8>,
+    steps: Vec<StepCount>,      // one per score term, then lives
+    score: f32,
+    frames: usize,
+    held: u16,          // keys of the previous action
+    done: bool,
+}
+
+impl Env {
+    // new creates the environment of spec, running rom. None if rom is too big.
+    // an episode starts with reset
+    pub fn new(spec: EnvSpec, rom: &[u8]) -> Option<Env> {
+        let mut machine = Machine::new();
+        machine.set_quirks(spec.quirks);
+
+        for over in spec.game_over.iter() {
+            match over.condition {
+                Some((register, comparison, value)) => machine.add_conditional_breakpoint(over.addr, register, comparison, value),
+                None => machine.add_breakpoint(over.addr),
+            }
+        }
+
+        if !machine.load_rom(rom) {
+            return None;
+        }
+
+        let steps = vec![StepCount::default(); spec.score.len() + 1];
+        let mut env = Env { machine, spec, rom: rom.to_vec(), steps, score: 0.0, frames: 0, held: 0, done: false };
+        env.reset(0);
+        Some(env)
+    }
+
+    pub fn spec(&self) -> &EnvSpec {
+        &self.spec
+    }
+
+    pub fn action_count(&self) -> usize {
+        self.spec.actions.len()
+    }
+
+    // machine_mut gives access to machine settings, e.g. speed. it must not be reset, use reset of Env
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        &mut self.machine
+    }
+
+    // reset starts a new episode with random numbers seeded, returns the observation
+    pub fn reset(&mut self, seed: u32) -> &[u8] {
+        self.machine.restart(&self.rom, seed);
+        self.count_steps(true);
+        self.score = self.read_score();
+        self.frames = 0;
+        self.held = 0;
+        self.done = false;
+
+        self.observation()
+    }
+
+    // step runs one frame pressing the keys of action, returns the observation, the reward & whether the
+    // episode is done. a done episode doesn't run anymore, until reset
+    pub fn step(&mut self, action: usize) -> (&[u8], Step) {
+        if self.done {
+            return (self.observation(), Step { reward: 0.0, done: true });
+        }
+
+        let keys = self.spec.actions[action];
+        self.machine.set_keys(keys, self.held & !keys);
+        self.held = keys;
+
+        let status = self.machine.update();
+        self.frames += 1;
+        self.count_steps(false);
+
+        let score = self.read_score();
+        let reward = score - self.score;
+        self.score = score;
+
+        self.done = match status {
+            ExecutionStatus::Breakpoint | ExecutionStatus::Halt | ExecutionStatus::RuntimeError => true,
+            _ => self.spec.max_frames.is_some_and(|max| self.frames >= max),
+        };
+
+        (self.observation(), Step { reward, done: self.done })
+    }
+
+    // observation returns the display, one byte per pixel holding its plane bits like Machine::display
+    pub fn observation(&self) -> &[u8] {
+        self.machine.display()
+    }
+
+    // score returns the weighted sum of the score terms
+    pub fn score(&self) -> f32 {
+        self.score
+    }
+
+    // lives returns the lives counter, None if the spec has none
+    pub fn lives(&self) -> Option<u32> {
+        self.spec.lives.map(|lives| self.value(self.spec.score.len(), lives))
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    fn read_score(&self) -> f32 {
+        self.spec.score.iter().enumerate().map(|(i, term)| self.value(i, term.counter) as f32 * term.weight).sum()
+    }
+
+    // value returns counter i of the score terms & lives
+    fn value(&self, i: usize, counter: Counter) -> u32 {
+        match counter {
+            Counter::Steps { .. } => self.steps[i].count,
+            _ => counter.read(&self.machine),
+        }
+    }
+
+    // count_steps counts the steps counters after a frame, or starts them over on reset
+    fn count_steps(&mut self, reset: bool) {
+        let counters = self.spec.score.iter().map(|term| term.counter).chain(self.spec.lives);
+
+        for (state, counter) in self.steps.iter_mut().zip(counters) {
+            if let Counter::Steps { step, .. } = counter {
+                let value = counter.read(&self.machine);
+
+                if reset {
+                    *state = StepCount { last: value, count: 0 };
+                } else {
+                    state.count += (value == (state.last + step as u32) & 0xFF) as u32;
+                    state.last = value;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[wasm_bindgen_test]
+    fn test_parse_spec() {
+        let spec = EnvSpec::parse("
+            # comment
+            name brix
+            rom  Brix [Andreas Gustafsson, 1990].ch8
+            platform CosmacVip
+            actions - 4 6 4+6   # none, left, right
+            score bcd 0x314 3
+            score raw VE -0.5
+            score steps 0x301 10
+            lives raw 0x300
+            game_over 0x2DE
+            game_over 0x232 V1 >= 3
+            frames 100
+        ").unwrap();
+
+        assert_eq!(spec.name, "brix");
+        assert_eq!(spec.rom, "Brix [Andreas Gustafsson, 1990].ch8");
+        assert_eq!(spec.quirks, Quirks::for_platform(Platform::CosmacVip));
+        assert_eq!(spec.actions, vec![0, 1 << 4, 1 << 6, 1 << 4 | 1 << 6]);
+        assert_eq!(spec.score, vec![
+            ScoreTerm { counter: Counter::Bcd { addr: 0x314, digits: 3 }, weight: 1.0 },
+            ScoreTerm { counter: Counter::Raw(Location::Register(0xE)), weight: -0.5 },
+            ScoreTerm { counter: Counter::Steps { location: Location::Memory(0x301), step: 10 }, weight: 1.0 },
+        ]);
+        assert_eq!(spec.lives, Some(Counter::Raw(Location::Memory(0x300))));
+        assert_eq!(spec.game_over, vec![
+            GameOver { addr: 0x2DE, condition: None },
+            GameOver { addr: 0x232, condition: Some((1, Comparison::GreaterEqual, 3)) },
+        ]);
+        assert_eq!(spec.max_frames, Some(100));
+
+        let error = |source: &str| EnvSpec::parse(source).unwrap_err();
+        assert_eq!(error("name a\nrom a.ch8\nactions 1 g").line, 3);
+        assert_eq!(error("name a\nscore bcd 0x300").line, 2);
+        assert_eq!(error("name a\ngame_over 0x200 V1 = 3").line, 2);
+        assert_eq!(error("name a\nlives raw VG").line, 2);
+        assert_eq!(error("name a\nlives raw VE 2").line, 2);
+        assert_eq!(error("name a\nscore steps VE 0").line, 2);
+        assert_eq!(error("name a\nspeed 10").line, 2);
+        assert_eq!(error("name a\nrom a.ch8").message, "name, rom & actions are required");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_env() {
+        // scores 12 points in bcd at 0x300 on key 5, then loses its life in VE and stops at `over`
+        let rom = assemble("
+                LD VE, 1
+                LD V0, 12
+                LD V1, 5
+            wait:
+                SKP V1
+                JP wait
+                LD I, 0x300
+                LD B, V0
+                LD V2, 6
+            life:
+                SKP V2
+                JP life
+                LD VE, 0
+            over:
+                JP over
+        ").unwrap().rom;
+
+        let spec = EnvSpec::parse("
+            name test
+            rom test.ch8
+            actions - 5 6
+            score bcd 0x300 3 0.5
+            lives raw VE
+            game_over 0x216
+            frames 10
+        ").unwrap();
+
+        let mut env = Env::new(spec.clone(), &rom).unwrap();
+        assert_eq!(env.action_count(), 3);
+
+        env.reset(1);
+        assert_eq!(env.step(0).1, Step { reward: 0.0, done: false });
+        assert_eq!(env.lives(), Some(1));
+        assert_eq!(env.step(1).1, Step { reward: 6.0, done: false });
+        assert_eq!(env.score(), 6.0);
+
+        // game over is caught mid frame
+        let (observation, step) = env.step(2);
+        assert_eq!(observation.len(), 64 * 32);
+        assert_eq!(step, Step { reward: 0.0, done: true });
+        assert_eq!(env.lives(), Some(0));
+        assert_eq!(env.machine().get_pc(), 0x216);
+        assert_eq!(env.step(1).1, Step { reward: 0.0, done: true });
+
+        // reset clears memory & starts over, then the episode ends by frame count
+        env.reset(2);
+        assert_eq!(env.score(), 0.0);
+        assert!(!env.is_done());
+
+        for frame in 1..=10 {
+            let (_, step) = env.step(0);
+            assert_eq!(step.done, frame == 10);
+        }
+
+        assert!(Env::new(spec, &vec![0; 0x1000]).is_none());
+    }
+}
@@ -1,15 +1,67 @@
 use wasm_bindgen::prelude::*;
+use crate::cpu::{AUDIO_PATTERN_SIZE, DEFAULT_PITCH};
+use crate::quirks::Quirks;
+use crate::savestate::{StateReader, StateWriter, StateError};
 
 pub const DISPLAY_WIDTH : usize = 64;
 pub const DISPLAY_HEIGHT : usize = 32;
-pub const DISPLAY_SIZE : usize = DISPLAY_WIDTH * DISPLAY_HEIGHT;
+
+// SCHIP high resolution mode
+pub const HIRES_DISPLAY_WIDTH : usize = 128;
+pub const HIRES_DISPLAY_HEIGHT : usize = 64;
+
+// buffer is allocated for the largest resolution
+pub const DISPLAY_SIZE : usize = HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT;
 
 pub const NO_KEY : u8 = 0xFF;
 
-pub trait IOInterface {
+// XO-CHIP drawing planes. each pixel in display buffer holds one bit per plane
+pub const PLANE_1 : u8 = 0x1;
+pub const PLANE_2 : u8 = 0x2;
+pub const ALL_PLANES : u8 = PLANE_1 | PLANE_2;
+
+// Display, Keypad & Audio are the devices driven by CPU::tick, each can be implemented on its own by a host.
+// IODevice bundles the implementations used by Machine
+
+pub trait Display {
+    // clear_display clears the selected planes
     fn clear_display(&mut self);
-    fn draw_pixel(&mut self, x: u8, y: u8) -> u8;
 
+    // draw_pixel toggles the pixel on `plane`, returns 1 if it is erased
+    fn draw_pixel(&mut self, x: u8, y: u8, plane: u8) -> u8;
+
+    // draw_row toggles the 8 pixels of sprite `row` at (x, y) on `plane`, returns true if any pixel is erased.
+    // pixels out of screen are clipped or wrapped as by draw_pixel
+    fn draw_row(&mut self, x: u8, y: u8, row: u8, plane: u8) -> bool;
+
+    // draw_sprite toggles 8 pixel wide `rows` at (x, y) on `plane` row by row, returns true if any pixel is erased
+    fn draw_sprite(&mut self, x: u8, y: u8, rows: &[u8], plane: u8) -> bool {
+        let mut erased = false;
+
+        for (dy, &row) in rows.iter().enumerate() {
+            erased |= self.draw_row(x, y + dy as u8, row, plane);
+        }
+
+        erased
+    }
+
+    // select_planes sets which planes are affected by clear, scroll and draw
+    fn select_planes(&mut self, planes: u8);
+    fn selected_planes(&self) -> u8;
+
+    // set_high_res switches between 64x32 and 128x64 mode. switching clears the display, or keeps the picture
+    // scaled to the new resolution without the resolution clear quirk (SCHIP 1.x)
+    fn set_high_res(&mut self, enabled: bool);
+    fn is_high_res(&self) -> bool;
+
+    // scroll display down / up by n pixels, or left / right by 4 pixels
+    fn scroll_down(&mut self, n: u8);
+    fn scroll_up(&mut self, n: u8);
+    fn scroll_left(&mut self);
+    fn scroll_right(&mut self);
+}
+
+pub trait Keypad {
     // key_pressed returns true if `key` is in pressed state
     fn key_pressed(&self, key: u8) -> bool;
 
@@ -17,112 +69,444 @@ pub trait IOInterface {
     fn read_any_key(&mut self) -> u8;
 }
 
+pub trait Audio {
+    // set_beeping turns sound on while the sound timer is not zero
+    fn set_beeping(&mut self, beeping: bool);
+
+    // set_pattern replaces the buzzer tone with an XO-CHIP 1-bit audio pattern
+    fn set_pattern(&mut self, pattern: &[u8; AUDIO_PATTERN_SIZE]);
+
+    // set_pitch sets audio pattern playback pitch, 64 is 4000 Hz
+    fn set_pitch(&mut self, pitch: u8);
+}
+
 #[wasm_bindgen]
 #[derive(Clone, Copy)]
 pub struct DisplayUpdate {
     pub display_ptr: *const u8,
-    pub updated_ptr: *const bool,
+    pub updated_ptr: *const u8,
     pub buffer_size: usize,
+    pub width: usize,
+    pub height: usize,
     pub display_updated: bool,
     pub display_cleared: bool,
 }
 
 pub struct IODevice {
-    display_buffer: [u8; DISPLAY_SIZE],
-    updated: [bool; DISPLAY_SIZE],
+    display: FrameBuffer,
+    keypad: KeyState,
+    audio: AudioState,
+}
 
-    pressed_keys: u16,      // mapping u16 of which key is currently pressed
-    released_keys: u16,     // mapping u16 of which key has just been released
+impl IODevice {
+    pub fn new() -> IODevice {
+        IODevice { display: FrameBuffer::new(), keypad: KeyState::new(), audio: AudioState::new() }
+    }
+
+    // devices returns display, keypad & audio to be borrowed together by CPU::tick
+    pub fn devices(&mut self) -> (&mut FrameBuffer, &mut KeyState, &mut AudioState) {
+        (&mut self.display, &mut self.keypad, &mut self.audio)
+    }
+
+    pub fn set_quirks(&mut self, quirks: &Quirks) {
+        self.display.set_quirks(quirks);
+    }
+
+    pub fn reset(&mut self) {
+        self.display.reset();
+        self.keypad = KeyState::new();
+        self.audio = AudioState::new();
+    }
+
+    pub fn reset_display_flags(&mut self) {
+        self.display.reset_flags();
+    }
+
+    pub fn get_display_update(&self) -> DisplayUpdate {
+        self.display.get_update()
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.display.planes
+    }
+
+    pub fn set_keys(&mut self, pressed_keys: u16, released_keys: u16) {
+        self.keypad.set_keys(pressed_keys, released_keys);
+    }
+
+    // keys returns (pressed, released) key bit masks
+    pub fn keys(&self) -> (u16, u16) {
+        (self.keypad.pressed_keys, self.keypad.released_keys)
+    }
+
+    // display returns the visible part of display buffer, row by row
+    pub fn display(&self) -> &[u8] {
+        self.display.visible()
+    }
+
+    pub fn audio(&self) -> &AudioState {
+        &self.audio
+    }
+
+    pub fn audio_mut(&mut self) -> &mut AudioState {
+        &mut self.audio
+    }
+
+    // save_state writes display & keys. audio is cpu state, set again by CPU::sync_audio
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.display.is_high_res());
+        writer.write_u8(self.display.planes);
+        writer.write_u16(self.keypad.pressed_keys);
+        writer.write_u16(self.keypad.released_keys);
+        writer.write_bytes(self.display.visible());
+    }
+
+    // load_state marks the whole display as cleared & updated, so it is redrawn
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), StateError> {
+        let high_res = reader.read_bool()?;
+        self.display.set_high_res(high_res);
+
+        self.display.planes = reader.read_u8()? & ALL_PLANES;
+        self.keypad.pressed_keys = reader.read_u16()?;
+        self.keypad.released_keys = reader.read_u16()?;
+
+        let display = &mut self.display;
+        let size = display.width * display.height;
+        reader.read_into(&mut display.display_buffer[..size])?;
+
+        for i in 0..size {
+            display.updated[i] = 1;
+        }
+
+        Ok(())
+    }
+}
+
+// FrameBuffer is the display of IODevice, also usable on its own, with flags of pixels updated since the last frame
+pub struct FrameBuffer {
+    display_buffer: [u8; DISPLAY_SIZE],     // planes of each pixel
+    updated: [u8; DISPLAY_SIZE],            // 1 if pixel is updated
+
+    width: usize,
+    height: usize,
+    planes: u8,             // selected planes
 
     display_cleared: bool,
     display_updated: bool,
 
     quirk_wrap_sprite: bool,
+    quirk_res_clear: bool,
 }
 
-impl IODevice {
-    pub fn new() -> IODevice {
-        let display_buffer = [0; DISPLAY_SIZE];
-        let updated = [false; DISPLAY_SIZE];
-
-        IODevice {
-            display_buffer,
-            updated,
-            pressed_keys: 0,
-            released_keys: 0,
+impl FrameBuffer {
+    pub fn new() -> FrameBuffer {
+        FrameBuffer {
+            display_buffer: [0; DISPLAY_SIZE],
+            updated: [0; DISPLAY_SIZE],
+            width: DISPLAY_WIDTH,
+            height: DISPLAY_HEIGHT,
+            planes: PLANE_1,
             display_cleared: false,
             display_updated: false,
             quirk_wrap_sprite: false,
+            quirk_res_clear: true,
         }
     }
 
-    pub fn set_quirks(&mut self, clip_sprite: bool) {
-        self.quirk_wrap_sprite = clip_sprite;
-    }
-
-    pub fn reset(&mut self) {
-        self.clear_display();
-        self.pressed_keys = 0;
-        self.released_keys = 0;
+    fn reset(&mut self) {
+        self.width = DISPLAY_WIDTH;
+        self.height = DISPLAY_HEIGHT;
+        self.planes = PLANE_1;
+        self.clear_all();
         self.display_cleared = false;
         self.display_updated = false;
     }
 
-    pub fn reset_display_flags(&mut self) {
+    fn reset_flags(&mut self) {
         if !self.display_updated {
             return;
         }
 
         for i in 0..DISPLAY_SIZE {
-            self.updated[i] = false;
+            self.updated[i] = 0;
         }
 
         self.display_cleared = false;
         self.display_updated = false;
     }
 
-    pub fn get_display_update(&self) -> DisplayUpdate {
+    fn get_update(&self) -> DisplayUpdate {
         DisplayUpdate {
             display_ptr: self.display_buffer.as_ptr(),
             updated_ptr: self.updated.as_ptr(),
-            buffer_size: DISPLAY_SIZE,
+            buffer_size: self.width * self.height,
+            width: self.width,
+            height: self.height,
             display_cleared: self.display_cleared,
             display_updated: self.display_updated,
         }
     }
 
-    pub fn set_keys(&mut self, pressed_keys: u16, released_keys: u16) {
-        self.pressed_keys = pressed_keys;
-        self.released_keys = released_keys;
+    pub fn set_quirks(&mut self, quirks: &Quirks) {
+        self.quirk_wrap_sprite = quirks.wrap_sprite;
+        self.quirk_res_clear = quirks.res_clear;
+    }
+
+    // visible returns the planes of the visible pixels, row by row
+    pub fn visible(&self) -> &[u8] {
+        &self.display_buffer[..self.width * self.height]
     }
 }
 
-impl IOInterface for IODevice {
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for FrameBuffer {
     fn clear_display(&mut self) {
+        if self.planes == ALL_PLANES {
+            self.clear_all();
+            return;
+        }
+
+        let mask = !self.planes;
+
+        for i in 0..self.width * self.height {
+            let color = self.display_buffer[i] & mask;
+            self.set_pixel(i, color);
+        }
+    }
+
+    fn draw_pixel(&mut self, x: u8, y: u8, plane: u8) -> u8 {
+        let (x, y) = (x as usize, y as usize);
+
+        if !self.quirk_wrap_sprite && ((x >= self.width) || (y >= self.height)) {
+            return 0;
+        }
+
+        let i = to_index(x, y, self.width, self.height);
+
+        let color = self.display_buffer[i] ^ plane;
+        self.display_buffer[i] = color;
+        self.updated[i] = 1;
+        self.display_updated = true;
+
+        (color & plane == 0) as u8
+    }
+
+    // pixels are bytes (the layout read through DisplayUpdate::display_ptr), so a row is XORed as one u64
+    fn draw_row(&mut self, x: u8, y: u8, row: u8, plane: u8) -> bool {
+        let (mut x, mut y) = (x as usize, y as usize);
+
+        if x >= self.width || y >= self.height {
+            if !self.quirk_wrap_sprite {
+                return false;
+            }
+
+            x %= self.width;
+            y %= self.height;
+        }
+
+        if row == 0 {
+            return false;
+        }
+
+        // pixels past the right edge, wrapped to the left edge
+        let split = (self.width - x).min(8);
+        let i = y * self.width;
+        let mut erased = self.xor_row(i + x, row & !((0xFF_u16 >> split) as u8), plane);
+
+        if split < 8 && self.quirk_wrap_sprite {
+            erased |= self.xor_row(i, row << split, plane);
+        }
+
+        erased
+    }
+
+    fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & ALL_PLANES;
+    }
+
+    fn selected_planes(&self) -> u8 {
+        self.planes
+    }
+
+    fn set_high_res(&mut self, enabled: bool) {
+        let (previous, previous_width) = (self.display_buffer, self.width);
+        let switched = enabled != self.is_high_res();
+
+        if enabled {
+            self.width = HIRES_DISPLAY_WIDTH;
+            self.height = HIRES_DISPLAY_HEIGHT;
+        } else {
+            self.width = DISPLAY_WIDTH;
+            self.height = DISPLAY_HEIGHT;
+        }
+
+        if self.quirk_res_clear {
+            self.clear_all();
+            return;
+        }
+
+        if !switched {
+            return;
+        }
+
+        // low resolution pixels become 2x2 pixels, high resolution ones keep the top left pixel of each 2x2
+        self.display_buffer = [0; DISPLAY_SIZE];
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let src = if enabled { (y / 2) * previous_width + x / 2 } else { 2 * y * previous_width + 2 * x };
+                self.display_buffer[y * self.width + x] = previous[src];
+            }
+        }
+
+        // every pixel moved, redraw the whole screen
+        for i in 0..DISPLAY_SIZE {
+            self.updated[i] = 1;
+        }
+
+        self.display_cleared = true;
+        self.display_updated = true;
+    }
+
+    fn is_high_res(&self) -> bool {
+        self.width == HIRES_DISPLAY_WIDTH
+    }
+
+    fn scroll_down(&mut self, n: u8) {
+        self.shift_display(0, n as isize);
+    }
+
+    fn scroll_up(&mut self, n: u8) {
+        self.shift_display(0, -(n as isize));
+    }
+
+    fn scroll_left(&mut self) {
+        self.shift_display(-4, 0);
+    }
+
+    fn scroll_right(&mut self) {
+        self.shift_display(4, 0);
+    }
+}
+
+impl FrameBuffer {
+    // clear_all clears every plane regardless of plane selection
+    fn clear_all(&mut self) {
         for i in 0..DISPLAY_SIZE {
             self.display_buffer[i] = 0;
-            self.updated[i] = false;
+            self.updated[i] = 0;
         }
 
         self.display_cleared = true;
         self.display_updated = true;
     }
 
-    fn draw_pixel(&mut self, x: u8, y: u8) -> u8 {
-        if !self.quirk_wrap_sprite && ((x > 63) || (y > 31)) {
-            return 0;
+    // shift_display moves the selected planes by (dx, dy) pixels. pixels moved out of screen are lost
+    fn shift_display(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width as isize, self.height as isize);
+        let planes = self.planes;
+        let previous = self.display_buffer;
+
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+
+                let shifted = if src_x >= 0 && src_x < width && src_y >= 0 && src_y < height {
+                    previous[(src_y * width + src_x) as usize] & planes
+                } else {
+                    0
+                };
+
+                let i = (y * width + x) as usize;
+                self.set_pixel(i, (previous[i] & !planes) | shifted);
+            }
         }
+    }
 
-        let i = to_index(x as usize, y as usize);
+    // set_pixel writes color to buffer index i, marking it updated only if it changes
+    fn set_pixel(&mut self, i: usize, color: u8) {
+        if self.display_buffer[i] == color {
+            return;
+        }
 
-        let color = self.display_buffer[i] ^ 1;
         self.display_buffer[i] = color;
-        self.updated[i] = true;
+        self.updated[i] = 1;
         self.display_updated = true;
+    }
+
+    // xor_row toggles `plane` of 8 pixels from buffer index i as one u64, returns true if any pixel is erased.
+    // row bits must not go past the end of the display row
+    fn xor_row(&mut self, i: usize, row: u8, plane: u8) -> bool {
+        let end = (i + 8).min(DISPLAY_SIZE);
+        let set = SPREAD_ROW[row as usize];
+        let mask = set * plane as u64;
+
+        let mut bytes = [0; 8];
+        bytes[..end - i].copy_from_slice(&self.display_buffer[i..end]);
+        let pixels = u64::from_le_bytes(bytes);
+        self.display_buffer[i..end].copy_from_slice(&(pixels ^ mask).to_le_bytes()[..end - i]);
+
+        bytes[..end - i].copy_from_slice(&self.updated[i..end]);
+        let updated = u64::from_le_bytes(bytes) | set;
+        self.updated[i..end].copy_from_slice(&updated.to_le_bytes()[..end - i]);
+        self.display_updated |= set != 0;
+
+        pixels & mask != 0
+    }
+}
+
+// SPREAD_ROW maps a sprite row to 8 pixel bytes of a little endian u64, 1 for each set bit, left most first
+const SPREAD_ROW : [u64; 256] = spread_rows();
+
+const fn spread_rows() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut row = 0;
+
+    while row < 256 {
+        let mut dx = 0;
+
+        while dx < 8 {
+            if row & (0x80 >> dx) != 0 {
+                table[row] |= 1 << (8 * dx);
+            }
+            dx += 1;
+        }
+        row += 1;
+    }
 
-        !color & 1
+    table
+}
+
+// KeyState is the keypad of IODevice, set by the host each frame
+pub struct KeyState {
+    pressed_keys: u16,      // mapping u16 of which key is currently pressed
+    released_keys: u16,     // mapping u16 of which key has just been released
+}
+
+impl KeyState {
+    pub fn new() -> KeyState {
+        KeyState { pressed_keys: 0, released_keys: 0 }
     }
 
+    // set_keys sets the pressed & just released key bit masks
+    pub fn set_keys(&mut self, pressed_keys: u16, released_keys: u16) {
+        self.pressed_keys = pressed_keys;
+        self.released_keys = released_keys;
+    }
+}
+
+impl Default for KeyState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Keypad for KeyState {
     fn key_pressed(&self, key: u8) -> bool {
         check_key(self.pressed_keys, key)
     }
@@ -146,9 +530,54 @@ impl IOInterface for IODevice {
     }
 }
 
+// AudioState is the audio of IODevice, polled by the web player each frame
+pub struct AudioState {
+    beeping: bool,
+    pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,     // None until program loads one
+    pitch: u8,
+}
+
+impl AudioState {
+    pub fn new() -> AudioState {
+        AudioState { beeping: false, pattern: None, pitch: DEFAULT_PITCH }
+    }
+
+    pub fn beeping(&self) -> bool {
+        self.beeping
+    }
+
+    pub fn pattern(&self) -> Option<&[u8; AUDIO_PATTERN_SIZE]> {
+        self.pattern.as_ref()
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+}
+
+impl Default for AudioState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Audio for AudioState {
+    fn set_beeping(&mut self, beeping: bool) {
+        self.beeping = beeping;
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; AUDIO_PATTERN_SIZE]) {
+        self.pattern = Some(*pattern);
+    }
+
+    fn set_pitch(&mut self, pitch: u8) {
+        self.pitch = pitch;
+    }
+}
+
 #[inline]
-fn to_index(x: usize, y: usize) -> usize {
-    (y % DISPLAY_HEIGHT) * DISPLAY_WIDTH + (x % DISPLAY_WIDTH)
+fn to_index(x: usize, y: usize, width: usize, height: usize) -> usize {
+    (y % height) * width + (x % width)
 }
 
 #[inline]
@@ -160,22 +589,28 @@ fn check_key(keys: u16, key: u8) -> bool {
 mod test {
     use wasm_bindgen_test::*;
     use super::*;
+    use crate::quirks::Platform;
 
     #[wasm_bindgen_test]
     fn test_to_index() {
-        let minus_one = std::usize::MAX;
-        let max_x = DISPLAY_WIDTH - 1;
-        let max_y = DISPLAY_HEIGHT - 1;
+        let w = DISPLAY_WIDTH;
+        let h = DISPLAY_HEIGHT;
+        let max_x = w - 1;
+        let max_y = h - 1;
 
         // normal case
-        let i = 5 * DISPLAY_WIDTH + 3;
-        assert_eq!(i, to_index(3, 5));
+        let i = 5 * w + 3;
+        assert_eq!(i, to_index(3, 5, w, h));
 
         // wrap-around cases
-        assert_eq!(to_index(0, 5), to_index(max_x + 1, 5)); // right
-        assert_eq!(to_index(max_x, 5), to_index(minus_one, 5)); // left
-        assert_eq!(to_index(5, max_y), to_index(5, minus_one)); // top
-        assert_eq!(to_index(5, 0), to_index(5, max_y + 1)); // top
+        assert_eq!(to_index(0, 5, w, h), to_index(max_x + 1, 5, w, h)); // right
+        assert_eq!(to_index(max_x, 5, w, h), to_index(w * 2 - 1, 5, w, h)); // left
+        assert_eq!(to_index(5, max_y, w, h), to_index(5, h * 2 - 1, w, h)); // top
+        assert_eq!(to_index(5, 0, w, h), to_index(5, max_y + 1, w, h)); // top
+
+        // high resolution
+        let i = 5 * HIRES_DISPLAY_WIDTH + 100;
+        assert_eq!(i, to_index(100, 5, HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT));
     }
 
     #[wasm_bindgen_test]
@@ -183,18 +618,177 @@ mod test {
         let mut device = IODevice::new();
 
         let x = 17; let y = 26;
-        let i = to_index(x as usize, y as usize);
+        let i = to_index(x as usize, y as usize, DISPLAY_WIDTH, DISPLAY_HEIGHT);
 
         // normal draw case
-        let result = device.draw_pixel(x, y);
+        let result = device.display.draw_pixel(x, y, PLANE_1);
 
-        assert_eq!(device.display_buffer[i], 1);
+        assert_eq!(device.display.display_buffer[i], 1);
         assert_eq!(result, 0);
 
         // erasure case
-        let result = device.draw_pixel(x, y);
+        let result = device.display.draw_pixel(x, y, PLANE_1);
 
-        assert_eq!(device.display_buffer[i], 0);
+        assert_eq!(device.display.display_buffer[i], 0);
         assert_eq!(result, 1);
     }
+
+    // PixelDisplay draws sprite rows pixel by pixel
+    struct PixelDisplay(FrameBuffer);
+
+    impl Display for PixelDisplay {
+        fn clear_display(&mut self) { self.0.clear_display() }
+        fn draw_pixel(&mut self, x: u8, y: u8, plane: u8) -> u8 { self.0.draw_pixel(x, y, plane) }
+        fn draw_row(&mut self, x: u8, y: u8, row: u8, plane: u8) -> bool {
+            let mut erased = 0;
+
+            for dx in (0..8).filter(|dx| row & (0x80 >> dx) != 0) {
+                erased |= self.0.draw_pixel(x + dx, y, plane);
+            }
+
+            erased != 0
+        }
+        fn select_planes(&mut self, planes: u8) { self.0.select_planes(planes) }
+        fn selected_planes(&self) -> u8 { self.0.selected_planes() }
+        fn set_high_res(&mut self, enabled: bool) { self.0.set_high_res(enabled) }
+        fn is_high_res(&self) -> bool { self.0.is_high_res() }
+        fn scroll_down(&mut self, n: u8) { self.0.scroll_down(n) }
+        fn scroll_up(&mut self, n: u8) { self.0.scroll_up(n) }
+        fn scroll_left(&mut self) { self.0.scroll_left() }
+        fn scroll_right(&mut self) { self.0.scroll_right() }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_draw_sprite() {
+        let rows = [0xFF, 0x81, 0x5A, 0x00, 0x3C, 0xA5, 0x01, 0x80];
+
+        // sprites at every edge position, clipped & wrapped, match drawing pixel by pixel
+        for &wrap in [false, true].iter() {
+            for &high_res in [false, true].iter() {
+                let mut fast = FrameBuffer::new();
+                let mut slow = PixelDisplay(FrameBuffer::new());
+                fast.quirk_wrap_sprite = wrap;
+                slow.0.quirk_wrap_sprite = wrap;
+                fast.set_high_res(high_res);
+                slow.set_high_res(high_res);
+
+                let (width, height) = (fast.width as u8, fast.height as u8);
+                let positions = [(0, 0), (3, 5), (width - 8, 2), (width - 5, height - 3), (width, 1), (width + 4, height - 1)];
+
+                for &(x, y) in positions.iter() {
+                    for &plane in [PLANE_1, PLANE_2].iter() {
+                        let erased = fast.draw_sprite(x, y, &rows, plane);
+                        assert_eq!(erased, slow.draw_sprite(x, y, &rows, plane), "x {} y {}", x, y);
+                        assert_eq!(fast.visible(), slow.0.visible(), "x {} y {}", x, y);
+                        assert_eq!(&fast.updated[..], &slow.0.updated[..]);
+                    }
+                }
+
+                // drawing twice on a clear display erases the sprite
+                fast.clear_all();
+                assert!(!fast.draw_sprite(3, 5, &rows, PLANE_1));
+                assert!(fast.draw_sprite(3, 5, &rows, PLANE_1));
+                assert!(fast.visible().iter().all(|&p| p == 0));
+            }
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_high_res() {
+        let mut device = IODevice::new();
+
+        device.display.draw_pixel(3, 3, PLANE_1);
+        device.display.set_high_res(true);
+
+        // switching resolution clears display
+        assert!(device.display.is_high_res());
+        assert_eq!(device.display.display_buffer[to_index(3, 3, HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT)], 0);
+
+        // pixels outside low resolution are drawable
+        let result = device.display.draw_pixel(100, 50, PLANE_1);
+        assert_eq!(result, 0);
+        assert_eq!(device.display.display_buffer[to_index(100, 50, HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT)], 1);
+
+        let update = device.get_display_update();
+        assert_eq!(update.width, HIRES_DISPLAY_WIDTH);
+        assert_eq!(update.height, HIRES_DISPLAY_HEIGHT);
+        assert_eq!(update.buffer_size, DISPLAY_SIZE);
+
+        device.display.set_high_res(false);
+        assert!(!device.display.is_high_res());
+        assert_eq!(device.get_display_update().buffer_size, DISPLAY_WIDTH * DISPLAY_HEIGHT);
+
+        // SCHIP 1.x keeps the picture, scaled to the new resolution
+        device.set_quirks(&Quirks::for_platform(Platform::SChip11));
+        device.display.draw_pixel(3, 4, PLANE_1);
+        device.display.set_high_res(true);
+
+        let lit: Vec<usize> = (0..DISPLAY_SIZE).filter(|&i| device.display.display_buffer[i] != 0).collect();
+        let block: Vec<usize> = [(6, 8), (7, 8), (6, 9), (7, 9)].iter()
+            .map(|&(x, y)| to_index(x, y, HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT))
+            .collect();
+        assert_eq!(lit, block);
+        assert!(device.get_display_update().display_cleared);
+
+        device.display.draw_pixel(127, 63, PLANE_1);
+        device.display.set_high_res(false);
+
+        let lit: Vec<usize> = (0..DISPLAY_SIZE).filter(|&i| device.display.display_buffer[i] != 0).collect();
+        assert_eq!(lit, vec![to_index(3, 4, DISPLAY_WIDTH, DISPLAY_HEIGHT)]);
+
+        // setting the current resolution again changes nothing
+        device.display.set_high_res(false);
+        assert_eq!(device.display.display_buffer[to_index(3, 4, DISPLAY_WIDTH, DISPLAY_HEIGHT)], PLANE_1);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_scroll() {
+        let mut device = IODevice::new();
+        let (w, h) = (DISPLAY_WIDTH, DISPLAY_HEIGHT);
+
+        device.display.draw_pixel(10, 10, PLANE_1);
+
+        device.display.scroll_down(3);
+        assert_eq!(device.display.display_buffer[to_index(10, 10, w, h)], 0);
+        assert_eq!(device.display.display_buffer[to_index(10, 13, w, h)], 1);
+
+        device.display.scroll_right();
+        assert_eq!(device.display.display_buffer[to_index(10, 13, w, h)], 0);
+        assert_eq!(device.display.display_buffer[to_index(14, 13, w, h)], 1);
+
+        device.display.scroll_left();
+        device.display.scroll_left();
+        assert_eq!(device.display.display_buffer[to_index(14, 13, w, h)], 0);
+        assert_eq!(device.display.display_buffer[to_index(6, 13, w, h)], 1);
+
+        // pixels scrolled out of the screen are lost
+        device.display.scroll_down(h as u8);
+        assert_eq!(device.display.display_buffer.iter().filter(|&&p| p == 1).count(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_planes() {
+        let mut device = IODevice::new();
+        let i = to_index(7, 8, DISPLAY_WIDTH, DISPLAY_HEIGHT);
+
+        // each plane holds its own bit
+        assert_eq!(device.display.draw_pixel(7, 8, PLANE_1), 0);
+        assert_eq!(device.display.draw_pixel(7, 8, PLANE_2), 0);
+        assert_eq!(device.display.display_buffer[i], 3);
+
+        assert_eq!(device.display.draw_pixel(7, 8, PLANE_2), 1);
+        assert_eq!(device.display.display_buffer[i], 1);
+
+        // clear and scroll only affect selected planes
+        device.display.draw_pixel(7, 8, PLANE_2);
+        device.display.select_planes(PLANE_2);
+        device.display.scroll_up(2);
+
+        assert_eq!(device.display.display_buffer[i], 1);
+        assert_eq!(device.display.display_buffer[to_index(7, 6, DISPLAY_WIDTH, DISPLAY_HEIGHT)], 2);
+
+        device.display.clear_display();
+        assert_eq!(device.display.display_buffer.iter().filter(|&&p| p != 0).count(), 1);
+        assert_eq!(device.display.display_buffer[i], 1);
+    }
 }
@@ -3,21 +3,53 @@ mod utils;
 mod cpu;
 mod iodevice;
 mod memory;
+mod quirks;
+mod debugger;
+mod savestate;
+mod rewind;
+mod backend;
+mod error;
+mod trace;
+mod movie;
+mod clock;
+mod pool;
+mod gym;
+pub mod disasm;
+pub mod assembler;
+pub mod aot;
 
 use wasm_bindgen::prelude::*;
-use cpu::ExecutionStatus;
+pub use cpu::{ExecutionStatus, TimingMode, Engine};
+pub use iodevice::{Display, Keypad, Audio, FrameBuffer, KeyState, AudioState};
+pub use quirks::{Platform, Quirks, LoadStore};
+pub use debugger::{BreakReason, Comparison, WatchKind, Condition};
+pub use savestate::{StateError, STATE_VERSION};
+pub use memory::PROGRAM_START;
+pub use backend::{Rng, Logger, XorShiftRng, StderrLogger, NullLogger};
+pub use cpu::instruction::Instruction;
+pub use error::{Chip8Error, Fault, ErrorReport, ErrorPolicy};
+pub use trace::{Tracer, TraceEntry, OpClass, Change, Effect};
+pub use movie::{Movie, FrameInput, MOVIE_VERSION};
+pub use clock::DEFAULT_IPS;
+pub use pool::{MachinePool, RewardTerm};
+pub use gym::{Env, EnvSpec, SpecError, Step, ScoreTerm, Counter, Location, GameOver};
+use disasm::Syntax;
 
 // use `wee_alloc` as the global allocator.
-#[cfg(feature = "wee_alloc")]
+#[cfg(all(feature = "wee_alloc", target_arch = "wasm32"))]
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
-const CPU_TICK_PER_FRAME : u8 = 9;
-
 #[wasm_bindgen]
 pub struct Machine {
     cpu: cpu::CPU,
     device: iodevice::IODevice,
+    debugger: debugger::Debugger,
+    rewind: rewind::Rewind,
+    recording: Option<Movie>,
+    replay: Option<movie::Replay>,
+    clock: clock::Clock,
+    frame_input: Option<FrameInput>,    // keys at the start of the current frame, None between frames
 }
 
 #[wasm_bindgen]
@@ -31,28 +63,278 @@ impl Machine {
         Machine {
             cpu,
             device,
+            debugger: debugger::Debugger::new(),
+            rewind: rewind::Rewind::new(0),
+            recording: None,
+            replay: None,
+            clock: clock::Clock::new(),
+            frame_input: None,
         }
     }
 
     /*** Runtime Related ***/
 
-    pub fn set_quirks(&mut self, shift: bool, loadstore: bool, wrap_sprite: bool) {
-        self.cpu.set_quirks(shift, loadstore);
-        self.device.set_quirks(wrap_sprite);
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.set_quirks(Quirks::for_platform(platform));
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.set_quirks(quirks);
+        self.device.set_quirks(&quirks);
+    }
+
+    pub fn get_quirks(&self) -> Quirks {
+        self.cpu.quirks()
+    }
+
+    // set_error_policy chooses between stopping on errors (development) or keeping bad roms running
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.cpu.set_error_policy(policy);
     }
 
+    pub fn get_error_policy(&self) -> ErrorPolicy {
+        self.cpu.error_policy()
+    }
+
+    // set_seed makes cxkk random numbers reproducible: the same seed and input give the same run
+    pub fn set_seed(&mut self, seed: u32) {
+        self.cpu.set_rng(Box::new(XorShiftRng::new(seed)));
+    }
+
+    // reset also stops movie recording & replay
     pub fn reset(&mut self) {
         self.cpu.reset();
         self.device.reset();
+        self.debugger.reset();
+        self.rewind.clear();
+        self.recording = None;
+        self.replay = None;
+        self.clock.restart();
+        self.frame_input = None;
     }
 
+    // set_instructions_per_second sets cpu speed, timers always run at 60 Hz. movies record the speed
+    // set before start_recording
+    pub fn set_instructions_per_second(&mut self, ips: u32) {
+        self.clock.set_ips(ips);
+    }
+
+    pub fn get_instructions_per_second(&self) -> u32 {
+        self.clock.ips()
+    }
+
+    // set_timing_mode chooses between the fixed instruction rate and VIP machine cycle costs, where speed
+    // is set by the VIP clock instead of set_instructions_per_second
+    pub fn set_timing_mode(&mut self, timing: TimingMode) {
+        self.cpu.set_timing(timing);
+        self.clock.set_timing(timing);
+    }
+
+    pub fn get_timing_mode(&self) -> TimingMode {
+        self.cpu.timing()
+    }
+
+    // update runs until the end of the current 60 Hz frame
     pub fn update(&mut self) -> ExecutionStatus {
         self.device.reset_display_flags();
+        self.run_until(clock::FRAME_TIME)
+    }
 
-        let status = self.update_cpu();
-        self.update_device();
+    // advance runs the instructions and timer ticks due in `elapsed_micros` of real time, so speed does not
+    // depend on how often it is called. a frame may span several calls, frames end on each 60 Hz timer tick
+    pub fn advance(&mut self, elapsed_micros: u32) -> ExecutionStatus {
+        self.device.reset_display_flags();
 
-        status
+        let mut remaining = elapsed_micros as u64 * 60;
+
+        loop {
+            let time = (self.clock.frame_time() + remaining).min(clock::FRAME_TIME);
+            remaining -= time - self.clock.frame_time();
+
+            let status = self.run_until(time);
+
+            if status != ExecutionStatus::OK || remaining == 0 {
+                return status;
+            }
+        }
+    }
+
+    // set_rewind_budget sets memory for rewind history in bytes, 0 disables rewind
+    pub fn set_rewind_budget(&mut self, bytes: usize) {
+        self.rewind.set_budget(bytes);
+    }
+
+    // rewind goes back up to `frames` frames, returns how many frames were actually rewound
+    // rewinding drops the rewound frames from movie recording
+    pub fn rewind(&mut self, frames: usize) -> usize {
+        let (state, rewound) = self.rewind.rewind(frames);
+
+        if rewound > 0 {
+            let state = state.to_vec();
+            self.restore_state(&state).expect("rewind state is valid");
+
+            if let Some(movie) = self.recording.as_mut() {
+                let len = movie.frames.len().saturating_sub(rewound);
+                movie.frames.truncate(len);
+            }
+
+            self.replay = None;
+        }
+
+        rewound
+    }
+
+    pub fn rewind_frames(&self) -> usize {
+        self.rewind.frames()
+    }
+
+    // save_state returns a snapshot of cpu, device, quirks & random generator. debugger state is not included
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = savestate::StateWriter::new();
+
+        self.cpu.quirks().save_state(&mut writer);
+        self.cpu.save_state(&mut writer);
+        self.device.save_state(&mut writer);
+
+        let rng_state = self.cpu.rng_state();
+        writer.write_bool(rng_state.is_some());
+        writer.write_u32(rng_state.unwrap_or(0));
+
+        writer.finish()
+    }
+
+    // load_state restores a snapshot from save_state. on error, machine is left unchanged.
+    // a loaded state can not be reproduced from recorded input, so it stops movie recording & replay
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        self.restore_state(state)?;
+        self.recording = None;
+        self.replay = None;
+        self.clock.restart();
+        self.frame_input = None;
+
+        Ok(())
+    }
+
+    // state_hash is a checksum of the whole machine state, equal states have equal hashes.
+    // it is the crc32 trailer of save_state: hashing the whole state, trailer included, gives a constant
+    pub fn state_hash(&self) -> u32 {
+        let state = self.save_state();
+        let trailer = &state[state.len() - 4..];
+
+        u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]])
+    }
+
+    /*** Movie ***/
+
+    // start_recording resets machine and records key input of each frame from now on, using `seed` for
+    // random numbers. rom must be freshly loaded, as the movie identifies it by program memory
+    pub fn start_recording(&mut self, seed: u32) {
+        self.reset();
+        self.set_seed(seed);
+
+        self.recording = Some(Movie {
+            rom_hash: self.rom_hash(),
+            memory_size: self.cpu.memory_size(),
+            seed,
+            error_policy: self.cpu.error_policy(),
+            quirks: self.cpu.quirks(),
+            ips: self.clock.ips(),
+            timing: self.cpu.timing(),
+            frames: Vec::new(),
+            final_hash: 0,
+        });
+    }
+
+    // stop_recording returns the movie file of the recorded frames, None if not recording
+    pub fn stop_recording(&mut self) -> Option<Vec<u8>> {
+        let mut movie = self.recording.take()?;
+        movie.final_hash = self.state_hash();
+
+        Some(movie.to_bytes())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // start_replay resets machine with the movie settings, then each update plays the next recorded frame.
+    // the movie's rom must be loaded and memory size set beforehand
+    pub fn start_replay(&mut self, movie: &[u8]) -> Result<(), StateError> {
+        let movie = Movie::from_bytes(movie)?;
+
+        if movie.memory_size != self.cpu.memory_size() {
+            return Err(StateError::InvalidData("memory size does not match movie"));
+        }
+
+        if movie.rom_hash != self.rom_hash() {
+            return Err(StateError::InvalidData("rom does not match movie"));
+        }
+
+        self.reset();
+        self.set_seed(movie.seed);
+        self.set_quirks(movie.quirks);
+        self.cpu.set_error_policy(movie.error_policy);
+        self.clock.set_ips(movie.ips);
+        self.set_timing_mode(movie.timing);
+
+        self.replay = Some(movie::Replay::new(movie));
+
+        Ok(())
+    }
+
+    // is_replaying returns true until every frame of the movie is played
+    pub fn is_replaying(&self) -> bool {
+        self.replay.as_ref().is_some_and(|replay| replay.remaining() > 0)
+    }
+
+    // replay_matches returns true when replay has ended in the same state as the recording
+    pub fn replay_matches(&self) -> bool {
+        match &self.replay {
+            Some(replay) => replay.remaining() == 0 && replay.movie().final_hash == self.state_hash(),
+            None => false,
+        }
+    }
+
+    fn rom_hash(&self) -> u32 {
+        savestate::crc32(&self.cpu.memory()[PROGRAM_START..])
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut reader = savestate::StateReader::new(state)?;
+        let mut cpu = cpu::CPU::new();
+        let mut device = iodevice::IODevice::new();
+
+        // version 3 adds the RPL limit quirk, version 4 the resolution clear quirk
+        let added = reader.version().saturating_sub(2);
+        let quirks = Quirks::load_state(&mut reader, added)?;
+        cpu.load_state(&mut reader)?;
+        device.load_state(&mut reader)?;
+
+        // random generator is a host setting, only its state is restored, when saved & the generator supports it
+        let rng_state = if reader.version() >= 2 {
+            let saved = reader.read_bool()?;
+            let state = reader.read_u32()?;
+            saved.then_some(state)
+        } else {
+            None
+        };
+
+        reader.finish()?;
+
+        cpu.set_quirks(quirks);
+        cpu.sync_audio(device.audio_mut());
+        device.set_quirks(&quirks);
+
+        let previous = std::mem::replace(&mut self.cpu, cpu);
+        self.cpu.adopt_host(previous);
+        self.device = device;
+
+        if let Some(state) = rng_state {
+            self.cpu.set_rng_state(state);
+        }
+        self.debugger.reset();
+
+        Ok(())
     }
 
     /*** Memory & Device Related ***/
@@ -62,7 +344,12 @@ impl Machine {
     }
 
     pub fn max_rom_size(&self) -> usize {
-        memory::MEM_SIZE - memory::PROGRAM_START + 1
+        self.cpu.memory_size() - memory::PROGRAM_START + 1
+    }
+
+    // set_memory_size sets memory size up to 64 KiB (XO-CHIP). rom pointer must be requested again after this
+    pub fn set_memory_size(&mut self, size: usize) {
+        self.cpu.set_memory_size(size);
     }
 
     pub fn get_display_update(&self) -> iodevice::DisplayUpdate {
@@ -73,33 +360,309 @@ impl Machine {
         self.device.set_keys(pressed_keys, released_keys);
     }
 
+    // disassemble returns up to `count` instructions from `addr` in live memory, one instruction per line
+    pub fn disassemble(&self, addr: usize, count: usize, syntax: Syntax) -> String {
+        let lines: Vec<String> = disasm::disassemble_memory(self.cpu.memory(), addr, count, syntax)
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+
+        lines.join("\n")
+    }
+
     pub fn is_beeping(&self) -> bool {
-        self.cpu.beeping()
+        self.device.audio().beeping()
+    }
+
+    pub fn has_audio_pattern(&self) -> bool {
+        self.device.audio().pattern().is_some()
+    }
+
+    pub fn get_audio_pattern_ptr(&self) -> *const u8 {
+        match self.device.audio().pattern() {
+            Some(pattern) => pattern.as_ptr(),
+            None => std::ptr::null(),
+        }
+    }
+
+    pub fn get_pitch(&self) -> u8 {
+        self.device.audio().pitch()
+    }
+
+    // get_playback_rate returns audio pattern sample rate in Hz: 4000 * 2 ^ ((pitch - 64) / 48)
+    pub fn get_playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.get_pitch() as f64 - 64.0) / 48.0)
+    }
+
+    /*** Debugger Related ***/
+
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.debugger.add_breakpoint(addr, None);
+    }
+
+    // add_conditional_breakpoint stops at addr only when `V[register] comparison value` holds
+    pub fn add_conditional_breakpoint(&mut self, addr: usize, register: u8, comparison: Comparison, value: u8) {
+        self.debugger.add_breakpoint(addr, Some(Condition { register, comparison, value }));
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) {
+        self.debugger.remove_breakpoint(addr);
+    }
+
+    // add_watchpoint stops before an instruction accesses memory in start..=end
+    pub fn add_watchpoint(&mut self, start: usize, end: usize, kind: WatchKind) {
+        self.debugger.add_watchpoint(start, end, kind);
+    }
+
+    pub fn remove_watchpoint(&mut self, start: usize, end: usize) {
+        self.debugger.remove_watchpoint(start, end);
+    }
+
+    // clear_breakpoints removes all breakpoints & watchpoints
+    pub fn clear_breakpoints(&mut self) {
+        self.debugger.clear();
+    }
+
+    // resume & step_* take effect on the next update, which returns Breakpoint when stopped again
+    pub fn resume(&mut self) {
+        self.debugger.resume();
+    }
+
+    pub fn step_into(&mut self) {
+        self.debugger.step_into();
+    }
+
+    pub fn step_over(&mut self) {
+        self.debugger.step_over(&self.cpu);
+    }
+
+    pub fn step_out(&mut self) {
+        self.debugger.step_out(&self.cpu);
+    }
+
+    pub fn get_break_reason(&self) -> BreakReason {
+        self.debugger.reason()
+    }
+
+    pub fn get_break_addr(&self) -> usize {
+        self.debugger.break_addr()
+    }
+
+    pub fn get_pc(&self) -> usize {
+        self.cpu.pc()
+    }
+
+    pub fn get_index(&self) -> usize {
+        self.cpu.ir()
+    }
+
+    pub fn get_register(&self, x: usize) -> u8 {
+        self.cpu.register(x & 0xF)
+    }
+
+    pub fn get_stack_depth(&self) -> usize {
+        self.cpu.sp()
+    }
+
+    /*** Trace ***/
+
+    // enable_trace starts recording executed instructions, keeping the last `capacity` entries
+    pub fn enable_trace(&mut self, capacity: usize) {
+        self.cpu.set_tracer(Some(trace::Tracer::new(capacity)));
+    }
+
+    pub fn disable_trace(&mut self) {
+        self.cpu.set_tracer(None);
+    }
+
+    pub fn clear_trace(&mut self) {
+        if let Some(tracer) = self.cpu.tracer_mut() {
+            tracer.clear();
+        }
+    }
+
+    // set_trace_range only records instructions at start..=end
+    pub fn set_trace_range(&mut self, start: usize, end: usize) {
+        if let Some(tracer) = self.cpu.tracer_mut() {
+            tracer.set_range(start, end);
+        }
+    }
+
+    pub fn clear_trace_range(&mut self) {
+        if let Some(tracer) = self.cpu.tracer_mut() {
+            tracer.clear_range();
+        }
+    }
+
+    pub fn set_trace_class(&mut self, class: OpClass, enabled: bool) {
+        if let Some(tracer) = self.cpu.tracer_mut() {
+            tracer.set_class(class, enabled);
+        }
+    }
+
+    // export_trace returns recorded entries as text lines, or as a JSON array
+    pub fn export_trace(&self, json: bool) -> String {
+        match self.cpu.tracer() {
+            Some(tracer) if json => tracer.to_json(),
+            Some(tracer) => tracer.to_text(),
+            None => String::new(),
+        }
+    }
+
+    // get_error returns details of the error that stopped the last update with RuntimeError
+    pub fn get_error(&self) -> Option<ErrorReport> {
+        self.cpu.error().map(ErrorReport::from)
+    }
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Machine {
-    fn update_cpu(&mut self) -> ExecutionStatus {
-        let mut status = ExecutionStatus::OK;
+    pub fn set_rng(&mut self, rng: Box<dyn Rng>) {
+        self.cpu.set_rng(rng);
+    }
 
-        for _ in 0..CPU_TICK_PER_FRAME {
-            status = self.cpu.tick(&mut self.device);
+    pub fn set_logger(&mut self, logger: Box<dyn Logger>) {
+        self.cpu.set_logger(logger);
+    }
+
+    // load_rom copies rom into memory, returns false if it is too big. memory size must be set before loading
+    pub fn load_rom(&mut self, rom: &[u8]) -> bool {
+        self.cpu.load_rom(rom)
+    }
+
+    // restart powers on again with rom loaded & random numbers seeded, keeping settings & breakpoints.
+    // unlike reset, memory is cleared. returns false if rom is too big
+    pub fn restart(&mut self, rom: &[u8], seed: u32) -> bool {
+        self.reset();
+        self.set_seed(seed);
+
+        let memory = memory::allocate_memory(self.memory().len());
+        self.memory_mut().copy_from_slice(&memory);
+        self.load_rom(rom)
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        self.cpu.memory()
+    }
+
+    // memory_mut allows patching memory, e.g. a test rom option below PROGRAM_START
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        self.cpu.memory_mut()
+    }
+
+    // set_engine chooses between the interpreter and the basic block engine, which runs the same faster
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.cpu.set_engine(engine);
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.cpu.engine()
+    }
+
+    // set_decode_cache turns caching of decoded instructions on or off, e.g. to compare speed. on by default
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.cpu.set_decode_cache(enabled);
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.cpu.tracer()
+    }
+
+    // error returns the error that stopped the last update with RuntimeError, cleared on reset
+    pub fn error(&self) -> Option<&Chip8Error> {
+        self.cpu.error()
+    }
+
+    // display returns the visible display buffer, one byte per pixel holding its plane bits
+    pub fn display(&self) -> &[u8] {
+        self.device.display()
+    }
+
+    // run_until runs the current frame up to `time`, ending it when `time` reaches the end of frame
+    fn run_until(&mut self, time: u64) -> ExecutionStatus {
+        if self.frame_input.is_none() {
+            self.begin_frame();
+        }
+
+        let status = self.update_cpu(time);
+
+        // a breakpoint stops mid frame, the frame continues on the next call
+        if status == ExecutionStatus::Breakpoint {
+            return status;
+        }
+
+        self.clock.set_frame_time(time);
+
+        if time == clock::FRAME_TIME || status != ExecutionStatus::OK {
+            self.end_frame();
+        }
+
+        status
+    }
+
+    fn begin_frame(&mut self) {
+        // replayed movie overrides keys given by set_keys
+        if let Some(input) = self.replay.as_mut().and_then(|replay| replay.next_input()) {
+            self.device.set_keys(input.pressed, input.released);
+        }
+
+        let (pressed, released) = self.device.keys();
+        self.frame_input = Some(FrameInput { pressed, released });
+    }
+
+    fn end_frame(&mut self) {
+        self.cpu.update_timer(self.device.audio_mut());
+        self.update_device();
+        self.clock.end_frame();
+
+        if let (Some(movie), Some(input)) = (self.recording.as_mut(), self.frame_input) {
+            movie.frames.push(input);
+        }
+
+        self.frame_input = None;
+
+        if self.rewind.is_enabled() {
+            self.rewind.capture(self.save_state());
+        }
+    }
+
+    // update_cpu runs the instructions due at `time` of the current frame
+    fn update_cpu(&mut self, time: u64) -> ExecutionStatus {
+        while self.clock.pending(time) > 0 {
+            // stopping leaves timers untouched
+            if self.debugger.is_active() && self.debugger.check(&self.cpu, self.device.selected_planes()) {
+                return ExecutionStatus::Breakpoint;
+            }
+
+            let (display, keypad, audio) = self.device.devices();
+
+            // the debugger checks every instruction, so it steps one at a time
+            let (status, spent) = if self.debugger.is_active() {
+                self.cpu.step(display, keypad, audio)
+            } else {
+                self.cpu.run(self.clock.pending(time), display, keypad, audio)
+            };
+
+            self.clock.spend(spent);
 
             match status {
-                ExecutionStatus::OK => (), // continue,
+                ExecutionStatus::OK => self.debugger.executed(),
 
-                // other than ok, break loop
-                ExecutionStatus::WaitForKey => {
-                    status = ExecutionStatus::OK;
+                // waiting skips the rest of the frame
+                ExecutionStatus::WaitForKey | ExecutionStatus::WaitForVBlank => {
+                    self.clock.set_idle();
                     break;
                 },
-                _ => { break; }
+                _ => return status,
             }
         }
 
-        self.cpu.update_timer();
-
-        status
+        ExecutionStatus::OK
     }
 
     fn update_device(&mut self) {
@@ -1,20 +1,27 @@
-use std::ptr;
-
 pub const MEM_SIZE : usize = 4096;
 pub const PROGRAM_START : usize = 0x200;
 
-pub type Memory = [u8; MEM_SIZE];
+// XO-CHIP extended memory size
+pub const MAX_MEM_SIZE : usize = 0x10000;
+
+pub type Memory = Vec<u8>;
 
 const DIGIT_SPRITE_SIZE : usize = 80;
 const DIGIT_SPRITE : [u8; DIGIT_SPRITE_SIZE] = [0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0, 0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0, 0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80, 0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80];
 
-pub fn allocate_memory() -> Memory {
-    let mut mem = [0; MEM_SIZE];
+// SCHIP 8x10 sprite for digit 0-9, located right after the small digit sprite
+pub const BIG_DIGIT_SPRITE_START : usize = DIGIT_SPRITE_SIZE;
+const BIG_DIGIT_SPRITE_SIZE : usize = 100;
+const BIG_DIGIT_SPRITE : [u8; BIG_DIGIT_SPRITE_SIZE] = [0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, 0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, 0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, 0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, 0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, 0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, 0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, 0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, 0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, 0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C];
+
+// allocate_memory allocates `size` bytes of memory, clamped between MEM_SIZE and MAX_MEM_SIZE
+pub fn allocate_memory(size: usize) -> Memory {
+    let size = size.clamp(MEM_SIZE, MAX_MEM_SIZE);
+    let mut mem = vec![0; size];
 
     // initialized digit sprite
-    unsafe {
-        ptr::copy(DIGIT_SPRITE.as_ptr(), mem.as_mut_ptr(), DIGIT_SPRITE_SIZE);
-    }
+    mem[..DIGIT_SPRITE_SIZE].copy_from_slice(&DIGIT_SPRITE);
+    mem[BIG_DIGIT_SPRITE_START..BIG_DIGIT_SPRITE_START + BIG_DIGIT_SPRITE_SIZE].copy_from_slice(&BIG_DIGIT_SPRITE);
 
     mem
 }
@@ -26,10 +33,25 @@ mod test {
 
     #[wasm_bindgen_test]
     fn test_allocate() {
-        let mem = allocate_memory();
+        let mem = allocate_memory(MEM_SIZE);
+
+        assert_eq!(mem.len(), MEM_SIZE);
 
         for i in 0..DIGIT_SPRITE_SIZE {
             assert_eq!(mem[i], DIGIT_SPRITE[i]);
         }
+
+        for i in 0..BIG_DIGIT_SPRITE_SIZE {
+            assert_eq!(mem[BIG_DIGIT_SPRITE_START + i], BIG_DIGIT_SPRITE[i]);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_allocate_size() {
+        assert_eq!(allocate_memory(0x8000).len(), 0x8000);
+
+        // clamped to supported size
+        assert_eq!(allocate_memory(0).len(), MEM_SIZE);
+        assert_eq!(allocate_memory(0x20000).len(), MAX_MEM_SIZE);
     }
 }
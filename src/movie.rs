@@ -0,0 +1,279 @@
+/****
+ * Movie: key input of a recorded session, replayed to reproduce the session exactly
+ *
+ *   magic "C8MV" | version u16 | rom hash u32 | memory size | seed u32 | error policy u8 | quirks
+ *   | instructions per second u32 (v2) | timing mode u8 (v3) | frame count | (pressed u16, released u16) per frame
+ *   | final state hash u32 | crc32
+ *
+ * Quirks include the RPL limit quirk since v4, and the resolution clear quirk since v5.
+ *
+ * A run only depends on rom, memory size, quirks, error policy, speed, random seed and the keys of each frame,
+ * so replaying a movie on the same rom must end in the same state, checked with the final state hash.
+ * Rom hash is the CRC-32 of program memory (PROGRAM_START to the end of memory) right after loading.
+ */
+
+use crate::clock::DEFAULT_IPS;
+use crate::cpu::TimingMode;
+use crate::error::ErrorPolicy;
+use crate::quirks::Quirks;
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+const MAGIC : &[u8; 4] = b"C8MV";
+pub const MOVIE_VERSION : u16 = 5;
+
+// FrameInput is the key state given to one frame, see Machine::set_keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameInput {
+    pub pressed: u16,
+    pub released: u16,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u32,
+    pub memory_size: usize,
+    pub seed: u32,
+    pub error_policy: ErrorPolicy,
+    pub quirks: Quirks,
+    pub ips: u32,
+    pub timing: TimingMode,
+    pub frames: Vec<FrameInput>,
+    pub final_hash: u32,    // state hash after the last frame
+}
+
+impl Movie {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::with_header(MAGIC, MOVIE_VERSION);
+
+        writer.write_u32(self.rom_hash);
+        writer.write_usize(self.memory_size);
+        writer.write_u32(self.seed);
+        writer.write_u8(self.error_policy as u8);
+        self.quirks.save_state(&mut writer);
+        writer.write_u32(self.ips);
+        writer.write_u8(self.timing as u8);
+
+        writer.write_usize(self.frames.len());
+
+        for input in self.frames.iter() {
+            writer.write_u16(input.pressed);
+            writer.write_u16(input.released);
+        }
+
+        writer.write_u32(self.final_hash);
+        writer.finish()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, StateError> {
+        let mut reader = StateReader::with_header(data, MAGIC, MOVIE_VERSION)?;
+
+        let rom_hash = reader.read_u32()?;
+        let memory_size = reader.read_usize()?;
+        let seed = reader.read_u32()?;
+
+        let error_policy = match reader.read_u8()? {
+            0 => ErrorPolicy::Strict,
+            1 => ErrorPolicy::Lenient,
+            2 => ErrorPolicy::Wrap,
+            _ => return Err(StateError::InvalidData("error policy")),
+        };
+
+        // version 4 adds the RPL limit quirk, version 5 the resolution clear quirk
+        let added = reader.version().saturating_sub(3);
+        let quirks = Quirks::load_state(&mut reader, added)?;
+
+        // version 1 movies ran at the fixed default speed
+        let ips = if reader.version() >= 2 { reader.read_u32()? } else { DEFAULT_IPS };
+
+        let timing = match if reader.version() >= 3 { reader.read_u8()? } else { 0 } {
+            0 => TimingMode::Fixed,
+            1 => TimingMode::CosmacVip,
+            _ => return Err(StateError::InvalidData("timing mode")),
+        };
+
+        // each frame takes 4 bytes, check count before allocating
+        let count = reader.read_usize()?;
+
+        if count > data.len() / 4 {
+            return Err(StateError::Truncated);
+        }
+
+        let mut frames = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            frames.push(FrameInput { pressed: reader.read_u16()?, released: reader.read_u16()? });
+        }
+
+        let final_hash = reader.read_u32()?;
+        reader.finish()?;
+
+        Ok(Movie { rom_hash, memory_size, seed, error_policy, quirks, ips, timing, frames, final_hash })
+    }
+}
+
+// Replay feeds movie input to the machine frame by frame
+pub struct Replay {
+    movie: Movie,
+    frame: usize,
+}
+
+impl Replay {
+    pub fn new(movie: Movie) -> Replay {
+        Replay { movie, frame: 0 }
+    }
+
+    // next_input returns the keys of the next frame, or None when the movie has ended
+    pub fn next_input(&mut self) -> Option<FrameInput> {
+        let input = self.movie.frames.get(self.frame).copied();
+
+        if input.is_some() {
+            self.frame += 1;
+        }
+
+        input
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.movie.frames.len() - self.frame
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+    use crate::{Machine, Platform};
+    use crate::assembler::assemble;
+
+    // waits for a key, then draws its digit at a random position
+    const PROGRAM : &str = "
+        loop:
+            LD V0, K
+            LD F, V0
+            RND V1, 0x3F
+            RND V2, 0x1F
+            DRW V1, V2, 5
+            JP loop
+    ";
+
+    fn program_machine() -> Machine {
+        let mut machine = Machine::new();
+        machine.set_platform(Platform::SChip11);
+        machine.load_rom(&assemble(PROGRAM).unwrap().rom);
+        machine
+    }
+
+    #[wasm_bindgen_test]
+    fn test_movie_bytes() {
+        let movie = Movie {
+            rom_hash: 0x1234_5678,
+            memory_size: 0x1000,
+            seed: 42,
+            error_policy: ErrorPolicy::Lenient,
+            quirks: Quirks::for_platform(Platform::XoChip),
+            ips: 1000,
+            timing: TimingMode::CosmacVip,
+            frames: vec![FrameInput { pressed: 1, released: 0 }, FrameInput { pressed: 0, released: 1 }],
+            final_hash: 0xCAFE,
+        };
+
+        let bytes = movie.to_bytes();
+        assert_eq!(&bytes[..4], b"C8MV");
+        assert_eq!(Movie::from_bytes(&bytes), Ok(movie));
+
+        assert_eq!(Movie::from_bytes(&bytes[..bytes.len() - 1]).err(), Some(StateError::ChecksumMismatch));
+        assert_eq!(Movie::from_bytes(&Machine::new().save_state()).err(), Some(StateError::InvalidMagic));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_record_replay() {
+        let mut machine = program_machine();
+        machine.start_recording(1234);
+
+        for frame in 0..40 {
+            let key = frame / 4 % 16;
+
+            // press a key every other 4 frames
+            if frame % 8 < 4 {
+                machine.set_keys(1 << key, 0);
+            } else {
+                machine.set_keys(0, 1 << (key - 1));
+            }
+
+            machine.update();
+        }
+
+        let display = machine.display().to_vec();
+        let movie = machine.stop_recording().unwrap();
+        assert!(!machine.is_recording());
+
+        // replay in a fresh machine with the same rom
+        let mut replayed = program_machine();
+        replayed.start_replay(&movie).unwrap();
+
+        while replayed.is_replaying() {
+            replayed.set_keys(0xFFFF, 0);     // ignored while replaying
+            replayed.update();
+        }
+
+        assert!(replayed.replay_matches());
+        assert_eq!(replayed.display(), &display[..]);
+
+        // tampered key input, each key moved to the next one, ends in another state
+        let mut tampered = Movie::from_bytes(&movie).unwrap();
+
+        for input in tampered.frames.iter_mut() {
+            input.pressed <<= 1;
+            input.released <<= 1;
+        }
+
+        let mut replayed = program_machine();
+        replayed.start_replay(&tampered.to_bytes()).unwrap();
+
+        while replayed.is_replaying() {
+            replayed.update();
+        }
+
+        assert!(!replayed.replay_matches());
+
+        // different rom is rejected
+        let mut other = Machine::new();
+        other.load_rom(&[0x12, 0x00]);
+        assert_eq!(other.start_replay(&movie), Err(StateError::InvalidData("rom does not match movie")));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_record_rewind_replay() {
+        let mut machine = program_machine();
+        machine.set_rewind_budget(1 << 20);
+        machine.start_recording(99);
+
+        // draws at random positions, rewinds over some of them and draws again
+        for frame in 0..60 {
+            machine.set_keys(1 << (frame % 16), !(1 << (frame % 16)));
+            machine.update();
+
+            if frame == 30 {
+                assert_eq!(machine.rewind(10), 10);
+            }
+        }
+
+        let display = machine.display().to_vec();
+        let movie = machine.stop_recording().unwrap();
+        assert_eq!(Movie::from_bytes(&movie).unwrap().frames.len(), 50);
+
+        let mut replayed = program_machine();
+        replayed.start_replay(&movie).unwrap();
+
+        while replayed.is_replaying() {
+            replayed.update();
+        }
+
+        assert!(replayed.replay_matches());
+        assert_eq!(replayed.display(), &display[..]);
+    }
+}
@@ -0,0 +1,311 @@
+/****
+ * Machine pool: many machines running one rom, stepped together a frame at a time
+ *
+ * Made for reinforcement learning, where agents play millions of frames. step() takes the keys of each machine
+ * and leaves observations, rewards & halted flags in buffers allocated once by new(), so stepping doesn't
+ * allocate. Engine::BasicBlock is faster but allocates each block the first time its address is run, which
+ * for a whole game takes many frames. Observations are one contiguous array, machine after machine, one byte
+ * per pixel holding its plane bits like Machine::display. Reward is the change of weighted memory bytes, e.g.
+ * the score, over the step.
+ */
+
+use crate::{Machine, ExecutionStatus, Quirks};
+use crate::iodevice::{DISPLAY_WIDTH, DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT};
+
+// RewardTerm is a memory byte counting towards the reward
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RewardTerm {
+    pub addr: usize,
+    pub weight: f32,
+}
+
+pub struct MachinePool {
+    machines: Vec<Machine>,
+    rom: Vec<u8>,
+    reward_terms: Vec<RewardTerm>,
+    high_res: bool,                 // observation resolution
+
+    observations: Vec<u8>,
+    rewards: Vec<f32>,
+    halted: Vec<bool>,
+    statuses: Vec<ExecutionStatus>,
+    held: Vec<u16>,                 // keys pressed in the previous step
+    scores: Vec<f32>,               // weighted reward terms after the previous step
+}
+
+impl MachinePool {
+    // new creates `count` machines with rom loaded, machine i seeded with seed + i. None if rom is too big
+    pub fn new(rom: &[u8], quirks: Quirks, count: usize, seed: u32) -> Option<MachinePool> {
+        let mut machines = Vec::with_capacity(count);
+
+        for i in 0..count {
+            let mut machine = Machine::new();
+            machine.set_quirks(quirks);
+            machine.set_seed(seed.wrapping_add(i as u32));
+
+            if !machine.load_rom(rom) {
+                return None;
+            }
+
+            machines.push(machine);
+        }
+
+        let mut pool = MachinePool {
+            machines,
+            rom: rom.to_vec(),
+            reward_terms: Vec::new(),
+            high_res: false,
+            observations: Vec::new(),
+            rewards: vec![0.0; count],
+            halted: vec![false; count],
+            statuses: vec![ExecutionStatus::OK; count],
+            held: vec![0; count],
+            scores: vec![0.0; count],
+        };
+
+        pool.set_high_res_observations(false);
+        Some(pool)
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    // machine_mut gives access to settings of one machine, e.g. speed, engine or error policy
+    pub fn machine(&self, i: usize) -> &Machine {
+        &self.machines[i]
+    }
+
+    pub fn machine_mut(&mut self, i: usize) -> &mut Machine {
+        &mut self.machines[i]
+    }
+
+    // set_reward sets the memory bytes whose weighted sum is the score, rewards are its change in each step
+    pub fn set_reward(&mut self, terms: &[RewardTerm]) {
+        self.reward_terms = terms.to_vec();
+
+        for i in 0..self.machines.len() {
+            self.scores[i] = self.score(i);
+        }
+    }
+
+    // set_high_res_observations chooses observations of 128x64 pixels, low resolution pixels doubled, instead of
+    // 64x32 pixels, high resolution pixels merged by 2x2
+    pub fn set_high_res_observations(&mut self, high_res: bool) {
+        self.high_res = high_res;
+
+        let (width, height) = self.observation_size();
+        self.observations = vec![0; width * height * self.machines.len()];
+
+        for i in 0..self.machines.len() {
+            self.observe(i);
+        }
+    }
+
+    // observation_size returns (width, height) of the observation of each machine
+    pub fn observation_size(&self) -> (usize, usize) {
+        if self.high_res {
+            (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT)
+        } else {
+            (DISPLAY_WIDTH, DISPLAY_HEIGHT)
+        }
+    }
+
+    // reset powers machine i on again with rom loaded & random numbers seeded, keeping its settings
+    pub fn reset(&mut self, i: usize, seed: u32) {
+        self.machines[i].restart(&self.rom, seed);
+
+        self.rewards[i] = 0.0;
+        self.halted[i] = false;
+        self.statuses[i] = ExecutionStatus::OK;
+        self.held[i] = 0;
+        self.scores[i] = self.score(i);
+        self.observe(i);
+    }
+
+    // step runs one frame of every machine still running, with keys[i] the key bit mask pressed on machine i.
+    // keys pressed in the previous step and not anymore are released. halted machines stay as they are, with
+    // no reward, until reset
+    pub fn step(&mut self, keys: &[u16]) {
+        assert_eq!(keys.len(), self.machines.len(), "one key mask per machine");
+
+        for (i, &pressed) in keys.iter().enumerate() {
+            self.rewards[i] = 0.0;
+
+            if self.halted[i] {
+                continue;
+            }
+
+            let machine = &mut self.machines[i];
+            machine.set_keys(pressed, self.held[i] & !pressed);
+            self.held[i] = pressed;
+
+            let status = machine.update();
+            self.statuses[i] = status;
+            self.halted[i] = matches!(status, ExecutionStatus::Halt | ExecutionStatus::RuntimeError);
+
+            let score = self.score(i);
+            self.rewards[i] = score - self.scores[i];
+            self.scores[i] = score;
+
+            self.observe(i);
+        }
+    }
+
+    // observations returns the observations of all machines, each observation_size pixels row by row
+    pub fn observations(&self) -> &[u8] {
+        &self.observations
+    }
+
+    pub fn observation(&self, i: usize) -> &[u8] {
+        let (width, height) = self.observation_size();
+        let size = width * height;
+        &self.observations[i * size..(i + 1) * size]
+    }
+
+    pub fn rewards(&self) -> &[f32] {
+        &self.rewards
+    }
+
+    // halted returns true for machines stopped by halt or runtime error
+    pub fn halted(&self) -> &[bool] {
+        &self.halted
+    }
+
+    // statuses returns the status of the last frame of each machine
+    pub fn statuses(&self) -> &[ExecutionStatus] {
+        &self.statuses
+    }
+
+    fn score(&self, i: usize) -> f32 {
+        let memory = self.machines[i].memory();
+
+        self.reward_terms.iter()
+            .map(|term| memory.get(term.addr).map_or(0.0, |&byte| byte as f32 * term.weight))
+            .sum()
+    }
+
+    // observe copies the display of machine i into its observation, scaled to the observation size
+    fn observe(&mut self, i: usize) {
+        let (width, height) = self.observation_size();
+        let display = self.machines[i].display();
+        let observation = &mut self.observations[i * width * height..(i + 1) * width * height];
+
+        if display.len() == observation.len() {
+            observation.copy_from_slice(display);
+        } else if self.high_res {
+            for (pos, pixel) in observation.iter_mut().enumerate() {
+                let (x, y) = (pos % width, pos / width);
+                *pixel = display[(y / 2) * DISPLAY_WIDTH + x / 2];
+            }
+        } else {
+            for (pos, pixel) in observation.iter_mut().enumerate() {
+                let top = (pos / width) * 2 * HIRES_DISPLAY_WIDTH + (pos % width) * 2;
+                let bottom = top + HIRES_DISPLAY_WIDTH;
+                *pixel = display[top] | display[top + 1] | display[bottom] | display[bottom + 1];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+    use crate::assembler::assemble;
+
+    // counts frames at 0x300 until key 5 is pressed, then draws a dot and halts on key 6
+    const PROGRAM : &str = "
+            :const COUNTER 0x300
+        loop:
+            LD I, COUNTER
+            LD V0, [I]
+            ADD V0, 1
+            LD I, COUNTER
+            LD [I], V0
+            LD V1, 1
+            LD DT, V1
+        wait:
+            LD V1, DT
+            SE V1, 0
+            JP wait
+            LD V2, 5
+            SKP V2
+            JP loop
+            LD I, dot
+            DRW V3, V3, 1
+            LD V2, 6
+        halt:
+            SKP V2
+            JP halt
+            EXIT
+        dot:
+            :byte 0x80
+    ";
+
+    #[wasm_bindgen_test]
+    fn test_step() {
+        let rom = assemble(PROGRAM).unwrap().rom;
+        let mut pool = MachinePool::new(&rom, Quirks::new(), 3, 1).unwrap();
+        pool.set_reward(&[RewardTerm { addr: 0x300, weight: 0.5 }]);
+
+        // one count per frame, the delay timer wait takes the rest of it
+        for i in 0..pool.len() {
+            pool.machine_mut(i).set_instructions_per_second(6000);
+        }
+
+        assert_eq!(pool.len(), 3);
+        assert_eq!(pool.observation_size(), (64, 32));
+        assert_eq!(pool.observations().len(), 3 * 64 * 32);
+
+        pool.step(&[0, 0, 0]);
+        assert_eq!(pool.rewards(), &[0.5, 0.5, 0.5]);
+
+        // machine 1 draws, machine 2 draws and halts
+        pool.step(&[0, 1 << 5, 1 << 5]);
+        pool.step(&[0, 0, 1 << 6]);
+
+        assert_eq!(pool.halted(), &[false, false, true]);
+        assert_eq!(pool.statuses()[2], ExecutionStatus::Halt);
+        assert_eq!(pool.rewards(), &[0.5, 0.0, 0.0]);
+        assert_eq!(pool.observation(0)[0], 0);
+        assert_eq!(pool.observation(1)[0], 1);
+        assert_eq!(pool.observation(2)[0], 1);
+
+        // halted machines don't run until reset
+        pool.step(&[0, 0, 0]);
+        assert_eq!(pool.statuses()[2], ExecutionStatus::Halt);
+
+        pool.reset(2, 7);
+        assert_eq!(pool.halted(), &[false, false, false]);
+        assert_eq!(pool.machine(2).memory()[0x300], 0);
+        assert_eq!(pool.observation(2)[0], 0);
+
+        pool.step(&[0, 0, 0]);
+        assert_eq!(pool.rewards(), &[0.5, 0.0, 0.5]);
+
+        // low resolution pixels are doubled
+        pool.set_high_res_observations(true);
+        assert_eq!(pool.observation_size(), (128, 64));
+        assert_eq!(&pool.observation(1)[..3], &[1, 1, 0]);
+        assert_eq!(&pool.observation(1)[128..131], &[1, 1, 0]);
+
+        assert!(MachinePool::new(&vec![0; 0x1000], Quirks::new(), 1, 0).is_none());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_empty() {
+        let rom = assemble(PROGRAM).unwrap().rom;
+        let mut pool = MachinePool::new(&rom, Quirks::new(), 0, 1).unwrap();
+
+        // nothing to step nor observe, in either resolution
+        pool.step(&[]);
+        pool.set_high_res_observations(true);
+        assert!(pool.is_empty());
+        assert!(pool.observations().is_empty());
+    }
+}
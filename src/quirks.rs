@@ -0,0 +1,240 @@
+use wasm_bindgen::prelude::*;
+use crate::savestate::{StateReader, StateWriter, StateError};
+
+// HP48 calculators only had 8 RPL user flags for fx75 & fx85, XO-CHIP (Octo) has 16
+const HP48_RPL_FLAGS : usize = 8;
+const RPL_FLAGS : usize = 16;
+
+// Platform is a known CHIP-8 target whose behaviour can be emulated
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    CosmacVip,
+    Chip48,
+    SChip10,
+    SChip11,
+    SChipModern,
+    XoChip,
+}
+
+// LoadStore is how fx55 & fx65 change I register after storing / loading V0..Vx
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadStore {
+    IncrementXPlusOne,  // I += x + 1
+    IncrementX,         // I += x
+    Unchanged,          // I is not changed
+}
+
+// Quirks bundles every behavioural difference between platforms.
+// Start from a preset (`for_platform`) and override individual fields if needed
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    pub vf_reset: bool,         // 8xy1, 8xy2 & 8xy3 reset VF to 0
+    pub jump_vx: bool,          // bnnn jumps to xnn + Vx instead of nnn + V0
+    pub display_wait: bool,     // dxyn waits for vertical blank before drawing in low resolution
+    pub wrap_sprite: bool,      // sprites drawn outside display wrap around instead of being clipped
+    pub loadstore: LoadStore,   // I register change on fx55 & fx65
+    pub shift_vx: bool,         // 8xy6 & 8xyE shift Vx instead of Vy
+    pub rpl_limit: bool,        // fx75 & fx85 can only use 8 RPL flags instead of 16
+    pub res_clear: bool,        // 00FE & 00FF clear the display, instead of keeping the picture scaled
+}
+
+#[wasm_bindgen]
+impl Quirks {
+    // new creates default quirks: the original behaviour of this interpreter
+    pub fn new() -> Quirks {
+        Quirks {
+            vf_reset: false,
+            jump_vx: false,
+            display_wait: false,
+            wrap_sprite: false,
+            loadstore: LoadStore::IncrementXPlusOne,
+            shift_vx: false,
+            rpl_limit: false,
+            res_clear: true,
+        }
+    }
+
+    pub fn for_platform(platform: Platform) -> Quirks {
+        match platform {
+            Platform::CosmacVip => Quirks {
+                vf_reset: true,
+                display_wait: true,
+                ..Quirks::new()
+            },
+
+            Platform::Chip48 => Quirks {
+                jump_vx: true,
+                loadstore: LoadStore::IncrementX,
+                shift_vx: true,
+                ..Quirks::new()
+            },
+
+            // SCHIP 1.0 kept CHIP-48's bxnn jump & I += x, SCHIP 1.1 leaves I unchanged.
+            // neither clears the display when switching resolution
+            Platform::SChip10 => Quirks {
+                display_wait: true,
+                jump_vx: true,
+                loadstore: LoadStore::IncrementX,
+                shift_vx: true,
+                rpl_limit: true,
+                res_clear: false,
+                ..Quirks::new()
+            },
+
+            Platform::SChip11 => Quirks {
+                display_wait: true,
+                jump_vx: true,
+                loadstore: LoadStore::Unchanged,
+                shift_vx: true,
+                rpl_limit: true,
+                res_clear: false,
+                ..Quirks::new()
+            },
+
+            // SCHIP as run by modern interpreters (e.g. Octo): no display wait nor VF reset, sprites clipped, 16 flags
+            Platform::SChipModern => Quirks {
+                vf_reset: false,
+                display_wait: false,
+                wrap_sprite: false,
+                jump_vx: true,
+                loadstore: LoadStore::Unchanged,
+                shift_vx: true,
+                rpl_limit: false,
+                res_clear: true,
+            },
+
+            Platform::XoChip => Quirks {
+                wrap_sprite: true,
+                ..Quirks::new()
+            },
+        }
+    }
+}
+
+impl Quirks {
+    // rpl_flags is the number of RPL flags fx75 & fx85 can use
+    pub fn rpl_flags(&self) -> usize {
+        if self.rpl_limit { HP48_RPL_FLAGS } else { RPL_FLAGS }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.vf_reset);
+        writer.write_bool(self.jump_vx);
+        writer.write_bool(self.display_wait);
+        writer.write_bool(self.wrap_sprite);
+        writer.write_u8(self.loadstore as u8);
+        writer.write_bool(self.shift_vx);
+        writer.write_bool(self.rpl_limit);
+        writer.write_bool(self.res_clear);
+    }
+
+    // load_state reads quirks saved by save_state. `added` is the number of quirks the file has beyond the first
+    // six: 1 with the RPL limit, 2 with the resolution clear. files without them behave as they were recorded:
+    // fx75 & fx85 limited to 8 flags, 00FE & 00FF clearing the display
+    pub fn load_state(reader: &mut StateReader, added: u16) -> Result<Quirks, StateError> {
+        Ok(Quirks {
+            vf_reset: reader.read_bool()?,
+            jump_vx: reader.read_bool()?,
+            display_wait: reader.read_bool()?,
+            wrap_sprite: reader.read_bool()?,
+            loadstore: match reader.read_u8()? {
+                0 => LoadStore::IncrementXPlusOne,
+                1 => LoadStore::IncrementX,
+                2 => LoadStore::Unchanged,
+                _ => return Err(StateError::InvalidData("loadstore quirk")),
+            },
+            shift_vx: reader.read_bool()?,
+            rpl_limit: if added >= 1 { reader.read_bool()? } else { true },
+            res_clear: if added >= 2 { reader.read_bool()? } else { true },
+        })
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+
+    #[wasm_bindgen_test]
+    fn test_presets() {
+        let vip = Quirks::for_platform(Platform::CosmacVip);
+        assert!(vip.vf_reset && vip.display_wait && !vip.shift_vx);
+        assert_eq!(vip.loadstore, LoadStore::IncrementXPlusOne);
+
+        let schip = Quirks::for_platform(Platform::SChip11);
+        assert!(schip.jump_vx && schip.shift_vx && !schip.wrap_sprite);
+        assert_eq!(schip.loadstore, LoadStore::Unchanged);
+
+        let xochip = Quirks::for_platform(Platform::XoChip);
+        assert!(xochip.wrap_sprite && !xochip.jump_vx);
+
+        // overriding one field keeps the rest of the preset
+        let custom = Quirks { wrap_sprite: true, ..schip };
+        assert!(custom.wrap_sprite && custom.jump_vx);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_platform_differences() {
+        let platforms = [Platform::CosmacVip, Platform::Chip48, Platform::SChip10, Platform::SChip11,
+            Platform::SChipModern, Platform::XoChip];
+
+        // no two platforms share a preset
+        for (i, &a) in platforms.iter().enumerate() {
+            for &b in platforms[i + 1..].iter() {
+                assert_ne!(Quirks::for_platform(a), Quirks::for_platform(b), "{:?} & {:?}", a, b);
+            }
+        }
+
+        // SCHIP 1.0 is CHIP-48 with display wait, SCHIP 1.1 stops incrementing I
+        let chip48 = Quirks::for_platform(Platform::Chip48);
+        let schip10 = Quirks::for_platform(Platform::SChip10);
+        let schip11 = Quirks::for_platform(Platform::SChip11);
+        assert_eq!(Quirks { display_wait: true, rpl_limit: true, res_clear: false, ..chip48 }, schip10);
+        assert_eq!(Quirks { loadstore: LoadStore::Unchanged, ..schip10 }, schip11);
+
+        // modern SCHIP is SCHIP 1.1 without display wait
+        let modern = Quirks::for_platform(Platform::SChipModern);
+        assert!(schip11.display_wait && !modern.display_wait);
+        assert!(!modern.vf_reset && !modern.wrap_sprite);
+        assert_eq!(Quirks { display_wait: false, rpl_limit: false, res_clear: true, ..schip11 }, modern);
+
+        // only the HP48 based SCHIP versions have 8 RPL flags
+        assert_eq!((schip10.rpl_flags(), schip11.rpl_flags()), (8, 8));
+        assert_eq!((modern.rpl_flags(), Quirks::for_platform(Platform::XoChip).rpl_flags()), (16, 16));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_save_state() {
+        let modern = Quirks::for_platform(Platform::SChipModern);
+
+        let mut writer = StateWriter::new();
+        modern.save_state(&mut writer);
+        let data = writer.finish();
+
+        let mut reader = StateReader::new(&data).unwrap();
+        assert_eq!(Quirks::load_state(&mut reader, 2), Ok(modern));
+
+        // files without the added quirks keep the 8 flags & display clear they were recorded with
+        let mut writer = StateWriter::new();
+        for &flag in [false, true, false, false].iter() {
+            writer.write_bool(flag);
+        }
+        writer.write_u8(LoadStore::Unchanged as u8);
+        writer.write_bool(true);
+        let data = writer.finish();
+
+        let mut reader = StateReader::new(&data).unwrap();
+        assert_eq!(Quirks::load_state(&mut reader, 0), Ok(Quirks { rpl_limit: true, ..modern }));
+    }
+}
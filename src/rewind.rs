@@ -0,0 +1,278 @@
+/****
+ * Rewind buffer
+ *
+ * Keeps the latest save state in full, and older ones as deltas going backward in time:
+ * deltas[0] turns the latest state into the one before it, deltas[1] turns that into the one before, etc.
+ * A delta is the XOR of two states encoded as (zero run, literal run) pairs, so unchanged bytes cost almost nothing.
+ * Oldest deltas are dropped when the buffer exceeds its memory budget.
+ */
+
+use std::collections::VecDeque;
+
+const DELTA_XOR : u8 = 0;
+const DELTA_FULL : u8 = 1;     // states of different size, delta holds the older state as is
+
+pub struct Rewind {
+    budget: usize,              // max bytes held, including the latest state
+    current: Vec<u8>,
+    deltas: VecDeque<Vec<u8>>,
+    used: usize,                // bytes held by deltas
+}
+
+impl Rewind {
+    pub fn new(budget: usize) -> Rewind {
+        Rewind {
+            budget,
+            current: Vec::new(),
+            deltas: VecDeque::new(),
+            used: 0,
+        }
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.trim();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.budget > 0
+    }
+
+    pub fn clear(&mut self) {
+        self.current.clear();
+        self.deltas.clear();
+        self.used = 0;
+    }
+
+    // frames returns how many frames can be rewound
+    pub fn frames(&self) -> usize {
+        self.deltas.len()
+    }
+
+    // memory_used returns bytes held, including the latest state
+    pub fn memory_used(&self) -> usize {
+        self.used + self.current.len()
+    }
+
+    // capture records state as the latest frame
+    pub fn capture(&mut self, state: Vec<u8>) {
+        if !self.current.is_empty() {
+            let delta = encode_delta(&state, &self.current);
+            self.used += delta.len();
+            self.deltas.push_front(delta);
+        }
+
+        self.current = state;
+        self.trim();
+    }
+
+    // rewind goes back up to `frames` frames, dropping the newer ones. returns the state and frames rewound
+    pub fn rewind(&mut self, frames: usize) -> (&[u8], usize) {
+        let frames = frames.min(self.deltas.len());
+
+        for _ in 0..frames {
+            if let Some(delta) = self.deltas.pop_front() {
+                self.used -= delta.len();
+                self.current = apply_delta(&self.current, &delta);
+            }
+        }
+
+        (&self.current, frames)
+    }
+
+    fn trim(&mut self) {
+        while self.memory_used() > self.budget {
+            match self.deltas.pop_back() {
+                Some(delta) => self.used -= delta.len(),
+                None => break,
+            }
+        }
+
+        if self.budget == 0 {
+            self.clear();
+        }
+    }
+}
+
+// encode_delta returns delta that turns `from` into `to`
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+
+    if from.len() != to.len() {
+        delta.push(DELTA_FULL);
+        delta.extend_from_slice(to);
+        return delta;
+    }
+
+    delta.push(DELTA_XOR);
+
+    let mut i = 0;
+
+    while i < from.len() {
+        let zero_start = i;
+
+        while i < from.len() && from[i] == to[i] {
+            i += 1;
+        }
+
+        let literal_start = i;
+
+        while i < from.len() && from[i] != to[i] {
+            i += 1;
+        }
+
+        write_varint(&mut delta, literal_start - zero_start);
+        write_varint(&mut delta, i - literal_start);
+
+        for j in literal_start..i {
+            delta.push(from[j] ^ to[j]);
+        }
+    }
+
+    delta
+}
+
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    if delta[0] == DELTA_FULL {
+        return delta[1..].to_vec();
+    }
+
+    let mut state = from.to_vec();
+    let mut pos = 1;
+    let mut i = 0;
+
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let literal = read_varint(delta, &mut pos);
+
+        for byte in &mut state[i..i + literal] {
+            *byte ^= delta[pos];
+            pos += 1;
+        }
+
+        i += literal;
+    }
+
+    state
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+
+        value |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+    use crate::Machine;
+    use crate::assembler::assemble;
+
+    #[wasm_bindgen_test]
+    fn test_delta() {
+        let mut from = vec![0u8; 1000];
+        let mut to = from.clone();
+        to[0] = 1;
+        to[500] = 0xFF;
+        to[501] = 0x0F;
+        to[999] = 7;
+
+        let delta = encode_delta(&from, &to);
+        assert!(delta.len() < 20);
+        assert_eq!(apply_delta(&from, &delta), to);
+
+        // identical & different size states
+        assert_eq!(apply_delta(&from, &encode_delta(&from, &from)), from);
+
+        from.truncate(10);
+        assert_eq!(apply_delta(&from, &encode_delta(&from, &to)), to);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_rewind_budget() {
+        let mut rewind = Rewind::new(300);
+
+        for i in 0..20u8 {
+            rewind.capture(vec![i; 50]);
+        }
+
+        assert!(rewind.memory_used() <= 300);
+
+        let available = rewind.frames();
+        assert_eq!(available, 4);
+
+        let (state, frames) = rewind.rewind(3);
+        assert_eq!((state, frames), (&[16u8; 50][..], 3));
+
+        let (state, frames) = rewind.rewind(100);
+        assert_eq!(frames, available - 3);
+        assert_eq!(state, &[19 - available as u8; 50][..]);
+
+        rewind.set_budget(0);
+        assert!(!rewind.is_enabled());
+        assert_eq!(rewind.memory_used(), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_machine_rewind() {
+        let rom = assemble("
+                LD I, sprite
+            loop:
+                DRW V0, V0, 1
+                ADD V0, 1
+                JP loop
+            sprite:
+                :byte 0x80
+        ").unwrap().rom;
+
+        let mut machine = Machine::new();
+
+        assert!(machine.load_rom(&rom));
+
+        machine.set_rewind_budget(1 << 20);
+
+        let mut states = Vec::new();
+
+        for _ in 0..10 {
+            machine.update();
+            states.push(machine.save_state());
+        }
+
+        assert_eq!(machine.rewind_frames(), 9);
+
+        assert_eq!(machine.rewind(4), 4);
+        assert_eq!(machine.save_state(), states[5]);
+
+        // history continues from the rewound frame
+        machine.update();
+        assert_eq!(machine.save_state(), states[6]);
+        assert_eq!(machine.rewind_frames(), 6);
+
+        assert_eq!(machine.rewind(100), 6);
+        assert_eq!(machine.save_state(), states[0]);
+
+        machine.reset();
+        assert_eq!(machine.rewind_frames(), 0);
+    }
+}
@@ -0,0 +1,324 @@
+/****
+ * Save state binary format
+ *
+ *   magic "C8ST" | version u16 | payload | crc32 u32 of everything before it
+ *
+ * Integers are little endian. Payload is written by each component in order (quirks, cpu, device), then
+ * the random generator state (v2). Version 3 adds the RPL limit quirk & widens RPL flags from 8 to 16 bytes,
+ * version 4 adds the resolution clear quirk.
+ * Other binary files (movies) reuse this container with their own magic & version.
+ * A field added in a later version must be read only when `reader.version()` is high enough,
+ * so states saved by older versions can still be loaded.
+ */
+
+use std::error::Error;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+const MAGIC : &[u8; 4] = b"C8ST";
+pub const STATE_VERSION : u16 = 4;
+
+const HEADER_SIZE : usize = 6;
+const CHECKSUM_SIZE : usize = 4;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateError {
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    ChecksumMismatch,
+    Truncated,
+    InvalidData(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::InvalidMagic => write!(f, "unrecognized file format"),
+            StateError::UnsupportedVersion(v) => write!(f, "unsupported save state version {}", v),
+            StateError::ChecksumMismatch => write!(f, "save state is corrupted"),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::InvalidData(field) => write!(f, "invalid data: {}", field),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+impl From<StateError> for JsValue {
+    fn from(err: StateError) -> JsValue {
+        JsValue::from_str(&err.to_string())
+    }
+}
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter::with_header(MAGIC, STATE_VERSION)
+    }
+
+    pub fn with_header(magic: &[u8; 4], version: u16) -> StateWriter {
+        let mut data = Vec::new();
+        data.extend_from_slice(magic);
+        data.extend_from_slice(&version.to_le_bytes());
+
+        StateWriter { data }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // write_usize writes value as u32, enough for any address or size in 64 KiB memory
+    pub fn write_usize(&mut self, value: usize) {
+        self.data.extend_from_slice(&(value as u32).to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    // finish appends checksum and returns the state
+    pub fn finish(mut self) -> Vec<u8> {
+        let checksum = crc32(&self.data);
+        self.data.extend_from_slice(&checksum.to_le_bytes());
+        self.data
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct StateReader<'a> {
+    payload: &'a [u8],
+    pos: usize,
+    version: u16,
+}
+
+impl<'a> StateReader<'a> {
+    // new validates header & checksum, then reads from the start of payload
+    pub fn new(data: &'a [u8]) -> Result<StateReader<'a>, StateError> {
+        StateReader::with_header(data, MAGIC, STATE_VERSION)
+    }
+
+    // with_header reads a file with `magic`, accepting versions up to `max_version`
+    pub fn with_header(data: &'a [u8], magic: &[u8; 4], max_version: u16) -> Result<StateReader<'a>, StateError> {
+        if data.len() < HEADER_SIZE + CHECKSUM_SIZE {
+            return Err(if data.starts_with(magic) { StateError::Truncated } else { StateError::InvalidMagic });
+        }
+
+        if &data[..4] != magic {
+            return Err(StateError::InvalidMagic);
+        }
+
+        let version = u16::from_le_bytes([data[4], data[5]]);
+
+        if version == 0 || version > max_version {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let (content, checksum) = data.split_at(data.len() - CHECKSUM_SIZE);
+
+        if crc32(content) != u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) {
+            return Err(StateError::ChecksumMismatch);
+        }
+
+        Ok(StateReader { payload: &content[HEADER_SIZE..], pos: 0, version })
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidData("bool")),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize, StateError> {
+        Ok(self.read_u32()? as usize)
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.payload.len() - self.pos < len {
+            return Err(StateError::Truncated);
+        }
+
+        let bytes = &self.payload[self.pos..self.pos + len];
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    pub fn read_into(&mut self, buffer: &mut [u8]) -> Result<(), StateError> {
+        buffer.copy_from_slice(self.read_bytes(buffer.len())?);
+        Ok(())
+    }
+
+    // finish checks that the whole payload has been read
+    pub fn finish(self) -> Result<(), StateError> {
+        if self.pos == self.payload.len() {
+            Ok(())
+        } else {
+            Err(StateError::InvalidData("trailing bytes"))
+        }
+    }
+}
+
+// crc32 computes IEEE CRC-32 checksum
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for &byte in data {
+        crc ^= byte as u32;
+
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+    use crate::{Machine, ErrorPolicy};
+    use crate::assembler::assemble;
+
+    #[wasm_bindgen_test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_reader_writer() {
+        let mut writer = StateWriter::new();
+        writer.write_u8(0xAB);
+        writer.write_bool(true);
+        writer.write_u16(0x1234);
+        writer.write_usize(0xFFFF);
+        writer.write_bytes(&[1, 2, 3]);
+
+        let state = writer.finish();
+        assert_eq!(&state[..4], b"C8ST");
+
+        let mut reader = StateReader::new(&state).unwrap();
+        assert_eq!(reader.version(), STATE_VERSION);
+        assert_eq!(reader.read_u8(), Ok(0xAB));
+        assert_eq!(reader.read_bool(), Ok(true));
+        assert_eq!(reader.read_u16(), Ok(0x1234));
+        assert_eq!(reader.read_usize(), Ok(0xFFFF));
+        assert_eq!(reader.read_bytes(3), Ok(&[1u8, 2, 3][..]));
+        assert_eq!(reader.read_u8(), Err(StateError::Truncated));
+        assert_eq!(reader.finish(), Ok(()));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_invalid_state() {
+        let state = StateWriter::new().finish();
+
+        assert_eq!(StateReader::new(b"garbage data").err(), Some(StateError::InvalidMagic));
+        assert_eq!(StateReader::new(&state[..8]).err(), Some(StateError::Truncated));
+
+        let mut corrupted = state.clone();
+        corrupted[5] ^= 1;
+        assert_eq!(StateReader::new(&corrupted).err(), Some(StateError::UnsupportedVersion(STATE_VERSION | 0x100)));
+
+        let mut corrupted = state.clone();
+        corrupted[8] ^= 1;
+        assert_eq!(StateReader::new(&corrupted).err(), Some(StateError::ChecksumMismatch));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_machine_state() {
+        let rom = assemble("
+                HIGH
+                LD V0, 10
+                LD I, sprite
+            loop:
+                DRW V0, V0, 1
+                ADD V0, 1
+                CALL sub
+                JP loop
+            sub:
+                RET
+            sprite:
+                :byte 0xF0
+        ").unwrap().rom;
+
+        let mut machine = Machine::new();
+        machine.set_memory_size(0x2000);
+
+        assert!(machine.load_rom(&rom));
+
+        machine.update();
+        let state = machine.save_state();
+        let (pc, v0) = (machine.get_pc(), machine.get_register(0));
+        let display = machine.display().to_vec();
+
+        machine.update();
+        machine.update();
+        assert_ne!(machine.get_register(0), v0);
+
+        // restore into the running machine, and into a fresh one with different memory size
+        let mut fresh = Machine::new();
+        machine.set_error_policy(ErrorPolicy::Lenient);
+
+        for m in [&mut machine, &mut fresh] {
+            m.load_state(&state).unwrap();
+
+            assert_eq!((m.get_pc(), m.get_register(0)), (pc, v0));
+            assert_eq!(m.memory().len(), 0x2000);
+            assert_eq!(m.display(), &display[..]);
+            assert!(m.get_display_update().display_cleared);
+        }
+
+        // error policy is a host setting, kept when loading
+        assert_eq!(machine.get_error_policy(), ErrorPolicy::Lenient);
+
+        // failed load leaves machine untouched
+        let mut truncated = state[..state.len() - 10].to_vec();
+        truncated.extend_from_slice(&crc32(&truncated).to_le_bytes());
+
+        assert_eq!(machine.load_state(&truncated), Err(StateError::Truncated));
+        assert_eq!(machine.get_pc(), pc);
+    }
+}
@@ -0,0 +1,526 @@
+/****
+ * Execution trace: bounded log of executed instructions with their effects
+ *
+ * Tracer is owned by the CPU and only exists while tracing is enabled, so a disabled trace costs a single
+ * check per instruction. Each entry holds the changes to V registers, I & timers, and the calls made to
+ * the device. Entries can be filtered by address range & opcode class, and exported as text or JSON
+ */
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+use crate::cpu::{ExecutionStatus, AUDIO_PATTERN_SIZE};
+use crate::cpu::instruction::Instruction;
+use crate::disasm::{self, Syntax};
+use crate::iodevice::{Display, Keypad, Audio};
+
+// OpClass is a group of instructions, used to filter trace entries
+#[wasm_bindgen]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpClass {
+    Flow,           // jumps, calls, returns & register skips
+    Arithmetic,     // register loads, ALU & random
+    Memory,         // I register & memory load / store
+    Display,        // drawing, scrolling, resolution & planes
+    Input,          // key skips & waits
+    Timer,          // delay & sound timers
+    Audio,          // audio pattern & pitch
+    Unknown,
+}
+
+impl OpClass {
+    pub fn of(instruction: Instruction) -> OpClass {
+        use Instruction::*;
+
+        match instruction {
+            Ret | Exit | Sys(_) | Jump(_) | Call(_) | JumpV(_)
+            | SkipEq(..) | SkipNeq(..) | SkipEqV(..) | SkipNeqV(..) => OpClass::Flow,
+
+            Load(..) | Incr(..) | Set(..) | Or(..) | And(..) | Xor(..) | Add(..)
+            | Sub(..) | Shr(..) | SubN(..) | Shl(..) | Rand(..) => OpClass::Arithmetic,
+
+            SaveRange(..) | LoadRange(..) | LoadI(_) | LoadLong(_) | AddI(_) | Digit(_) | BigDigit(_)
+            | Bcd(_) | Store(_) | LoadReg(_) | SaveRpl(_) | LoadRpl(_) => OpClass::Memory,
+
+            ScrollDown(_) | ScrollUp(_) | ScrollRight | ScrollLeft | Cls
+            | LowRes | HighRes | Draw(..) | Plane(_) => OpClass::Display,
+
+            SkipKey(_) | SkipNotKey(_) | WaitKey(_) => OpClass::Input,
+            ReadDt(_) | LoadDt(_) | LoadSt(_) => OpClass::Timer,
+            Audio | Pitch(_) => OpClass::Audio,
+            Unknown(_) => OpClass::Unknown,
+        }
+    }
+
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+// Change is a register, I or timer value changed by an instruction: (old, new)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Register(u8, u8, u8),   // (x, old, new)
+    Index(usize, usize),
+    Delay(u8, u8),
+    Sound(u8, u8),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Register(x, old, new) => write!(f, "V{:X}:{:02X}->{:02X}", x, old, new),
+            Change::Index(old, new) => write!(f, "I:{:04X}->{:04X}", old, new),
+            Change::Delay(old, new) => write!(f, "DT:{:02X}->{:02X}", old, new),
+            Change::Sound(old, new) => write!(f, "ST:{:02X}->{:02X}", old, new),
+        }
+    }
+}
+
+// Effect is a device call made by an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Effect {
+    Clear,
+    Draw { pixels: usize, erased: bool },   // all pixels toggled by one dxyn
+    ScrollDown(u8),
+    ScrollUp(u8),
+    ScrollLeft,
+    ScrollRight,
+    SelectPlanes(u8),
+    HighRes(bool),
+    KeyCheck { key: u8, pressed: bool },
+    KeyRead(u8),                            // fx0a result, NO_KEY when still waiting
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Effect::Clear => write!(f, "clear"),
+            Effect::Draw { pixels, erased } => {
+                write!(f, "draw {}px{}", pixels, if *erased { " erased" } else { "" })
+            },
+            Effect::ScrollDown(n) => write!(f, "scroll down {}", n),
+            Effect::ScrollUp(n) => write!(f, "scroll up {}", n),
+            Effect::ScrollLeft => write!(f, "scroll left"),
+            Effect::ScrollRight => write!(f, "scroll right"),
+            Effect::SelectPlanes(planes) => write!(f, "planes {}", planes),
+            Effect::HighRes(enabled) => write!(f, "{}", if *enabled { "hires" } else { "lores" }),
+            Effect::KeyCheck { key, pressed } => {
+                write!(f, "key {:X} {}", key, if *pressed { "down" } else { "up" })
+            },
+            Effect::KeyRead(key) => write!(f, "key read {:02X}", key),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: usize,
+    pub opcode: u32,                // f000 nnnn holds all 4 bytes
+    pub instruction: Instruction,
+    pub status: ExecutionStatus,
+    pub changes: Vec<Change>,
+    pub effects: Vec<Effect>,
+}
+
+impl TraceEntry {
+    pub fn mnemonic(&self) -> String {
+        disasm::format_instruction(self.instruction, Syntax::Classic)
+    }
+}
+
+// text line: address, opcode, mnemonic, then changes, effects after `;` and status if not OK
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let opcode = if self.instruction.size() == 4 {
+            format!("{:08X}", self.opcode)
+        } else {
+            format!("{:04X}", self.opcode)
+        };
+
+        write!(f, "{:04X}: {:<8}  {:<20}", self.pc, opcode, self.mnemonic())?;
+
+        for change in self.changes.iter() {
+            write!(f, " {}", change)?;
+        }
+
+        if !self.effects.is_empty() {
+            let effects: Vec<String> = self.effects.iter().map(|e| e.to_string()).collect();
+            write!(f, " ; {}", effects.join(", "))?;
+        }
+
+        if self.status != ExecutionStatus::OK {
+            write!(f, " [{:?}]", self.status)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Snapshot is the traced cpu state before an instruction
+#[derive(Clone, Copy)]
+pub struct Snapshot {
+    pub registers: [u8; 16],
+    pub ir: usize,
+    pub dt: u8,
+    pub st: u8,
+}
+
+impl Snapshot {
+    // changes lists every value differing from `after`
+    pub fn changes(&self, after: &Snapshot) -> Vec<Change> {
+        let mut changes = Vec::new();
+
+        for x in 0..16 {
+            if self.registers[x] != after.registers[x] {
+                changes.push(Change::Register(x as u8, self.registers[x], after.registers[x]));
+            }
+        }
+
+        if self.ir != after.ir {
+            changes.push(Change::Index(self.ir, after.ir));
+        }
+
+        if self.dt != after.dt {
+            changes.push(Change::Delay(self.dt, after.dt));
+        }
+
+        if self.st != after.st {
+            changes.push(Change::Sound(self.st, after.st));
+        }
+
+        changes
+    }
+}
+
+pub struct Tracer {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+    dropped: usize,                         // entries evicted since last clear
+    range: Option<(usize, usize)>,          // inclusive pc range
+    classes: u16,                           // OpClass bit mask
+}
+
+impl Tracer {
+    // new creates tracer keeping the last `capacity` entries, recording every instruction
+    pub fn new(capacity: usize) -> Tracer {
+        Tracer {
+            entries: VecDeque::with_capacity(capacity.min(4096)),
+            capacity: capacity.max(1),
+            dropped: 0,
+            range: None,
+            classes: u16::MAX,
+        }
+    }
+
+    // set_range only records instructions with pc in start..=end
+    pub fn set_range(&mut self, start: usize, end: usize) {
+        self.range = Some((start.min(end), start.max(end)));
+    }
+
+    pub fn clear_range(&mut self) {
+        self.range = None;
+    }
+
+    // set_class enables or disables recording of an opcode class
+    pub fn set_class(&mut self, class: OpClass, enabled: bool) {
+        if enabled {
+            self.classes |= class.bit();
+        } else {
+            self.classes &= !class.bit();
+        }
+    }
+
+    // only_classes records `classes` and nothing else
+    pub fn only_classes(&mut self, classes: &[OpClass]) {
+        self.classes = classes.iter().fold(0, |mask, class| mask | class.bit());
+    }
+
+    pub fn matches(&self, pc: usize, instruction: Instruction) -> bool {
+        let in_range = self.range.is_none_or(|(start, end)| pc >= start && pc <= end);
+        in_range && self.classes & OpClass::of(instruction).bit() != 0
+    }
+
+    pub fn record(&mut self, entry: TraceEntry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+            self.dropped += 1;
+        }
+
+        self.entries.push_back(entry);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.dropped = 0;
+    }
+
+    // entries returns recorded entries, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    // to_text exports one entry per line
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        for entry in self.entries.iter() {
+            text.push_str(&entry.to_string());
+            text.push('\n');
+        }
+
+        text
+    }
+
+    // to_json exports an array of entries:
+    // {"pc", "opcode", "mnemonic", "status", "changes": [{"target", "old", "new"}], "effects": [string]}
+    pub fn to_json(&self) -> String {
+        let entries: Vec<String> = self.entries.iter().map(entry_json).collect();
+        format!("[{}]", entries.join(",\n"))
+    }
+}
+
+fn entry_json(entry: &TraceEntry) -> String {
+    let changes: Vec<String> = entry.changes.iter().map(|change| {
+        let (target, old, new) = match *change {
+            Change::Register(x, old, new) => (format!("V{:X}", x), old as usize, new as usize),
+            Change::Index(old, new) => ("I".to_string(), old, new),
+            Change::Delay(old, new) => ("DT".to_string(), old as usize, new as usize),
+            Change::Sound(old, new) => ("ST".to_string(), old as usize, new as usize),
+        };
+
+        format!("{{\"target\":\"{}\",\"old\":{},\"new\":{}}}", target, old, new)
+    }).collect();
+
+    let effects: Vec<String> = entry.effects.iter().map(|e| json_string(&e.to_string())).collect();
+
+    format!(
+        "{{\"pc\":{},\"opcode\":{},\"mnemonic\":{},\"status\":\"{:?}\",\"changes\":[{}],\"effects\":[{}]}}",
+        entry.pc, entry.opcode, json_string(&entry.mnemonic()), entry.status, changes.join(","), effects.join(",")
+    )
+}
+
+fn json_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// DeviceProbe forwards every call to a device and records it as an effect. probes of display, keypad &
+// audio share one effect list. key_pressed only borrows the device, so effects are kept in a RefCell
+pub struct DeviceProbe<'a, D> {
+    device: &'a mut D,
+    effects: &'a RefCell<Vec<Effect>>,
+}
+
+impl<'a, D> DeviceProbe<'a, D> {
+    pub fn new(device: &'a mut D, effects: &'a RefCell<Vec<Effect>>) -> DeviceProbe<'a, D> {
+        DeviceProbe { device, effects }
+    }
+
+    fn push(&self, effect: Effect) {
+        self.effects.borrow_mut().push(effect);
+    }
+
+    // record_draw adds drawn pixels, merging the pixels of one sprite into a single effect
+    fn record_draw(&self, count: usize, erased: bool) {
+        let mut effects = self.effects.borrow_mut();
+
+        match effects.last_mut() {
+            Some(Effect::Draw { pixels, erased: any_erased }) => {
+                *pixels += count;
+                *any_erased |= erased;
+            },
+            _ => effects.push(Effect::Draw { pixels: count, erased }),
+        }
+    }
+}
+
+impl<'a, D: Display> Display for DeviceProbe<'a, D> {
+    fn clear_display(&mut self) {
+        self.push(Effect::Clear);
+        self.device.clear_display();
+    }
+
+    fn draw_pixel(&mut self, x: u8, y: u8, plane: u8) -> u8 {
+        let erased = self.device.draw_pixel(x, y, plane);
+        self.record_draw(1, erased != 0);
+        erased
+    }
+
+    fn draw_row(&mut self, x: u8, y: u8, row: u8, plane: u8) -> bool {
+        let erased = self.device.draw_row(x, y, row, plane);
+
+        if row != 0 {
+            self.record_draw(row.count_ones() as usize, erased);
+        }
+
+        erased
+    }
+
+    fn draw_sprite(&mut self, x: u8, y: u8, rows: &[u8], plane: u8) -> bool {
+        let erased = self.device.draw_sprite(x, y, rows, plane);
+        let pixels = rows.iter().map(|row| row.count_ones() as usize).sum();
+
+        if pixels > 0 {
+            self.record_draw(pixels, erased);
+        }
+
+        erased
+    }
+
+    fn select_planes(&mut self, planes: u8) {
+        self.push(Effect::SelectPlanes(planes));
+        self.device.select_planes(planes);
+    }
+
+    fn selected_planes(&self) -> u8 {
+        self.device.selected_planes()
+    }
+
+    fn set_high_res(&mut self, enabled: bool) {
+        self.push(Effect::HighRes(enabled));
+        self.device.set_high_res(enabled);
+    }
+
+    fn is_high_res(&self) -> bool {
+        self.device.is_high_res()
+    }
+
+    fn scroll_down(&mut self, n: u8) {
+        self.push(Effect::ScrollDown(n));
+        self.device.scroll_down(n);
+    }
+
+    fn scroll_up(&mut self, n: u8) {
+        self.push(Effect::ScrollUp(n));
+        self.device.scroll_up(n);
+    }
+
+    fn scroll_left(&mut self) {
+        self.push(Effect::ScrollLeft);
+        self.device.scroll_left();
+    }
+
+    fn scroll_right(&mut self) {
+        self.push(Effect::ScrollRight);
+        self.device.scroll_right();
+    }
+}
+
+impl<'a, K: Keypad> Keypad for DeviceProbe<'a, K> {
+    fn key_pressed(&self, key: u8) -> bool {
+        let pressed = self.device.key_pressed(key);
+        self.push(Effect::KeyCheck { key, pressed });
+        pressed
+    }
+
+    fn read_any_key(&mut self) -> u8 {
+        let key = self.device.read_any_key();
+        self.push(Effect::KeyRead(key));
+        key
+    }
+}
+
+// audio changes are already traced as sound timer changes, so they are only forwarded
+impl<'a, A: Audio> Audio for DeviceProbe<'a, A> {
+    fn set_beeping(&mut self, beeping: bool) {
+        self.device.set_beeping(beeping);
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; AUDIO_PATTERN_SIZE]) {
+        self.device.set_pattern(pattern);
+    }
+
+    fn set_pitch(&mut self, pitch: u8) {
+        self.device.set_pitch(pitch);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::*;
+    use super::*;
+    use crate::Machine;
+    use crate::assembler::assemble;
+
+    #[wasm_bindgen_test]
+    fn test_trace() {
+        let rom = assemble("
+                LD V1, 5
+                LD I, sprite
+                DRW V1, V1, 1
+                LD DT, V1
+                ADD V1, 1
+                EXIT
+            sprite:
+                :byte 0x80
+        ").unwrap().rom;
+
+        let mut machine = Machine::new();
+        machine.load_rom(&rom);
+        machine.enable_trace(16);
+        machine.update();
+
+        let tracer = machine.tracer().unwrap();
+        let entries: Vec<&TraceEntry> = tracer.entries().collect();
+
+        assert_eq!(entries.len(), 6);
+        assert_eq!((entries[0].pc, entries[0].opcode), (0x200, 0x6105));
+        assert_eq!(entries[0].changes, vec![Change::Register(1, 0, 5)]);
+        assert_eq!(entries[1].changes, vec![Change::Index(0, 0x20C)]);
+        assert_eq!(entries[2].effects, vec![Effect::Draw { pixels: 1, erased: false }]);
+        assert_eq!(entries[3].changes, vec![Change::Delay(0, 5)]);
+        assert_eq!(entries[5].status, ExecutionStatus::Halt);
+
+        let text = tracer.to_text();
+        assert_eq!(text.lines().next(), Some("0200: 6105      LD V1, 0x05          V1:00->05"));
+        assert!(text.contains("DRW V1, V1, 1        ; draw 1px"));
+
+        let json = tracer.to_json();
+        assert!(json.starts_with("[{\"pc\":512,\"opcode\":24837,\"mnemonic\":\"LD V1, 0x05\",\"status\":\"OK\""));
+        assert!(json.contains("\"changes\":[{\"target\":\"V1\",\"old\":0,\"new\":5}]"));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_trace_filter() {
+        let rom = assemble("
+            loop:
+                ADD V0, 1
+                LD V1, V0
+                JP loop
+        ").unwrap().rom;
+
+        let mut machine = Machine::new();
+        machine.load_rom(&rom);
+        machine.enable_trace(4);
+        machine.set_trace_range(0x202, 0x204);
+        machine.set_trace_class(OpClass::Flow, false);
+
+        for _ in 0..4 {
+            machine.update();
+        }
+
+        // only `LD V1, V0` is recorded, older entries are dropped
+        let tracer = machine.tracer().unwrap();
+
+        assert_eq!(tracer.len(), 4);
+        assert!(tracer.dropped() > 0);
+        assert!(tracer.entries().all(|entry| entry.pc == 0x202));
+
+        machine.disable_trace();
+        assert!(machine.tracer().is_none());
+    }
+}
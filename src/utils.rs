@@ -1,5 +1,5 @@
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
 extern crate web_sys;
-extern crate js_sys;
 
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
@@ -10,28 +10,23 @@ pub fn set_panic_hook() {
     // https://github.com/rustwasm/console_error_panic_hook#readme
 
     // set panic hook only on development & test
-    #[cfg(all(feature = "console_error_panic_hook", debug_assertions))]
+    #[cfg(all(feature = "console_error_panic_hook", debug_assertions, target_arch = "wasm32"))]
     console_error_panic_hook::set_once();
 }
 
-#[cfg(not(test))]
-macro_rules! log {
+#[allow(unused_macros)]
+#[cfg(all(debug_assertions, feature = "wasm", target_arch = "wasm32"))]
+macro_rules! debug {
     ($( $t:tt )*) => {
         web_sys::console::log_1(&format!( $( $t )* ).into());
     };
 }
 
-// mock log for test
-#[cfg(test)]
-macro_rules! log {
-    ($( $t:tt )*) => {};
-}
-
 #[allow(unused_macros)]
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, not(all(feature = "wasm", target_arch = "wasm32"))))]
 macro_rules! debug {
     ($( $t:tt )*) => {
-        web_sys::console::log_1(&format!( $( $t )* ).into());
+        eprintln!( $( $t )* );
     };
 }
 
@@ -41,14 +36,3 @@ macro_rules! debug {
 macro_rules! debug {
     ($( $t:tt )*) => {};
 }
-
-#[cfg(not(test))]
-pub fn random() -> u8 {
-    (js_sys::Math::random() * 255.0).floor() as u8
-}
-
-// mock random for test
-#[cfg(test)]
-pub fn random() -> u8 {
-    0xBD
-}
@@ -0,0 +1,94 @@
+/****
+ * Recompiled rom test: tests/aot/pong.rs is the recompiler output for Pong, checked in and built as Rust
+ *
+ * The module must match what the recompiler emits today, and run in lockstep with the interpreter: same status,
+ * registers, memory & display after every frame, with the same seed & key input, at any speed & timing mode.
+ */
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+#[path = "aot/pong.rs"]
+mod pong;
+
+use chip8_rs::{Machine, ExecutionStatus, ErrorPolicy, TimingMode, NullLogger, FrameBuffer, KeyState, AudioState};
+use chip8_rs::aot::{Core, recover, emit};
+
+const ROM_FILE : &str = "Pong [Paul Vervalin, 1990].ch8";
+const FRAMES : usize = 600;
+const SEED : u32 = 0xC8;
+
+#[test]
+fn test_recompiled_module_is_current() {
+    let rom = common::read_rom(ROM_FILE);
+    assert_eq!(&rom[..], &pong::ROM[..]);
+
+    let module = emit(&rom, &recover(&rom), None, ROM_FILE);
+    let checked_in = include_str!("aot/pong.rs");
+
+    assert!(module == checked_in,
+        "tests/aot/pong.rs is stale, regenerate it with:\n  cargo run --bin recompile -- \"roms/{}\" -o tests/aot/pong.rs",
+        ROM_FILE);
+}
+
+#[test]
+fn test_recompiled_lockstep() {
+    let runs = [(None, TimingMode::Fixed), (Some(20_000), TimingMode::Fixed), (None, TimingMode::CosmacVip)];
+
+    for &(ips, timing) in runs.iter() {
+        let mut machine = Machine::new();
+        machine.set_quirks(pong::quirks());
+        machine.set_error_policy(ErrorPolicy::Lenient);
+        machine.set_seed(SEED);
+        machine.set_logger(Box::new(NullLogger));
+        assert!(machine.load_rom(&pong::ROM));
+
+        let mut core = pong::new_core();
+        core.set_error_policy(ErrorPolicy::Lenient);
+        core.set_seed(SEED);
+        core.set_logger(Box::new(NullLogger));
+
+        let mut display = FrameBuffer::new();
+        let mut keypad = KeyState::new();
+        let mut audio = AudioState::new();
+        display.set_quirks(&pong::quirks());
+
+        machine.set_timing_mode(timing);
+        core.set_timing_mode(timing);
+
+        if let Some(ips) = ips {
+            machine.set_instructions_per_second(ips);
+            core.set_instructions_per_second(ips);
+        }
+
+        let mut held = 0u16;
+
+        for frame in 0..FRAMES {
+            let pressed = common::scripted_keys(frame);
+            machine.set_keys(pressed, held & !pressed);
+            keypad.set_keys(pressed, held & !pressed);
+            held = pressed;
+
+            let expected = machine.update();
+            let status = pong::frame(&mut core, &mut display, &mut keypad, &mut audio);
+
+            assert_eq!(status, expected, "{:?} ips, {:?}: status at frame {}", ips, timing, frame);
+            assert!(same_state(&machine, &core, &display), "{:?} ips, {:?}: differs at frame {}", ips, timing, frame);
+            assert_eq!(audio.beeping(), machine.is_beeping(), "{:?} ips, {:?}: audio at frame {}", ips, timing, frame);
+
+            if status != ExecutionStatus::OK {
+                break;
+            }
+        }
+
+        // the game drew its paddles, ball & score
+        assert!(display.visible().iter().any(|&pixel| pixel != 0));
+    }
+}
+
+fn same_state(machine: &Machine, core: &Core, display: &FrameBuffer) -> bool {
+    machine.get_pc() == core.pc() && machine.get_index() == core.index() &&
+        (0..16).all(|x| machine.get_register(x) == core.register(x)) &&
+        machine.memory() == core.memory() && machine.display() == display.visible()
+}
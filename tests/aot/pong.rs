@@ -0,0 +1,801 @@
+// Generated from Pong [Paul Vervalin, 1990].ch8 by the chip8-rs recompiler, do not edit.
+//
+// 50 blocks recovered. Run by the interpreter:
+//   indirect jumps: none
+//   targets outside of the rom: none
+
+use chip8_rs::{Display, Keypad, Audio, ExecutionStatus, Quirks};
+use chip8_rs::Instruction::*;
+use chip8_rs::aot::Core;
+
+pub const ROM : [u8; 246] = [
+    0x6A, 0x02, 0x6B, 0x0C, 0x6C, 0x3F, 0x6D, 0x0C, 0xA2, 0xEA, 0xDA, 0xB6, 0xDC, 0xD6, 0x6E, 0x00,
+    0x22, 0xD4, 0x66, 0x03, 0x68, 0x02, 0x60, 0x60, 0xF0, 0x15, 0xF0, 0x07, 0x30, 0x00, 0x12, 0x1A,
+    0xC7, 0x17, 0x77, 0x08, 0x69, 0xFF, 0xA2, 0xF0, 0xD6, 0x71, 0xA2, 0xEA, 0xDA, 0xB6, 0xDC, 0xD6,
+    0x60, 0x01, 0xE0, 0xA1, 0x7B, 0xFE, 0x60, 0x04, 0xE0, 0xA1, 0x7B, 0x02, 0x60, 0x1F, 0x8B, 0x02,
+    0xDA, 0xB6, 0x60, 0x0C, 0xE0, 0xA1, 0x7D, 0xFE, 0x60, 0x0D, 0xE0, 0xA1, 0x7D, 0x02, 0x60, 0x1F,
+    0x8D, 0x02, 0xDC, 0xD6, 0xA2, 0xF0, 0xD6, 0x71, 0x86, 0x84, 0x87, 0x94, 0x60, 0x3F, 0x86, 0x02,
+    0x61, 0x1F, 0x87, 0x12, 0x46, 0x02, 0x12, 0x78, 0x46, 0x3F, 0x12, 0x82, 0x47, 0x1F, 0x69, 0xFF,
+    0x47, 0x00, 0x69, 0x01, 0xD6, 0x71, 0x12, 0x2A, 0x68, 0x02, 0x63, 0x01, 0x80, 0x70, 0x80, 0xB5,
+    0x12, 0x8A, 0x68, 0xFE, 0x63, 0x0A, 0x80, 0x70, 0x80, 0xD5, 0x3F, 0x01, 0x12, 0xA2, 0x61, 0x02,
+    0x80, 0x15, 0x3F, 0x01, 0x12, 0xBA, 0x80, 0x15, 0x3F, 0x01, 0x12, 0xC8, 0x80, 0x15, 0x3F, 0x01,
+    0x12, 0xC2, 0x60, 0x20, 0xF0, 0x18, 0x22, 0xD4, 0x8E, 0x34, 0x22, 0xD4, 0x66, 0x3E, 0x33, 0x01,
+    0x66, 0x03, 0x68, 0xFE, 0x33, 0x01, 0x68, 0x02, 0x12, 0x16, 0x79, 0xFF, 0x49, 0xFE, 0x69, 0xFF,
+    0x12, 0xC8, 0x79, 0x01, 0x49, 0x02, 0x69, 0x01, 0x60, 0x04, 0xF0, 0x18, 0x76, 0x01, 0x46, 0x40,
+    0x76, 0xFE, 0x12, 0x6C, 0xA2, 0xF2, 0xFE, 0x33, 0xF2, 0x65, 0xF1, 0x29, 0x64, 0x14, 0x65, 0x00,
+    0xD4, 0x55, 0x74, 0x15, 0xF2, 0x29, 0xD4, 0x55, 0x00, 0xEE, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80,
+    0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+pub fn quirks() -> Quirks {
+    Quirks::new()
+}
+
+// new_core returns a core with the rom loaded
+pub fn new_core() -> Core {
+    Core::new(&ROM, quirks())
+}
+
+// frame runs one 60 Hz frame: the instructions due, then a timer tick
+pub fn frame(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) -> ExecutionStatus {
+    core.begin_frame();
+
+    while core.running() {
+        match core.pc() {
+            0x200 => block_200(core, display, keypad, audio),
+            0x212 => block_212(core, display, keypad, audio),
+            0x216 => block_216(core, display, keypad, audio),
+            0x21A => block_21a(core, display, keypad, audio),
+            0x21E => block_21e(core, display, keypad, audio),
+            0x220 => block_220(core, display, keypad, audio),
+            0x22A => block_22a(core, display, keypad, audio),
+            0x234 => block_234(core, display, keypad, audio),
+            0x236 => block_236(core, display, keypad, audio),
+            0x23A => block_23a(core, display, keypad, audio),
+            0x23C => block_23c(core, display, keypad, audio),
+            0x246 => block_246(core, display, keypad, audio),
+            0x248 => block_248(core, display, keypad, audio),
+            0x24C => block_24c(core, display, keypad, audio),
+            0x24E => block_24e(core, display, keypad, audio),
+            0x266 => block_266(core, display, keypad, audio),
+            0x268 => block_268(core, display, keypad, audio),
+            0x26A => block_26a(core, display, keypad, audio),
+            0x26C => block_26c(core, display, keypad, audio),
+            0x26E => block_26e(core, display, keypad, audio),
+            0x270 => block_270(core, display, keypad, audio),
+            0x272 => block_272(core, display, keypad, audio),
+            0x274 => block_274(core, display, keypad, audio),
+            0x278 => block_278(core, display, keypad, audio),
+            0x282 => block_282(core, display, keypad, audio),
+            0x28A => block_28a(core, display, keypad, audio),
+            0x28C => block_28c(core, display, keypad, audio),
+            0x28E => block_28e(core, display, keypad, audio),
+            0x294 => block_294(core, display, keypad, audio),
+            0x296 => block_296(core, display, keypad, audio),
+            0x29A => block_29a(core, display, keypad, audio),
+            0x29C => block_29c(core, display, keypad, audio),
+            0x2A0 => block_2a0(core, display, keypad, audio),
+            0x2A2 => block_2a2(core, display, keypad, audio),
+            0x2A8 => block_2a8(core, display, keypad, audio),
+            0x2AC => block_2ac(core, display, keypad, audio),
+            0x2B0 => block_2b0(core, display, keypad, audio),
+            0x2B2 => block_2b2(core, display, keypad, audio),
+            0x2B6 => block_2b6(core, display, keypad, audio),
+            0x2B8 => block_2b8(core, display, keypad, audio),
+            0x2BA => block_2ba(core, display, keypad, audio),
+            0x2BE => block_2be(core, display, keypad, audio),
+            0x2C0 => block_2c0(core, display, keypad, audio),
+            0x2C2 => block_2c2(core, display, keypad, audio),
+            0x2C6 => block_2c6(core, display, keypad, audio),
+            0x2C8 => block_2c8(core, display, keypad, audio),
+            0x2D0 => block_2d0(core, display, keypad, audio),
+            0x2D2 => block_2d2(core, display, keypad, audio),
+            0x2D4 => block_2d4(core, display, keypad, audio),
+            0x2D8 => block_2d8(core, display, keypad, audio),
+            _ => core.interpret(display, keypad, audio),
+        }
+    }
+
+    core.end_frame(audio)
+}
+
+fn block_200(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x200, &ROM[0x000..0x012], 8, 1686) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x200: LD VA, 0x02
+    core.set_register(0xA, 0x02);
+    // 0x202: LD VB, 0x0C
+    core.set_register(0xB, 0x0C);
+    // 0x204: LD VC, 0x3F
+    core.set_register(0xC, 0x3F);
+    // 0x206: LD VD, 0x0C
+    core.set_register(0xD, 0x0C);
+    // 0x208: LD I, 0x2EA
+    core.set_index(0x2EA);
+    // 0x20A: DRW VA, VB, 6
+    if !core.device(0x20A, Draw(10, 11, 6), display, keypad, audio) { return core.spend(5, 236); }
+    // 0x20C: DRW VC, VD, 6
+    if !core.device(0x20C, Draw(12, 13, 6), display, keypad, audio) { return core.spend(5, 236); }
+    // 0x20E: LD VE, 0x00
+    core.set_register(0xE, 0x00);
+    // 0x210: CALL 0x2D4
+    if core.push(0x212) {
+        core.leave(0x2D4, 7, 348);
+    } else {
+        core.interpret_at(0x210, 6, 282, display, keypad, audio);
+    }
+}
+
+fn block_212(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x212, &ROM[0x012..0x016], 1, 46) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x212: LD V6, 0x03
+    core.set_register(0x6, 0x03);
+    // 0x214: LD V8, 0x02
+    core.set_register(0x8, 0x02);
+    core.leave(0x216, 2, 92);
+}
+
+fn block_216(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x216, &ROM[0x016..0x01A], 1, 46) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x216: LD V0, 0x60
+    core.set_register(0x0, 0x60);
+    // 0x218: LD DT, V0
+    core.set_delay_timer(core.register(0x0));
+    core.leave(0x21A, 2, 96);
+}
+
+fn block_21a(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x21A, &ROM[0x01A..0x01E], 1, 50) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x21A: LD V0, DT
+    core.set_register(0x0, core.delay_timer());
+    // 0x21C: SE V0, 0x00
+    if core.register(0x0) == 0x00 {
+        core.skip(0x21E, 2, 104);
+    } else {
+        core.leave(0x21E, 2, 100);
+    }
+}
+
+fn block_21e(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x21E, &ROM[0x01E..0x020], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x21E: JP 0x21A
+    core.leave(0x21A, 1, 52);
+}
+
+fn block_220(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x220, &ROM[0x020..0x02A], 4, 224) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x220: RND V7, 0x17
+    let value = core.rand() & 0x17;
+    core.set_register(0x7, value);
+    // 0x222: ADD V7, 0x08
+    core.set_register(0x7, core.register(0x7).wrapping_add(0x08));
+    // 0x224: LD V9, 0xFF
+    core.set_register(0x9, 0xFF);
+    // 0x226: LD I, 0x2F0
+    core.set_index(0x2F0);
+    // 0x228: DRW V6, V7, 1
+    core.device(0x228, Draw(6, 7, 1), display, keypad, audio);
+    core.spend(4, 224);
+}
+
+fn block_22a(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x22A, &ROM[0x02A..0x034], 4, 1502) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x22A: LD I, 0x2EA
+    core.set_index(0x2EA);
+    // 0x22C: DRW VA, VB, 6
+    if !core.device(0x22C, Draw(10, 11, 6), display, keypad, audio) { return core.spend(1, 52); }
+    // 0x22E: DRW VC, VD, 6
+    if !core.device(0x22E, Draw(12, 13, 6), display, keypad, audio) { return core.spend(1, 52); }
+    // 0x230: LD V0, 0x01
+    core.set_register(0x0, 0x01);
+    // 0x232: SKNP V0
+    core.device(0x232, SkipNotKey(0), display, keypad, audio);
+    core.spend(2, 98);
+}
+
+fn block_234(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x234, &ROM[0x034..0x036], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x234: ADD VB, 0xFE
+    core.set_register(0xB, core.register(0xB).wrapping_add(0xFE));
+    core.leave(0x236, 1, 50);
+}
+
+fn block_236(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x236, &ROM[0x036..0x03A], 1, 46) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x236: LD V0, 0x04
+    core.set_register(0x0, 0x04);
+    // 0x238: SKNP V0
+    core.device(0x238, SkipNotKey(0), display, keypad, audio);
+    core.spend(1, 46);
+}
+
+fn block_23a(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x23A, &ROM[0x03A..0x03C], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x23A: ADD VB, 0x02
+    core.set_register(0xB, core.register(0xB).wrapping_add(0x02));
+    core.leave(0x23C, 1, 50);
+}
+
+fn block_23c(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x23C, &ROM[0x03C..0x046], 4, 878) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x23C: LD V0, 0x1F
+    core.set_register(0x0, 0x1F);
+    // 0x23E: AND VB, V0
+    core.set_register(0xB, core.register(0xB) & core.register(0x0));
+    // 0x240: DRW VA, VB, 6
+    if !core.device(0x240, Draw(10, 11, 6), display, keypad, audio) { return core.spend(2, 130); }
+    // 0x242: LD V0, 0x0C
+    core.set_register(0x0, 0x0C);
+    // 0x244: SKNP V0
+    core.device(0x244, SkipNotKey(0), display, keypad, audio);
+    core.spend(3, 176);
+}
+
+fn block_246(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x246, &ROM[0x046..0x048], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x246: ADD VD, 0xFE
+    core.set_register(0xD, core.register(0xD).wrapping_add(0xFE));
+    core.leave(0x248, 1, 50);
+}
+
+fn block_248(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x248, &ROM[0x048..0x04C], 1, 46) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x248: LD V0, 0x0D
+    core.set_register(0x0, 0x0D);
+    // 0x24A: SKNP V0
+    core.device(0x24A, SkipNotKey(0), display, keypad, audio);
+    core.spend(1, 46);
+}
+
+fn block_24c(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x24C, &ROM[0x04C..0x04E], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x24C: ADD VD, 0x02
+    core.set_register(0xD, core.register(0xD).wrapping_add(0x02));
+    core.leave(0x24E, 1, 50);
+}
+
+fn block_24e(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x24E, &ROM[0x04E..0x066], 11, 1484) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x24E: LD V0, 0x1F
+    core.set_register(0x0, 0x1F);
+    // 0x250: AND VD, V0
+    core.set_register(0xD, core.register(0xD) & core.register(0x0));
+    // 0x252: DRW VC, VD, 6
+    if !core.device(0x252, Draw(12, 13, 6), display, keypad, audio) { return core.spend(2, 130); }
+    // 0x254: LD I, 0x2F0
+    core.set_index(0x2F0);
+    // 0x256: DRW V6, V7, 1
+    if !core.device(0x256, Draw(6, 7, 1), display, keypad, audio) { return core.spend(3, 182); }
+    // 0x258: ADD V6, V8
+    let (value, carry) = core.register(0x6).overflowing_add(core.register(0x8));
+    core.set_register(0x6, value);
+    core.set_register(0xF, carry as u8);
+    // 0x25A: ADD V7, V9
+    let (value, carry) = core.register(0x7).overflowing_add(core.register(0x9));
+    core.set_register(0x7, value);
+    core.set_register(0xF, carry as u8);
+    // 0x25C: LD V0, 0x3F
+    core.set_register(0x0, 0x3F);
+    // 0x25E: AND V6, V0
+    core.set_register(0x6, core.register(0x6) & core.register(0x0));
+    // 0x260: LD V1, 0x1F
+    core.set_register(0x1, 0x1F);
+    // 0x262: AND V7, V1
+    core.set_register(0x7, core.register(0x7) & core.register(0x1));
+    // 0x264: SNE V6, 0x02
+    if core.register(0x6) != 0x02 {
+        core.skip(0x266, 10, 664);
+    } else {
+        core.leave(0x266, 10, 660);
+    }
+}
+
+fn block_266(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x266, &ROM[0x066..0x068], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x266: JP 0x278
+    core.leave(0x278, 1, 52);
+}
+
+fn block_268(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x268, &ROM[0x068..0x06A], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x268: SNE V6, 0x3F
+    if core.register(0x6) != 0x3F {
+        core.skip(0x26A, 1, 54);
+    } else {
+        core.leave(0x26A, 1, 50);
+    }
+}
+
+fn block_26a(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x26A, &ROM[0x06A..0x06C], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x26A: JP 0x282
+    core.leave(0x282, 1, 52);
+}
+
+fn block_26c(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x26C, &ROM[0x06C..0x06E], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x26C: SNE V7, 0x1F
+    if core.register(0x7) != 0x1F {
+        core.skip(0x26E, 1, 54);
+    } else {
+        core.leave(0x26E, 1, 50);
+    }
+}
+
+fn block_26e(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x26E, &ROM[0x06E..0x070], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x26E: LD V9, 0xFF
+    core.set_register(0x9, 0xFF);
+    core.leave(0x270, 1, 46);
+}
+
+fn block_270(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x270, &ROM[0x070..0x072], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x270: SNE V7, 0x00
+    if core.register(0x7) != 0x00 {
+        core.skip(0x272, 1, 54);
+    } else {
+        core.leave(0x272, 1, 50);
+    }
+}
+
+fn block_272(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x272, &ROM[0x072..0x074], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x272: LD V9, 0x01
+    core.set_register(0x9, 0x01);
+    core.leave(0x274, 1, 46);
+}
+
+fn block_274(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x274, &ROM[0x074..0x078], 1, 172) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x274: DRW V6, V7, 1
+    if !core.device(0x274, Draw(6, 7, 1), display, keypad, audio) { return; }
+    // 0x276: JP 0x22A
+    core.leave(0x22A, 1, 52);
+}
+
+fn block_278(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x278, &ROM[0x078..0x082], 4, 228) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x278: LD V8, 0x02
+    core.set_register(0x8, 0x02);
+    // 0x27A: LD V3, 0x01
+    core.set_register(0x3, 0x01);
+    // 0x27C: LD V0, V7
+    core.set_register(0x0, core.register(0x7));
+    // 0x27E: SUB V0, VB
+    let (value, borrow) = core.register(0x0).overflowing_sub(core.register(0xB));
+    core.set_register(0x0, value);
+    core.set_register(0xF, !borrow as u8);
+    // 0x280: JP 0x28A
+    core.leave(0x28A, 5, 280);
+}
+
+fn block_282(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x282, &ROM[0x082..0x08A], 3, 144) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x282: LD V8, 0xFE
+    core.set_register(0x8, 0xFE);
+    // 0x284: LD V3, 0x0A
+    core.set_register(0x3, 0x0A);
+    // 0x286: LD V0, V7
+    core.set_register(0x0, core.register(0x7));
+    // 0x288: SUB V0, VD
+    let (value, borrow) = core.register(0x0).overflowing_sub(core.register(0xD));
+    core.set_register(0x0, value);
+    core.set_register(0xF, !borrow as u8);
+    core.leave(0x28A, 4, 228);
+}
+
+fn block_28a(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x28A, &ROM[0x08A..0x08C], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x28A: SE VF, 0x01
+    if core.register(0xF) == 0x01 {
+        core.skip(0x28C, 1, 54);
+    } else {
+        core.leave(0x28C, 1, 50);
+    }
+}
+
+fn block_28c(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x28C, &ROM[0x08C..0x08E], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x28C: JP 0x2A2
+    core.leave(0x2A2, 1, 52);
+}
+
+fn block_28e(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x28E, &ROM[0x08E..0x094], 2, 130) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x28E: LD V1, 0x02
+    core.set_register(0x1, 0x02);
+    // 0x290: SUB V0, V1
+    let (value, borrow) = core.register(0x0).overflowing_sub(core.register(0x1));
+    core.set_register(0x0, value);
+    core.set_register(0xF, !borrow as u8);
+    // 0x292: SE VF, 0x01
+    if core.register(0xF) == 0x01 {
+        core.skip(0x294, 3, 184);
+    } else {
+        core.leave(0x294, 3, 180);
+    }
+}
+
+fn block_294(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x294, &ROM[0x094..0x096], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x294: JP 0x2BA
+    core.leave(0x2BA, 1, 52);
+}
+
+fn block_296(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x296, &ROM[0x096..0x09A], 1, 84) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x296: SUB V0, V1
+    let (value, borrow) = core.register(0x0).overflowing_sub(core.register(0x1));
+    core.set_register(0x0, value);
+    core.set_register(0xF, !borrow as u8);
+    // 0x298: SE VF, 0x01
+    if core.register(0xF) == 0x01 {
+        core.skip(0x29A, 2, 138);
+    } else {
+        core.leave(0x29A, 2, 134);
+    }
+}
+
+fn block_29a(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x29A, &ROM[0x09A..0x09C], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x29A: JP 0x2C8
+    core.leave(0x2C8, 1, 52);
+}
+
+fn block_29c(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x29C, &ROM[0x09C..0x0A0], 1, 84) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x29C: SUB V0, V1
+    let (value, borrow) = core.register(0x0).overflowing_sub(core.register(0x1));
+    core.set_register(0x0, value);
+    core.set_register(0xF, !borrow as u8);
+    // 0x29E: SE VF, 0x01
+    if core.register(0xF) == 0x01 {
+        core.skip(0x2A0, 2, 138);
+    } else {
+        core.leave(0x2A0, 2, 134);
+    }
+}
+
+fn block_2a0(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2A0, &ROM[0x0A0..0x0A2], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2A0: JP 0x2C2
+    core.leave(0x2C2, 1, 52);
+}
+
+fn block_2a2(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2A2, &ROM[0x0A2..0x0A8], 2, 96) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2A2: LD V0, 0x20
+    core.set_register(0x0, 0x20);
+    // 0x2A4: LD ST, V0
+    if !core.device(0x2A4, LoadSt(0), display, keypad, audio) { return core.spend(1, 46); }
+    // 0x2A6: CALL 0x2D4
+    if core.push(0x2A8) {
+        core.leave(0x2D4, 2, 112);
+    } else {
+        core.interpret_at(0x2A6, 1, 46, display, keypad, audio);
+    }
+}
+
+fn block_2a8(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2A8, &ROM[0x0A8..0x0AC], 1, 84) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2A8: ADD VE, V3
+    let (value, carry) = core.register(0xE).overflowing_add(core.register(0x3));
+    core.set_register(0xE, value);
+    core.set_register(0xF, carry as u8);
+    // 0x2AA: CALL 0x2D4
+    if core.push(0x2AC) {
+        core.leave(0x2D4, 2, 150);
+    } else {
+        core.interpret_at(0x2AA, 1, 84, display, keypad, audio);
+    }
+}
+
+fn block_2ac(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2AC, &ROM[0x0AC..0x0B0], 1, 46) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2AC: LD V6, 0x3E
+    core.set_register(0x6, 0x3E);
+    // 0x2AE: SE V3, 0x01
+    if core.register(0x3) == 0x01 {
+        core.skip(0x2B0, 2, 100);
+    } else {
+        core.leave(0x2B0, 2, 96);
+    }
+}
+
+fn block_2b0(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2B0, &ROM[0x0B0..0x0B2], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2B0: LD V6, 0x03
+    core.set_register(0x6, 0x03);
+    core.leave(0x2B2, 1, 46);
+}
+
+fn block_2b2(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2B2, &ROM[0x0B2..0x0B6], 1, 46) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2B2: LD V8, 0xFE
+    core.set_register(0x8, 0xFE);
+    // 0x2B4: SE V3, 0x01
+    if core.register(0x3) == 0x01 {
+        core.skip(0x2B6, 2, 100);
+    } else {
+        core.leave(0x2B6, 2, 96);
+    }
+}
+
+fn block_2b6(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2B6, &ROM[0x0B6..0x0B8], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2B6: LD V8, 0x02
+    core.set_register(0x8, 0x02);
+    core.leave(0x2B8, 1, 46);
+}
+
+fn block_2b8(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2B8, &ROM[0x0B8..0x0BA], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2B8: JP 0x216
+    core.leave(0x216, 1, 52);
+}
+
+fn block_2ba(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2BA, &ROM[0x0BA..0x0BE], 1, 50) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2BA: ADD V9, 0xFF
+    core.set_register(0x9, core.register(0x9).wrapping_add(0xFF));
+    // 0x2BC: SNE V9, 0xFE
+    if core.register(0x9) != 0xFE {
+        core.skip(0x2BE, 2, 104);
+    } else {
+        core.leave(0x2BE, 2, 100);
+    }
+}
+
+fn block_2be(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2BE, &ROM[0x0BE..0x0C0], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2BE: LD V9, 0xFF
+    core.set_register(0x9, 0xFF);
+    core.leave(0x2C0, 1, 46);
+}
+
+fn block_2c0(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2C0, &ROM[0x0C0..0x0C2], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2C0: JP 0x2C8
+    core.leave(0x2C8, 1, 52);
+}
+
+fn block_2c2(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2C2, &ROM[0x0C2..0x0C6], 1, 50) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2C2: ADD V9, 0x01
+    core.set_register(0x9, core.register(0x9).wrapping_add(0x01));
+    // 0x2C4: SNE V9, 0x02
+    if core.register(0x9) != 0x02 {
+        core.skip(0x2C6, 2, 104);
+    } else {
+        core.leave(0x2C6, 2, 100);
+    }
+}
+
+fn block_2c6(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2C6, &ROM[0x0C6..0x0C8], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2C6: LD V9, 0x01
+    core.set_register(0x9, 0x01);
+    core.leave(0x2C8, 1, 46);
+}
+
+fn block_2c8(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2C8, &ROM[0x0C8..0x0D0], 3, 146) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2C8: LD V0, 0x04
+    core.set_register(0x0, 0x04);
+    // 0x2CA: LD ST, V0
+    if !core.device(0x2CA, LoadSt(0), display, keypad, audio) { return core.spend(1, 46); }
+    // 0x2CC: ADD V6, 0x01
+    core.set_register(0x6, core.register(0x6).wrapping_add(0x01));
+    // 0x2CE: SNE V6, 0x40
+    if core.register(0x6) != 0x40 {
+        core.skip(0x2D0, 3, 150);
+    } else {
+        core.leave(0x2D0, 3, 146);
+    }
+}
+
+fn block_2d0(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2D0, &ROM[0x0D0..0x0D2], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2D0: ADD V6, 0xFE
+    core.set_register(0x6, core.register(0x6).wrapping_add(0xFE));
+    core.leave(0x2D2, 1, 50);
+}
+
+fn block_2d2(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2D2, &ROM[0x0D2..0x0D4], 0, 0) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2D2: JP 0x26C
+    core.leave(0x26C, 1, 52);
+}
+
+fn block_2d4(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2D4, &ROM[0x0D4..0x0D8], 1, 52) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2D4: LD I, 0x2F2
+    core.set_index(0x2F2);
+    // 0x2D6: LD B, VE
+    let (addr, value) = (core.index(), core.register(0xE));
+    if !core.fits(addr, 3) { return core.interpret_at(0x2D6, 1, 52, display, keypad, audio); }
+    core.write(addr, value / 100);
+    core.write(addr + 1, value / 10 % 10);
+    core.write(addr + 2, value % 10);
+    core.leave(0x2D8, 2, 172 + 16 * (value / 100 + value / 10 % 10 + value % 10) as u32);
+}
+
+fn block_2d8(core: &mut Core, display: &mut impl Display, keypad: &mut impl Keypad, audio: &mut impl Audio) {
+    if !core.enter(0x2D8, &ROM[0x0D8..0x0EA], 8, 1550) {
+        return core.interpret(display, keypad, audio);
+    }
+
+    // 0x2D8: LD V2, [I]
+    let addr = core.index();
+    if !core.fits(addr, 3) { return core.interpret_at(0x2D8, 0, 0, display, keypad, audio); }
+    core.set_register(0x0, core.memory()[addr]);
+    core.set_register(0x1, core.memory()[addr + 1]);
+    core.set_register(0x2, core.memory()[addr + 2]);
+    core.set_index(addr + 3);
+    // 0x2DA: LD F, V1
+    core.set_index(5 * core.register(0x1) as usize);
+    // 0x2DC: LD V4, 0x14
+    core.set_register(0x4, 0x14);
+    // 0x2DE: LD V5, 0x00
+    core.set_register(0x5, 0x00);
+    // 0x2E0: DRW V4, V5, 5
+    if !core.device(0x2E0, Draw(4, 5, 5), display, keypad, audio) { return core.spend(4, 248); }
+    // 0x2E2: ADD V4, 0x15
+    core.set_register(0x4, core.register(0x4).wrapping_add(0x15));
+    // 0x2E4: LD F, V2
+    core.set_index(5 * core.register(0x2) as usize);
+    // 0x2E6: DRW V4, V5, 5
+    if !core.device(0x2E6, Draw(4, 5, 5), display, keypad, audio) { return core.spend(6, 358); }
+    // 0x2E8: RET
+    match core.pop() {
+        Some(addr) => core.leave(addr, 7, 408),
+        None => core.interpret_at(0x2E8, 6, 358, display, keypad, audio),
+    }
+}
@@ -0,0 +1,254 @@
+/****
+ * Shared by the rom tests: the bundled rom index and a scripted key input
+ */
+
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use chip8_rs::{Quirks, Platform, LoadStore};
+
+// (frame, key, duration): start most games, then move around a bit
+const KEY_SCRIPT : [(usize, u8, usize); 8] = [
+    (60, 0x5, 4),
+    (120, 0xF, 4),
+    (180, 0x4, 20),
+    (240, 0x6, 20),
+    (300, 0x2, 10),
+    (360, 0x8, 10),
+    (420, 0x1, 4),
+    (480, 0x5, 4),
+];
+
+pub struct RomEntry {
+    pub file: String,
+    pub quirks: Quirks,
+}
+
+// scripted_keys returns the keys pressed at `frame`
+pub fn scripted_keys(frame: usize) -> u16 {
+    let mut pressed = 0;
+
+    for &(start, key, duration) in KEY_SCRIPT.iter() {
+        if frame >= start && frame < start + duration {
+            pressed |= 1 << key;
+        }
+    }
+
+    pressed
+}
+
+// rom_index returns the entries of roms/index.js
+pub fn rom_index() -> Vec<RomEntry> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let index = fs::read_to_string(root.join("roms/index.js")).unwrap();
+    let entries = parse_index(&index);
+    assert!(!entries.is_empty(), "no rom in roms/index.js");
+
+    entries
+}
+
+// read_rom reads a rom file of roms/
+pub fn read_rom(file: &str) -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("roms").join(file)).unwrap()
+}
+
+// parse_index reads rom file & quirks of each entry in roms/index.js, a JSON array after `export default`
+fn parse_index(source: &str) -> Vec<RomEntry> {
+    let json = source.trim().trim_start_matches("export default").trim().trim_end_matches(';');
+
+    let mut parser = JsonParser { chars: json.chars().collect(), pos: 0 };
+    let entries = match parser.parse() {
+        Json::Array(entries) => entries,
+        _ => panic!("roms/index.js is not an array"),
+    };
+
+    entries.iter().map(|entry| {
+        let entry = entry.as_object();
+        let file = entry["file"].as_str().to_string();
+
+        let quirks = match entry.get("quirks") {
+            Some(config) => create_quirks(config.as_object()),
+            None => Quirks::new(),
+        };
+
+        RomEntry { file, quirks }
+    }).collect()
+}
+
+// create_quirks is the same as createQuirks in webplayer/game.js
+fn create_quirks(config: &BTreeMap<String, Json>) -> Quirks {
+    let platform = config.get("platform").map(|name| match name.as_str() {
+        "CosmacVip" => Platform::CosmacVip,
+        "Chip48" => Platform::Chip48,
+        "SChip10" => Platform::SChip10,
+        "SChip11" => Platform::SChip11,
+        "SChipModern" => Platform::SChipModern,
+        "XoChip" => Platform::XoChip,
+        other => panic!("unknown platform {}", other),
+    });
+
+    let mut quirks = platform.map(Quirks::for_platform).unwrap_or_default();
+    let flag = |key: &str| config.get(key).map(Json::as_bool);
+
+    if let Some(shift) = flag("shift") { quirks.shift_vx = shift; }
+    if let Some(load_store) = flag("loadStore") {
+        quirks.loadstore = if load_store { LoadStore::Unchanged } else { LoadStore::IncrementXPlusOne };
+    }
+    if let Some(wrap) = flag("wrapSprite") { quirks.wrap_sprite = wrap; }
+    if let Some(reset) = flag("vfReset") { quirks.vf_reset = reset; }
+    if let Some(jump) = flag("jumpVx") { quirks.jump_vx = jump; }
+    if let Some(wait) = flag("displayWait") { quirks.display_wait = wait; }
+    if let Some(limit) = flag("rplLimit") { quirks.rpl_limit = limit; }
+    if let Some(clear) = flag("resClear") { quirks.res_clear = clear; }
+
+    quirks
+}
+
+// Json is the subset of JSON used by roms/index.js
+enum Json {
+    Object(BTreeMap<String, Json>),
+    Array(Vec<Json>),
+    String(String),
+    Bool(bool),
+    Number(f64),
+}
+
+impl Json {
+    fn as_object(&self) -> &BTreeMap<String, Json> {
+        match self {
+            Json::Object(object) => object,
+            _ => panic!("expected object"),
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        match self {
+            Json::String(text) => text,
+            _ => panic!("expected string"),
+        }
+    }
+
+    fn as_bool(&self) -> bool {
+        match self {
+            Json::Bool(value) => *value,
+            Json::Number(value) => *value != 0.0,
+            _ => panic!("expected bool"),
+        }
+    }
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl JsonParser {
+    fn parse(&mut self) -> Json {
+        self.skip_space();
+
+        match self.next() {
+            '{' => {
+                let mut object = BTreeMap::new();
+
+                while self.peek_token() != '}' {
+                    self.skip_space();
+                    self.expect('"');
+                    let key = self.string();
+                    self.skip_space();
+                    self.expect(':');
+                    object.insert(key, self.parse());
+                    self.separator('}');
+                }
+
+                self.next();
+                Json::Object(object)
+            },
+            '[' => {
+                let mut array = Vec::new();
+
+                while self.peek_token() != ']' {
+                    array.push(self.parse());
+                    self.separator(']');
+                }
+
+                self.next();
+                Json::Array(array)
+            },
+            '"' => Json::String(self.string()),
+            't' => self.keyword("rue", Json::Bool(true)),
+            'f' => self.keyword("alse", Json::Bool(false)),
+            c if c == '-' || c.is_ascii_digit() => {
+                let start = self.pos - 1;
+                while self.pos < self.chars.len() && "0123456789.eE+-".contains(self.chars[self.pos]) {
+                    self.pos += 1;
+                }
+
+                let number: String = self.chars[start..self.pos].iter().collect();
+                Json::Number(number.parse().unwrap())
+            },
+            c => panic!("unexpected {:?} at {}", c, self.pos),
+        }
+    }
+
+    // string reads until closing quote, the opening quote is already consumed
+    fn string(&mut self) -> String {
+        let mut text = String::new();
+
+        loop {
+            match self.next() {
+                '"' => return text,
+                '\\' => match self.next() {
+                    'n' => text.push('\n'),
+                    't' => text.push('\t'),
+                    'u' => {
+                        let hex: String = (0..4).map(|_| self.next()).collect();
+                        text.push(char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap_or('?'));
+                    },
+                    c => text.push(c),
+                },
+                c => text.push(c),
+            }
+        }
+    }
+
+    fn keyword(&mut self, rest: &str, value: Json) -> Json {
+        for c in rest.chars() {
+            self.expect(c);
+        }
+
+        value
+    }
+
+    // separator consumes a comma before the next item, allowing a trailing comma before `end`
+    fn separator(&mut self, end: char) {
+        if self.peek_token() == ',' {
+            self.next();
+        } else {
+            assert_eq!(self.peek_token(), end, "expected {:?} at {}", end, self.pos);
+        }
+    }
+
+    fn peek_token(&mut self) -> char {
+        self.skip_space();
+        self.chars.get(self.pos).copied().expect("unexpected end of json")
+    }
+
+    fn next(&mut self) -> char {
+        let c = self.chars.get(self.pos).copied().expect("unexpected end of json");
+        self.pos += 1;
+        c
+    }
+
+    fn expect(&mut self, expected: char) {
+        let c = self.next();
+        assert_eq!(c, expected, "expected {:?} at {}", expected, self.pos);
+    }
+
+    fn skip_space(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+}
@@ -0,0 +1,156 @@
+/****
+ * Lockstep differential test of the basic block engine against the interpreter
+ *
+ * Both engines run each bundled rom side by side with the same quirks, seed & key input, and must give the same
+ * status, registers, memory & display after every frame, and the same whole state every STATE_INTERVAL frames.
+ * Roms also run at high speed, and with VIP timing in uneven host time steps, so blocks are cut by the frame
+ * budget at every position.
+ */
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use chip8_rs::{Machine, Quirks, Engine, TimingMode, ErrorPolicy, ExecutionStatus, NullLogger};
+use chip8_rs::assembler::assemble;
+
+const FRAMES : usize = 300;
+const STATE_INTERVAL : usize = 30;      // save_state is too slow to compare every frame
+const SEED : u32 = 0xC8;
+
+#[derive(Clone, Copy, Debug)]
+enum Speed {
+    Default,            // update() at default speed
+    Fast(u32),          // update() at instructions per second
+    VipAdvance,         // VIP timing, advance() by uneven steps
+}
+
+fn create_machine(rom: &[u8], quirks: Quirks, speed: Speed, engine: Engine) -> Machine {
+    let mut machine = Machine::new();
+    machine.set_quirks(quirks);
+    machine.set_error_policy(ErrorPolicy::Lenient);
+    machine.set_seed(SEED);
+    machine.set_logger(Box::new(NullLogger));
+    machine.set_engine(engine);
+
+    match speed {
+        Speed::Default => {},
+        Speed::Fast(ips) => machine.set_instructions_per_second(ips),
+        Speed::VipAdvance => machine.set_timing_mode(TimingMode::CosmacVip),
+    }
+
+    assert!(machine.load_rom(rom));
+    machine
+}
+
+// run_lockstep runs rom on both engines, returns the first frame they differ at
+fn run_lockstep(rom: &[u8], quirks: Quirks, speed: Speed) -> Result<(), String> {
+    let mut interpreter = create_machine(rom, quirks, speed, Engine::Interpreter);
+    let mut blocks = create_machine(rom, quirks, speed, Engine::BasicBlock);
+    let mut held = 0u16;
+
+    for frame in 0..FRAMES {
+        let pressed = common::scripted_keys(frame);
+        let mut statuses = Vec::new();
+
+        for machine in [&mut interpreter, &mut blocks].iter_mut() {
+            machine.set_keys(pressed, held & !pressed);
+
+            let status = match speed {
+                Speed::VipAdvance => {
+                    let steps = [4_001, 9_999, 2_667];
+                    steps.iter().map(|&micros| machine.advance(micros)).last().unwrap()
+                },
+                _ => machine.update(),
+            };
+
+            statuses.push(status);
+        }
+
+        held = pressed;
+
+        let stopped = matches!(statuses[0], ExecutionStatus::Halt | ExecutionStatus::RuntimeError);
+        let whole_state = stopped || frame % STATE_INTERVAL == STATE_INTERVAL - 1;
+
+        if statuses[0] != statuses[1] || !same_machine(&interpreter, &blocks, whole_state) {
+            return Err(format!("{:?}: differs at frame {}, status {:?}", speed, frame, statuses));
+        }
+
+        if stopped {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn same_machine(a: &Machine, b: &Machine, whole_state: bool) -> bool {
+    if whole_state {
+        return a.save_state() == b.save_state();
+    }
+
+    a.get_pc() == b.get_pc() && a.get_index() == b.get_index() && a.get_stack_depth() == b.get_stack_depth() &&
+        (0..16).all(|x| a.get_register(x) == b.get_register(x)) &&
+        a.memory() == b.memory() && a.display() == b.display()
+}
+
+#[test]
+fn test_engines_lockstep_roms() {
+    let entries = common::rom_index();
+    let mut failures = Vec::new();
+
+    for entry in entries.iter() {
+        let rom = common::read_rom(&entry.file);
+
+        for &speed in [Speed::Default, Speed::Fast(20_000), Speed::VipAdvance].iter() {
+            if let Err(error) = run_lockstep(&rom, entry.quirks, speed) {
+                failures.push(format!("{}: {}", entry.file, error));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "engines differ:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_engines_lockstep_self_modifying() {
+    // rewrites the increment in its own loop body every iteration, counting in V2
+    let rom = assemble("
+            LD V1, 1
+        loop:
+            LD I, patch
+            LD V0, 0x72
+            LD [I], V1
+        patch:
+            ADD V2, 1
+            ADD V1, 1
+            SNE V1, 0x10
+            LD V1, 0
+            JP loop
+    ").unwrap().rom;
+
+    for &speed in [Speed::Default, Speed::Fast(1_000), Speed::VipAdvance].iter() {
+        run_lockstep(&rom, Quirks::new(), speed).unwrap();
+    }
+
+    // host writes between frames, and state loads, are seen by both engines
+    let mut interpreter = create_machine(&rom, Quirks::new(), Speed::Default, Engine::Interpreter);
+    let mut blocks = create_machine(&rom, Quirks::new(), Speed::Default, Engine::BasicBlock);
+    let state = interpreter.save_state();
+
+    for frame in 0..60 {
+        for machine in [&mut interpreter, &mut blocks].iter_mut() {
+            if frame == 20 {
+                machine.memory_mut()[0x20B] = 5;
+            }
+
+            if frame == 40 {
+                machine.load_state(&state).unwrap();
+            }
+
+            machine.update();
+        }
+
+        assert!(same_machine(&interpreter, &blocks, true), "frame {}", frame);
+    }
+}
@@ -0,0 +1,121 @@
+/****
+ * Environment spec test: every spec of envs/ must parse and play its rom, rewarding points & ending episodes
+ * as described
+ */
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use chip8_rs::{Env, EnvSpec};
+
+const MAX_FRAMES : usize = 20_000;
+
+fn load_env(name: &str) -> Env {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("envs").join(format!("{}.spec", name));
+    let spec = EnvSpec::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+
+    assert_eq!(spec.name, name);
+
+    let rom = common::read_rom(&spec.rom);
+    Env::new(spec, &rom).unwrap()
+}
+
+// Episode sums up an episode played by `policy`, given the frame number
+#[derive(Debug, Default)]
+struct Episode {
+    frames: usize,
+    reward: f32,
+    rewarded: usize,    // steps with a positive reward
+    penalized: usize,   // steps with a negative reward
+    done: bool,
+}
+
+fn play(env: &mut Env, seed: u32, policy: impl Fn(usize) -> usize) -> Episode {
+    let mut episode = Episode::default();
+    env.reset(seed);
+
+    while !episode.done && episode.frames < MAX_FRAMES {
+        let (_, step) = env.step(policy(episode.frames));
+
+        episode.frames += 1;
+        episode.reward += step.reward;
+        episode.rewarded += (step.reward > 0.0) as usize;
+        episode.penalized += (step.reward < 0.0) as usize;
+        episode.done = step.done;
+    }
+
+    episode
+}
+
+#[test]
+fn test_env_specs() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("envs");
+    let mut names: Vec<String> = fs::read_dir(&root).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "spec"))
+        .map(|path| path.file_stem().unwrap().to_string_lossy().into_owned())
+        .collect();
+
+    names.sort();
+    assert_eq!(names, vec!["brix", "pong", "tetris"]);
+
+    for name in names.iter() {
+        let mut env = load_env(name);
+        let actions = env.action_count();
+
+        // the same seed & actions play the same episode
+        let moving = |frame: usize| (frame / 8) % actions;
+        let first = play(&mut env, 1, moving);
+        let second = play(&mut env, 1, moving);
+
+        assert_eq!((first.frames, first.reward), (second.frames, second.reward), "{}", name);
+    }
+}
+
+#[test]
+fn test_env_pong() {
+    // ends by frame count, the ball scores on both sides
+    let mut env = load_env("pong");
+    let episode = play(&mut env, 0, |frame| [0, 1, 0, 2][(frame / 30) % 4]);
+
+    assert!(episode.done);
+    assert_eq!(episode.frames, 18_000);
+    assert!(episode.rewarded > 0 && episode.penalized > 0, "{:?}", episode);
+
+    // each point is rewarded +1 for the agent and -1 for the other player, also past 10 points and when VE
+    // overflows
+    let points = env.machine().get_register(0xE) as usize;
+    assert!(episode.penalized >= 10, "{:?}", episode);
+    assert_eq!((10 * episode.rewarded + episode.penalized) % 0x100, points, "{:?}", episode);
+    assert_eq!(episode.reward, episode.rewarded as f32 - episode.penalized as f32);
+}
+
+#[test]
+fn test_env_brix() {
+    // idle paddle loses all lives, breaking a few bricks
+    let mut env = load_env("brix");
+    env.step(0);
+    assert_eq!(env.lives(), Some(5));
+
+    let episode = play(&mut env, 0, |_| 0);
+
+    assert!(episode.done && episode.frames < MAX_FRAMES, "{:?}", episode);
+    assert!(episode.reward > 0.0 && episode.penalized == 0, "{:?}", episode);
+    assert_eq!(episode.reward, env.score());
+    assert_eq!(env.lives(), Some(0));
+}
+
+#[test]
+fn test_env_tetris() {
+    // idle pieces stack up to the spawn row
+    let mut env = load_env("tetris");
+    let episode = play(&mut env, 0, |_| 0);
+
+    assert!(episode.done && episode.frames < MAX_FRAMES, "{:?}", episode);
+    assert_eq!(env.machine().get_pc(), 0x232);
+    assert_eq!(env.machine().get_register(1), 3);
+    assert_eq!(episode.reward, 0.0);
+}
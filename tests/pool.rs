@@ -0,0 +1,123 @@
+/****
+ * Machine pool stepping test: a pool of machines playing Pong must match machines run one by one, and step
+ * without allocating
+ *
+ * Allocations are counted per thread by a wrapper of the system allocator, so other tests don't interfere.
+ */
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use chip8_rs::{Machine, MachinePool, Quirks, RewardTerm};
+
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+const ROM_FILE : &str = "Pong [Paul Vervalin, 1990].ch8";
+const MACHINES : usize = 8;
+const FRAMES : usize = 300;
+const SEED : u32 = 0xC8;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|count| count.get())
+}
+
+// keys of machine i at frame: the scripted keys, shifted so machines play differently
+fn keys(i: usize, frame: usize) -> u16 {
+    common::scripted_keys(frame + i * 7)
+}
+
+#[test]
+fn test_pool_matches_machines() {
+    let rom = common::read_rom(ROM_FILE);
+    let mut pool = MachinePool::new(&rom, Quirks::new(), MACHINES, SEED).unwrap();
+
+    let mut machines: Vec<Machine> = (0..MACHINES).map(|i| {
+        let mut machine = Machine::new();
+        machine.set_seed(SEED + i as u32);
+        machine.load_rom(&rom);
+        machine
+    }).collect();
+
+    let mut held = [0u16; MACHINES];
+    let mut masks = [0u16; MACHINES];
+
+    for frame in 0..FRAMES {
+        for (i, machine) in machines.iter_mut().enumerate() {
+            masks[i] = keys(i, frame);
+            machine.set_keys(masks[i], held[i] & !masks[i]);
+            held[i] = masks[i];
+            machine.update();
+        }
+
+        pool.step(&masks);
+
+        for (i, machine) in machines.iter().enumerate() {
+            assert_eq!(pool.observation(i), machine.display(), "machine {} at frame {}", i, frame);
+            assert_eq!(pool.machine(i).memory(), machine.memory(), "machine {} at frame {}", i, frame);
+        }
+    }
+
+    // reset machine restarts from power on
+    pool.reset(3, SEED + 3);
+    let mut fresh = MachinePool::new(&rom, Quirks::new(), 1, SEED + 3).unwrap();
+
+    for frame in 0..60 {
+        let mut masks = [0u16; MACHINES];
+        masks[3] = keys(3, frame);
+
+        pool.step(&masks);
+        fresh.step(&masks[3..4]);
+
+        assert_eq!(pool.observation(3), fresh.observation(0), "frame {} after reset", frame);
+        assert_eq!(pool.machine(3).memory(), fresh.machine(0).memory(), "frame {} after reset", frame);
+    }
+}
+
+#[test]
+fn test_pool_step_allocation_free() {
+    let rom = common::read_rom(ROM_FILE);
+    let mut pool = MachinePool::new(&rom, Quirks::new(), MACHINES, SEED).unwrap();
+
+    // bcd score digits
+    pool.set_reward(&[RewardTerm { addr: 0x2F3, weight: 10.0 }, RewardTerm { addr: 0x2F4, weight: 1.0 }]);
+
+    let mut masks = [0u16; MACHINES];
+    let before = allocations();
+
+    for frame in 0..FRAMES {
+        for (i, mask) in masks.iter_mut().enumerate() {
+            *mask = keys(i, frame);
+        }
+
+        pool.step(&masks);
+    }
+
+    assert_eq!(allocations() - before, 0, "step allocated");
+    assert!(pool.halted().iter().all(|&halted| !halted));
+}
@@ -0,0 +1,102 @@
+/****
+ * Golden image regression test over the bundled roms
+ *
+ * Each rom in roms/index.js runs with its default quirks, a fixed seed and a scripted key input, then its
+ * display is compared with tests/golden/<rom name>.pbm (plain PBM, a pixel is set when any plane is set).
+ * Mismatching displays are written to the cargo target tmp dir for inspection.
+ *
+ * Regenerate golden images after an intended change with:
+ *
+ *   UPDATE_GOLDEN=1 cargo test --test roms
+ */
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use chip8_rs::{Machine, Quirks, ErrorPolicy, ExecutionStatus, NullLogger};
+
+const FRAMES : usize = 600;
+const SEED : u32 = 0xC8;
+
+
+#[test]
+fn test_rom_golden_images() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let golden_dir = root.join("tests/golden");
+    let actual_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+
+    let entries = common::rom_index();
+
+    let mut failures = Vec::new();
+
+    for entry in entries.iter() {
+        let rom = common::read_rom(&entry.file);
+        let image = run_rom(&rom, entry.quirks);
+
+        let name = Path::new(&entry.file).with_extension("pbm");
+        let golden_path = golden_dir.join(&name);
+
+        if update {
+            fs::create_dir_all(&golden_dir).unwrap();
+            fs::write(&golden_path, &image).unwrap();
+            continue;
+        }
+
+        match fs::read_to_string(&golden_path) {
+            Ok(golden) if golden == image => {},
+            Ok(_) => {
+                fs::create_dir_all(&actual_dir).unwrap();
+                fs::write(actual_dir.join(&name), &image).unwrap();
+                failures.push(format!("{}: display differs, actual image in {}", entry.file, actual_dir.display()));
+            },
+            Err(_) => failures.push(format!("{}: missing {}", entry.file, golden_path.display())),
+        }
+    }
+
+    assert!(failures.is_empty(), "{} of {} roms failed (UPDATE_GOLDEN=1 regenerates images):\n{}",
+        failures.len(), entries.len(), failures.join("\n"));
+}
+
+// run_rom runs rom like the web player does and returns its final display as PBM
+fn run_rom(rom: &[u8], quirks: Quirks) -> String {
+    let mut machine = Machine::new();
+    machine.set_quirks(quirks);
+    machine.set_error_policy(ErrorPolicy::Lenient);
+    machine.set_seed(SEED);
+    machine.set_logger(Box::new(NullLogger));
+    assert!(machine.load_rom(rom));
+
+    let mut held = 0u16;
+
+    for frame in 0..FRAMES {
+        let pressed = common::scripted_keys(frame);
+        machine.set_keys(pressed, held & !pressed);
+        held = pressed;
+
+        // the player stops on halt & error, running on would execute past the halting instruction
+        match machine.update() {
+            ExecutionStatus::Halt | ExecutionStatus::RuntimeError => break,
+            _ => {},
+        }
+    }
+
+    let width = machine.get_display_update().width;
+    to_pbm(machine.display(), width)
+}
+
+fn to_pbm(display: &[u8], width: usize) -> String {
+    let height = display.len() / width;
+    let mut image = format!("P1\n{} {}\n", width, height);
+
+    for row in display.chunks(width) {
+        image.extend(row.iter().map(|&pixel| if pixel != 0 { '1' } else { '0' }));
+        image.push('\n');
+    }
+
+    image
+}
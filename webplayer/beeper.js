@@ -3,6 +3,7 @@ export class Beeper {
     this.setConfig(frequency, wave, volume);
 
     this.playing = false;
+    this.pattern = null;
     this.oscillator = null;
     this.gainNode = null;
 
@@ -15,6 +16,28 @@ export class Beeper {
     this.volume = volume;
   }
 
+  // setPattern sets XO-CHIP 1-bit audio pattern (16 bytes) played at `rate` samples per second.
+  // null pattern goes back to the plain oscillator beep
+  setPattern(pattern, rate) {
+    if (!pattern) {
+      this.pattern = null;
+      return;
+    }
+
+    if (this.pattern && this.rate === rate && this.pattern.every((v, i) => v === pattern[i])) {
+      return;
+    }
+
+    this.pattern = Uint8Array.from(pattern);
+    this.rate = rate;
+
+    // restart to apply new pattern
+    if (this.playing) {
+      this.stop();
+      this.start();
+    }
+  }
+
   setPlaying(playing) {
     if (playing == this.playing) return;
 
@@ -52,11 +75,36 @@ export class Beeper {
   }
 
   createOscillator() {
+    if (this.pattern) {
+      this.oscillator = this.createPatternSource();
+      return;
+    }
+
     this.oscillator = this.audioContext.createOscillator();
     this.oscillator.type = this.wave;
     this.oscillator.frequency.value = this.frequency;
   }
 
+  createPatternSource() {
+    // resample 128 bits of pattern into audio context sample rate
+    const sampleRate = this.audioContext.sampleRate;
+    const length = Math.max(1, Math.round(128 * sampleRate / this.rate));
+    const buffer = this.audioContext.createBuffer(1, length, sampleRate);
+    const data = buffer.getChannelData(0);
+
+    for (let i = 0; i < length; i++) {
+      const bit = Math.floor(i * this.rate / sampleRate) % 128;
+      const on = (this.pattern[bit >> 3] >> (7 - (bit & 7))) & 1;
+      data[i] = on ? 1 : -1;
+    }
+
+    const source = this.audioContext.createBufferSource();
+    source.buffer = buffer;
+    source.loop = true;
+
+    return source;
+  }
+
   createGainNode() {
     this.gainNode = this.audioContext.createGain();
     this.gainNode.gain.value = this.volume;
@@ -4,6 +4,9 @@ const defaultColors = ["#000044", "#808088", "#FFFFCC"];
 const defaultOnColor = defaultColors[defaultColors.length - 1];
 const defaultOffColor = defaultColors[0];
 
+// XO-CHIP colors for pixels drawn only on plane 2, and on both planes
+const defaultPlaneColors = ["#FF6600", "#662200"];
+
 export class Display {
   constructor(canvas, onColor = defaultOnColor, offColor = defaultOffColor) {
     this.setColor(onColor, offColor);
@@ -24,58 +27,53 @@ export class Display {
 
   }
 
-  setColor(onColor, offColor) {
+  setColor(onColor, offColor, planeColors = defaultPlaneColors) {
     this.onColor = onColor;
     this.offColor = offColor;
+
+    // pixel value is a bitmask of drawn planes
+    this.palette = [offColor, onColor, planeColors[0], planeColors[1]];
   }
 
-  draw(pixelPtr, changedPtr, size) {
-    const pixels = new Uint8Array(memory.buffer, pixelPtr, size);
-    const changed = new Uint8Array(memory.buffer, changedPtr, size);
+  setResolution(cols, rows) {
+    if (cols === this.cols && rows === this.rows) return;
 
-    let i;
-    this.context.beginPath();
-
-    // draw on
-    this.context.fillStyle = this.onColor;
+    this.cols = cols;
+    this.rows = rows;
+    this.pixelSize = this.canvasWidth / cols;
 
-    i = 0;
-    for (let row = 0; row < this.rows; row++) {
-      for (let col = 0; col < this.cols; col++) {
-        let idx = i;
-        i++;
+    this.clearCanvas();
+  }
 
-        if (!changed[idx] || pixels[idx] !== 1) {
-          continue;
-        }
+  draw(pixelPtr, changedPtr, size, cols = 64, rows = 32) {
+    this.setResolution(cols, rows);
 
-        this.context.fillRect(
-          col * this.pixelSize,
-          row * this.pixelSize,
-          this.pixelSize,
-          this.pixelSize
-        );
-      }
-    }
+    const pixels = new Uint8Array(memory.buffer, pixelPtr, size);
+    const changed = new Uint8Array(memory.buffer, changedPtr, size);
 
-    // draw off
-    this.context.fillStyle = this.offColor;
-    i = 0;
-    for (let row = 0; row < this.rows; row++) {
-      for (let col = 0; col < this.cols; col++) {
-        let idx = i;
-        i++;
+    this.context.beginPath();
 
-        if (!changed[idx] || pixels[idx] !== 0) {
-          continue;
+    // draw each color in one pass
+    for (let color = 0; color < this.palette.length; color++) {
+      this.context.fillStyle = this.palette[color];
+
+      let i = 0;
+      for (let row = 0; row < this.rows; row++) {
+        for (let col = 0; col < this.cols; col++) {
+          let idx = i;
+          i++;
+
+          if (!changed[idx] || pixels[idx] !== color) {
+            continue;
+          }
+
+          this.context.fillRect(
+            col * this.pixelSize,
+            row * this.pixelSize,
+            this.pixelSize,
+            this.pixelSize
+          );
         }
-
-        this.context.fillRect(
-          col * this.pixelSize,
-          row * this.pixelSize,
-          this.pixelSize,
-          this.pixelSize
-        );
       }
     }
 
@@ -121,7 +119,8 @@ export class PhosphorDisplay {
     canvas.width = this.canvasWidth;
     canvas.height = this.canvasHeight;
 
-    this.physicalDisplay = new Uint8Array(this.rows * this.cols);
+    // allocated for the largest (SCHIP high resolution) display
+    this.physicalDisplay = new Uint8Array(128 * 64);
 
     this.setColor(colors, framePersistence);
   }
@@ -143,7 +142,19 @@ export class PhosphorDisplay {
     this.maxColorLevel = this.colorLevels.length - 1;
   }
 
-  draw(pixelPtr, changedPtr, size) {
+  setResolution(cols, rows) {
+    if (cols === this.cols && rows === this.rows) return;
+
+    this.cols = cols;
+    this.rows = rows;
+    this.pixelSize = this.canvasWidth / cols;
+
+    this.resetCanvas();
+  }
+
+  draw(pixelPtr, changedPtr, size, cols = 64, rows = 32) {
+    this.setResolution(cols, rows);
+
     let i;
     this.context.beginPath();
 
@@ -159,7 +170,7 @@ export class PhosphorDisplay {
         let idx = i;
         i++;
 
-        if (!changed[idx] || pixels[idx] !== 1) {
+        if (!changed[idx] || pixels[idx] === 0) {
           continue;
         }
 
@@ -180,7 +191,7 @@ export class PhosphorDisplay {
         let idx = i;
         i++;
 
-        if (pixels[idx] === 1) continue; // skip if on
+        if (pixels[idx] !== 0) continue; // skip if on
 
         const prevLevel = this.physicalDisplay[idx];
         if (prevLevel === 0) continue; // skip if fully off
@@ -1,18 +1,47 @@
-import { Machine, ExecutionStatus } from "../pkg";
+import { memory } from "wasm-pkg/chip8_rs_bg"
+import { Machine, ExecutionStatus, Quirks, Platform, LoadStore, BreakReason, ErrorPolicy, TimingMode } from "../pkg";
 import { ROMLoader } from "./rom_loader";
 import { Display, PhosphorDisplay } from "./display";
 import { Keypad } from "./keypad";
 import { Beeper } from "./beeper";
 
+const MAX_FRAME_GAP_MS = 100;
+
 export const GameState = Object.freeze({
   HALTED: 0,
   PLAYING: 1,
   PAUSED: 2
 });
 
+// createQuirks builds quirks profile from config: a platform preset (`platform` key with Platform name)
+// with individual overrides on top of it
+function createQuirks(config) {
+  const platform = Platform[config.platform];
+  const quirks = (platform !== undefined) ? Quirks.for_platform(platform) : Quirks.new();
+
+  if ("shift" in config) quirks.shift_vx = !!config.shift;
+  if ("loadStore" in config) {
+    quirks.loadstore = config.loadStore ? LoadStore.Unchanged : LoadStore.IncrementXPlusOne;
+  }
+  if ("wrapSprite" in config) quirks.wrap_sprite = !!config.wrapSprite;
+  if ("vfReset" in config) quirks.vf_reset = !!config.vfReset;
+  if ("jumpVx" in config) quirks.jump_vx = !!config.jumpVx;
+  if ("displayWait" in config) quirks.display_wait = !!config.displayWait;
+  if ("rplLimit" in config) quirks.rpl_limit = !!config.rplLimit;
+  if ("resClear" in config) quirks.res_clear = !!config.resClear;
+
+  return quirks;
+}
+
+// memory for rewind history, enough for a few minutes of most games
+const REWIND_BUDGET = 4 * 1024 * 1024;
+
 export class Game {
   constructor(canvas) {
     this.machine = Machine.new();
+    this.machine.set_rewind_budget(REWIND_BUDGET);
+    // keep roms relying on unsupported opcodes or bad accesses playable
+    this.machine.set_error_policy(ErrorPolicy.Lenient);
     this.loader = new ROMLoader(this.machine);
 
     this.normalDisplay = new Display(canvas);
@@ -28,8 +57,12 @@ export class Game {
     this.playing = false;
     this.needReload = true;
     this.animationId = null;
+    this.lastTime = null;
 
     this.stateListeners = [];
+    this.saveSlots = new Map();
+    this.rewinding = false;
+    this.replaying = false;
   }
 
   setConfig(config) {
@@ -52,23 +85,69 @@ export class Game {
       this.display.resetCanvas();
     }
 
+    if (config.memorySize) {
+      this.machine.set_memory_size(config.memorySize);
+      this.needReload = true;
+    }
+
     if (config.quirks) {
-      const quirks = config.quirks;
-      this.machine.set_quirks(!!quirks.shift, !!quirks.loadStore, !!quirks.wrapSprite);
+      this.machine.set_quirks(createQuirks(config.quirks));
+    }
+
+    // fixed seed makes random numbers reproducible, e.g. to replay a bug report
+    if (config.seed !== undefined) {
+      this.machine.set_seed(config.seed);
+    }
+
+    // `errorPolicy` is an ErrorPolicy name, e.g. "Strict" to stop on the first error while developing
+    if (config.errorPolicy in ErrorPolicy) {
+      this.machine.set_error_policy(ErrorPolicy[config.errorPolicy]);
+    }
+
+    // `ips` is cpu speed in instructions per second, timers run at 60 Hz whatever the display refresh rate
+    if (config.ips) {
+      this.machine.set_instructions_per_second(config.ips);
+    }
+
+    // `timing` is a TimingMode name, "CosmacVip" runs at original VIP speed with per-opcode cycle costs
+    if (config.timing in TimingMode) {
+      this.machine.set_timing_mode(TimingMode[config.timing]);
     }
   }
 
-  loop() {
+  loop(time) {
     this.animationId = null;
 
+    // run for the time elapsed since the last animation frame, capped so a background tab does not fast forward
+    const elapsed = this.lastTime === null ? 0 : Math.min(time - this.lastTime, MAX_FRAME_GAP_MS);
+    this.lastTime = time;
+
     // update keys
     let [pressed, released] = this.keypad.read_keys();
     this.machine.set_keys(pressed, released);
 
-    // run machine
-    const executionResult = this.machine.update();
+    // run machine, or step back one frame while rewinding
+    let executionResult = ExecutionStatus.OK;
+
+    if (this.rewinding) {
+      this.machine.rewind(1);
+    } else {
+      executionResult = this.machine.advance(Math.round(elapsed * 1000));
+    }
+
+    if (this.replaying && !this.machine.is_replaying()) {
+      this.replaying = false;
+      console.log(`movie replay ended, ${this.machine.replay_matches() ? "state matches" : "state MISMATCH"}`);
+    }
 
     // update sound
+    if (this.machine.has_audio_pattern()) {
+      const pattern = new Uint8Array(memory.buffer, this.machine.get_audio_pattern_ptr(), 16);
+      this.beeper.setPattern(pattern, this.machine.get_playback_rate());
+    } else {
+      this.beeper.setPattern(null);
+    }
+
     this.beeper.setPlaying(this.machine.is_beeping());
 
     // update display
@@ -78,22 +157,61 @@ export class Game {
       this.display.clearCanvas();
     }
 
-    this.display.draw(updates.display_ptr, updates.updated_ptr, updates.buffer_size);
+    this.display.draw(updates.display_ptr, updates.updated_ptr, updates.buffer_size, updates.width, updates.height);
 
     // request next frame
     if (executionResult === ExecutionStatus.OK) {
-      this.animationId = requestAnimationFrame(() => this.loop());
+      this.animationId = requestAnimationFrame((time) => this.loop(time));
+    } else if (executionResult === ExecutionStatus.Breakpoint) {
+      const reason = BreakReason[this.machine.get_break_reason()];
+      const addr = this.machine.get_break_addr().toString(16).toUpperCase();
+
+      console.log(`machine paused by ${reason} at 0x${addr}`);
+      this.pause();
     } else {
       if (executionResult === ExecutionStatus.Halt) {
         console.log("machine halted");
       } else if (executionResult === ExecutionStatus.RuntimeError) {
-        console.log("machine halted because of runtime error");
+        const error = this.machine.get_error();
+        console.log(`machine halted because of runtime error: ${error.message}`);
       }
 
       this.halt(false, true);
     }
   }
 
+  // setRewinding makes the running game go backward in time, one frame per animation frame
+  setRewinding(rewinding) {
+    this.rewinding = rewinding;
+  }
+
+  // saveState stores a snapshot of the running machine in a quick-save slot
+  saveState(slot = 0) {
+    if (this.halted) return false;
+
+    this.saveSlots.set(slot, this.machine.save_state());
+    return true;
+  }
+
+  // loadState restores a quick-save slot, returns false if slot is empty or the state is invalid
+  loadState(slot = 0) {
+    const state = this.saveSlots.get(slot);
+    if (!state || this.halted) return false;
+
+    try {
+      this.machine.load_state(state);
+    } catch (err) {
+      console.error(err);
+      return false;
+    }
+
+    const updates = this.machine.get_display_update();
+    this.display.clearCanvas();
+    this.display.draw(updates.display_ptr, updates.updated_ptr, updates.buffer_size, updates.width, updates.height);
+
+    return true;
+  }
+
   loadFile(file) {
     if (!file) {
       console.error("File is empty");
@@ -102,6 +220,7 @@ export class Game {
 
     return this.loader.loadFile(file)
       .then(() => {
+        this.saveSlots.clear();
         this.halt(true, false);
       })
       .catch((err) =>{
@@ -110,6 +229,7 @@ export class Game {
   }
 
   loadBuffer(buffer) {
+    this.saveSlots.clear();
     this.loader.loadBuffer(buffer);
     this.halt(true, false);
   }
@@ -129,16 +249,70 @@ export class Game {
 
       this.display.resetCanvas();
       this.machine.reset();
+    } else {
+      this.machine.resume();
     }
 
     this.playing = true;
     this.halted = false;
 
-    this.animationId = requestAnimationFrame(() => this.loop());
+    this.animationId = requestAnimationFrame((time) => this.loop(time));
+
+    this.updateListeners();
+  }
+
+  // step runs machine until the next instruction ("into"), past a subroutine call ("over")
+  // or out of current subroutine ("out"), then pauses again
+  step(kind = "into") {
+    if (this.playing || this.halted) return;
+
+    if (kind === "over") {
+      this.machine.step_over();
+    } else if (kind === "out") {
+      this.machine.step_out();
+    } else {
+      this.machine.step_into();
+    }
 
+    this.playing = true;
+    this.animationId = requestAnimationFrame((time) => this.loop(time));
     this.updateListeners();
   }
 
+  // startRecording restarts the game and records its input as a movie, with a random seed unless given
+  startRecording(seed = Math.floor(Math.random() * 0xFFFFFFFF)) {
+    this.halt(true, true);
+    this.start();
+    this.machine.start_recording(seed);
+  }
+
+  // stopRecording returns the recorded movie file as Uint8Array, undefined if not recording
+  stopRecording() {
+    return this.machine.stop_recording();
+  }
+
+  // replayMovie restarts the game and plays a movie, throws if it was recorded with another rom
+  replayMovie(movie) {
+    this.halt(true, true);
+    this.start();
+    this.machine.start_replay(movie);
+    this.replaying = true;
+  }
+
+  // setTracing records the last `capacity` executed instructions, or stops recording when 0
+  setTracing(capacity) {
+    if (capacity > 0) {
+      this.machine.enable_trace(capacity);
+    } else {
+      this.machine.disable_trace();
+    }
+  }
+
+  // exportTrace returns recorded instructions as text lines, or as JSON
+  exportTrace(json = false) {
+    return this.machine.export_trace(json);
+  }
+
   pause() {
     if (!this.playing) return;
     this.playing = false;
@@ -150,6 +324,8 @@ export class Game {
       this.animationId = null;
     }
 
+    this.lastTime = null;
+
     this.updateListeners();
   }
 
@@ -166,6 +342,8 @@ export class Game {
       this.animationId = null;
     }
 
+    this.lastTime = null;
+
     if (resetCanvas) {
       this.display.resetCanvas();
     }
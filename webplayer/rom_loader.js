@@ -3,12 +3,11 @@ import { memory } from "wasm-pkg/chip8_rs_bg"
 export class ROMLoader {
   constructor(machine) {
     this.buffer = null;
-    this.max_rom_size = machine.max_rom_size();
     this.machine = machine;
   }
 
   loadFile(file) {
-    if (file.size > this.max_rom_size) {
+    if (file.size > this.machine.max_rom_size()) {
       return Promise.reject("File too big");
     }
 
@@ -44,7 +43,8 @@ export class ROMLoader {
       return false;
     }
 
-    const rom = new Uint8Array(memory.buffer, this.machine.get_rom_ptr(), this.max_rom_size);
+    // memory size may change between loads (XO-CHIP), so always request a fresh pointer
+    const rom = new Uint8Array(memory.buffer, this.machine.get_rom_ptr(), this.machine.max_rom_size());
 
     for (let i = 0; i < this.buffer.length; i++) {
       rom[i] = this.buffer[i];